@@ -24,7 +24,7 @@ pub fn impl_codable(tt: TokenStream) -> TokenStream {
     let ErrorType { path, error } = error.unwrap();
 
     let mut init_ = Vec::<proc_macro2::TokenStream>::new();
-    let mut from_str = Vec::<proc_macro2::TokenStream>::new();
+    let mut from_str = Vec::<(usize, proc_macro2::TokenStream)>::new();
     let mut from_u8 = Vec::<proc_macro2::TokenStream>::new();
     let mut display = Vec::<proc_macro2::TokenStream>::new();
     let mut match_ = Vec::<proc_macro2::TokenStream>::new();
@@ -67,9 +67,12 @@ pub fn impl_codable(tt: TokenStream) -> TokenStream {
         all_op.push_str(mnumonic);
         all_op.push('_');
 
-        from_str.push(quote! {
-            x if x.starts_with(#mnumonic) => Ok(Self::#variant_name),
-        });
+        from_str.push((
+            mnumonic.len(),
+            quote! {
+                x if x.starts_with(#mnumonic) => Ok(Self::#variant_name),
+            },
+        ));
 
         from_u8.push(quote! {
             #number => Ok(Self::#variant_name),
@@ -91,7 +94,6 @@ pub fn impl_codable(tt: TokenStream) -> TokenStream {
             Self::#variant_name(i) => write!(f, "{}", i),
         });
 
-
         encode_.push(quote! {
             Self::#variant_name(i) => i.mask(),
         });
@@ -147,35 +149,78 @@ pub fn impl_codable(tt: TokenStream) -> TokenStream {
                             ));
                         }
 
-                        let mut index = false;
-                        let mut val = crate::types::l12::new_u(0)?;
+                        // `[rn, #off]` and `[rn, #off]!` add the offset before the access,
+                        // `[rn], #off` after it, the offset is encoded as its magnitude
+                        let mut index = true;
+                        let mut write_back = false;
+                        let mut offset = "#0";
 
                         let rd = parts[0];
-                        let mut rn = &parts[1][1..];
+                        let mut rn = parts[1].trim_start_matches('[');
 
                         if let Some(num) = parts.get(2) {
-                            if num.ends_with("]") {
-                                val = num[1..num.len() - 1].parse::<crate::types::l12>()?;
+                            if let Some(num) = num.strip_suffix("]!") {
+                                write_back = true;
+                                offset = num;
+                            } else if let Some(num) = num.strip_suffix(']') {
+                                offset = num;
                             } else {
-                                index = true;
-                                rn = &rn[..rn.len() - 1];
-                                val = num[1..].parse::<crate::types::l12>()?;
+                                index = false;
+                                write_back = true;
+                                rn = rn.trim_end_matches(']');
+                                offset = num;
                             }
                         } else {
-                            rn = &rn[..rn.len() - 1];
+                            rn = rn.trim_end_matches(']');
                         }
 
+                        let offset = offset.trim_start_matches('#');
+                        let (negative, offset) = match offset.strip_prefix('-') {
+                            Some(x) => (true, x),
+                            None => (false, offset),
+                        };
+                        let val = offset.parse::<crate::types::l12>()?;
+
                         let rd = rd.parse::<crate::processor::Register>()?;
                         let rn = rn.parse::<crate::processor::Register>()?;
 
                         let mut lsi = instruction.mk_instruction::<crate::processor::LSI>(instruction_parsed, rd, rn, val)?;
 
                         lsi.index = index;
+                        lsi.negative = negative && val.value != 0;
+                        lsi.write_back = write_back;
 
                         Ok(Self::#variant_name(lsi))
                     },       
                 })
             }
+            "WDI" => {
+                decode_.push(quote! {
+                    Op::#variant_name => Ok(#name::#variant_name(crate::processor::WDI::try_from(value)?)),
+                });
+                parse_.push(quote! {
+                    Op::#variant_name => {
+                        if parts.len() < 2 {
+                            return Err(crate::error::EsiuxErrorKind::NotEnoughParts(
+                                Box::new(instruction_parsed),
+                                2,
+                            ));
+                        }
+
+                        let rd = parts[0].parse::<crate::processor::Register>()?;
+                        let imm = parts[1][1..].parse::<crate::types::l16>()?;
+
+                        let wdi = instruction.mk_instruction::<crate::processor::WDI>(
+                            instruction_parsed,
+                            rd,
+                            crate::processor::Register::R0,
+                            imm,
+                        )?;
+
+                        Ok(Self::#variant_name(wdi))
+                    },
+                })
+            }
             "BRI" => {
                 decode_.push(quote! {
                     Op::#variant_name => Ok(#name::#variant_name(crate::processor::BRI::try_from(value)?)),
@@ -189,7 +234,16 @@ pub fn impl_codable(tt: TokenStream) -> TokenStream {
                             ));
                         }
 
-                        let offset = parts[0][1..].parse::<crate::types::l20>()?;
+                        // `br` and `blr` keep their register in the low bits of the offset
+                        let offset = match (parts[0].strip_prefix('#'), Op::#variant_name.is_indirect()) {
+                            (Some(offset), false) => offset.parse::<crate::types::l20>()?,
+                            (None, true) => crate::types::l20 {
+                                value: parts[0].parse::<crate::processor::Register>()? as u32,
+                            },
+                            _ => return Err(crate::error::EsiuxErrorKind::FromStr(Box::new(format!(
+                                "invalid branch operand: {}", parts[0]
+                            )))),
+                        };
 
                         let bri = instruction.mk_instruction::<crate::processor::BRI>(
                             instruction_parsed,
@@ -225,6 +279,10 @@ pub fn impl_codable(tt: TokenStream) -> TokenStream {
         };
     }
 
+    // `movw` has to be tried before `mov`, the longest mnemonic that fits wins
+    from_str.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+    let from_str = from_str.into_iter().map(|(_, x)| x);
+
     quote! {
         use #path;
         use crate::parser::ParserImpl;
@@ -258,9 +316,12 @@ pub fn impl_codable(tt: TokenStream) -> TokenStream {
             fn try_from(value: u32) -> Result<Self, Self::Error> {
                 let ins = ((value >> 4) & 0b111) as u8;
                 let ins = match ins {
-                    0x1 | 0x5 | 0x7 => ((value >> 8) & 0xf) as u8 | ins << 4,
-                    0x3 => ((value >> 11) & 0b1) as u8 | ins << 4,
-                    _ => panic!("This shouldnt happen: instruction_val: {ins} - {ins:08b}"),
+                    0x1 | 0x2 | 0x5 | 0x7 => ((value >> 8) & 0xf) as u8 | ins << 4,
+                    // data processing with the flags set
+                    0x4 => ((value >> 8) & 0xf) as u8 | 0x1 << 4,
+                    // bit 11 and bit 7 are the low bits of a load or store
+                    0x3 | 0x6 => ((value >> 11) & 0b1 | (value >> 6) & 0b10) as u8 | ins << 4,
+                    _ => return Err(Self::Error::Decode(value)),
                 };
                 let ins = Op::try_from(ins)?;
 
@@ -111,13 +111,16 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    pub fn advance_untill(&mut self, word: &str) {
-        for cur in word.chars() {
-            while self.peek() != Some(cur) {
-                self.advance();
+    /// advances past the next occurence of word
+    /// returns false if the end of input is reached first
+    pub fn advance_untill(&mut self, word: &str) -> bool {
+        while !self.is_eof() {
+            if self.match_str(word) {
+                return true;
             }
+            self.advance();
         }
-        self.advance();
+        false
     }
 
     pub fn is_eof(&self) -> bool {
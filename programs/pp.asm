@@ -8,7 +8,7 @@
  Sub  \reg2, \reg1, \reg1
 .endm
 
-; .section text
+.section .text
 .global _start
 
 _start:
@@ -20,5 +20,5 @@ done:
 	svc  #0xf0
 
 
-; .data
-; duck: .word 4
+.data
+duck: .word 4
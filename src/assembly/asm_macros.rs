@@ -1,5 +1,13 @@
-use super::{PreProcessor, Statements, Symbol};
-use crate::{error::EsiuxErrorKind, Res};
+use std::{borrow::Cow, fs};
+
+use super::{constant, eval, PreProcessor, Statements, Symbol, Token, DATA_DIRECTIVES};
+use crate::{error::EsiuxErrorKind, format::Section, Res};
+
+/// directives whose body is repeated, closed by `.endr`
+pub const REPEATS: [&str; 3] = ["rept", "irp", "irpc"];
+
+/// most times a `.rept` body can be repeated
+pub const REPEAT_MAX: i64 = 0xffff;
 
 #[derive(Debug, Clone)]
 pub enum Macros<'a> {
@@ -7,51 +15,216 @@ pub enum Macros<'a> {
     Substitution(SubMacro<'a>),
 }
 
+/// # MacroParam
+///
+/// * a parameter of a substitution macro as written in the definition, `\reg`
+/// * `\reg=r1` gives it a default, `\rest:vararg` takes every value left over
+///
+#[derive(Debug, Clone)]
+pub struct MacroParam<'a> {
+    pub name: Cow<'a, str>,
+    pub default: Option<Symbol<'a>>,
+    pub vararg: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct SubMacro<'a> {
-    pub input: Vec<Symbol<'a>>,
+    pub params: Vec<MacroParam<'a>>,
     pub body: Vec<Statements<'a>>,
     pub offset: u32,
 }
 
+impl<'a> SubMacro<'a> {
+    /// number of arguments a call has to pass, for errors
+    fn arity(&self) -> String {
+        let required = self
+            .params
+            .iter()
+            .filter(|x| x.default.is_none() && !x.vararg)
+            .count();
+        match self.params.iter().any(|x| x.vararg) {
+            true => format!("at least {required}"),
+            false if required == self.params.len() => required.to_string(),
+            false => format!("{required} to {}", self.params.len()),
+        }
+    }
+
+    /// # bind
+    ///
+    /// * pairs every parameter with the values a call passes to it
+    /// * `name=value` passes a value by name, the others fill the remaining parameters in order
+    /// * parameters without a value take their default, a vararg tail can stay empty
+    ///
+    pub fn bind(
+        &self,
+        name: &Symbol<'a>,
+        values: Vec<Symbol<'a>>,
+    ) -> Res<Vec<(Cow<'a, str>, Vec<Symbol<'a>>)>> {
+        let mut bound: Vec<Option<Vec<Symbol<'a>>>> = vec![None; self.params.len()];
+        let found = values
+            .iter()
+            .filter(|x| !matches!(x, Symbol::Input(_)))
+            .count();
+        let arity = || EsiuxErrorKind::MacroArity(name.lexeme().to_string(), self.arity(), found);
+        let mut next = 0;
+        let mut values = values.into_iter();
+
+        while let Some(value) = values.next() {
+            if let Symbol::Input(param) = &value {
+                let key = param.lexeme.trim_start_matches('\\');
+                let index = self
+                    .params
+                    .iter()
+                    .position(|x| x.name.trim_start_matches('\\') == key)
+                    .ok_or_else(|| {
+                        EsiuxErrorKind::UnknownMacroParam(
+                            name.lexeme().to_string(),
+                            key.to_string(),
+                        )
+                        .at(param.span())
+                    })?;
+                bound[index] = values.next().map(|x| vec![x]);
+                continue;
+            }
+
+            // parameters passed by name are skipped
+            while bound.get(next).is_some_and(|x| x.is_some()) && !self.params[next].vararg {
+                next += 1;
+            }
+            match self.params.get(next) {
+                Some(param) if param.vararg => bound[next].get_or_insert_with(Vec::new).push(value),
+                Some(_) => {
+                    bound[next] = Some(vec![value]);
+                    next += 1;
+                }
+                None => return Err(arity()),
+            }
+        }
+
+        self.params
+            .iter()
+            .zip(bound)
+            .map(|(param, values)| {
+                let values = match (values, &param.default) {
+                    (Some(values), _) => values,
+                    (None, Some(default)) => vec![default.clone()],
+                    (None, None) if param.vararg => Vec::new(),
+                    (None, None) => return Err(arity()),
+                };
+                Ok((param.name.clone(), values))
+            })
+            .collect()
+    }
+}
+
 pub type Function<'a> =
     fn(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>>;
 
 impl<'a> PreProcessor<'a> {
     pub fn standard_directives(&mut self) {
         self.define_std("global", global);
+        self.define_std("extern", external);
+        self.define_std("entry", entry);
         self.define_std("macro", amacro);
-        self.define_std("section", section);
+        for name in ["section", "text", "data", "rodata", "bss"] {
+            self.define_std(name, section);
+        }
+        self.define_std("include", include);
+        self.define_std("incbin", incbin);
+        self.define_std("equ", equ);
+        self.define_std("set", equ);
+        self.define_std("ltorg", ltorg);
+        for name in DATA_DIRECTIVES {
+            self.define_std(name, data);
+        }
+        for name in REPEATS {
+            self.define_std(name, repeat);
+        }
     }
 }
 
+/// the symbols a `.global` or `.extern` names, at least one
+fn symbol_list<'a>(input: &Statements<'a>) -> Res<Vec<Token<'a>>> {
+    let Statements::Directive { name, params, .. } = input else {
+        return Err(EsiuxErrorKind::NotAnInstruction(Box::new(
+            input.to_string(),
+        )));
+    };
+    if params.is_empty() {
+        return Err(EsiuxErrorKind::ExpectedLabel(format!(".{}", name.lexeme())).at(name.span()));
+    }
+
+    params
+        .iter()
+        .map(|param| match param {
+            Symbol::Label(x) | Symbol::Ident(x) => Ok(x.clone()),
+            x => Err(EsiuxErrorKind::ExpectedLabel(x.lexeme().to_string()).at(x.span())),
+        })
+        .collect()
+}
+
+/// # global
+///
+/// * `.global a, b` exports a and b from the object
+/// * the entry point of an executable is `_start` if it is exported, or the only exported
+///   symbol, see entry
+///
 pub fn global<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
-    let mut st = Vec::new();
-    // println!("{input:#?}");
+    let symbols = symbol_list(&input)?;
+    pp.globals.extend(symbols);
+    Ok(vec![input])
+}
 
-    let (param, name) = if let Statements::Directive { name, params, .. } = input.clone() {
-        assert!(name.lexeme().as_ref() == "global", "assertion failed");
-        (params, name)
-    } else {
-        todo!()
+/// # entry
+///
+/// * `.entry main` starts the executable at main instead of `_start`
+/// * an object has no entry point, the linker takes one with `-e`
+///
+pub fn entry<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let Statements::Directive { name, .. } = &input else {
+        return Err(EsiuxErrorKind::NotADirective(Box::new(input.to_string())));
     };
+    let mut symbols = symbol_list(&input)?;
+    if symbols.len() != 1 {
+        let found = format!("{} symbols", symbols.len());
+        return Err(EsiuxErrorKind::Expected(Box::new("one symbol"), found).at(name.span()));
+    }
+    pp.start = symbols.pop();
+    Ok(vec![input])
+}
 
-    let entry = if let Some(Symbol::Label(x)) = param.first() {
-        Ok(x)
-    } else {
-        Err(EsiuxErrorKind::ExpectedLabel(
-            ".section".to_string(),
-            name.line(),
-        ))
-    }?
-    .clone();
-
-    pp.entry = Some(entry);
+/// # external
+///
+/// * `.extern a, b` declares symbols another object defines
+/// * they can only be referenced when assembling an object
+///
+pub fn external<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let symbols = symbol_list(&input)?;
+    pp.externs.extend(symbols);
+    Ok(vec![input])
+}
 
-    st.push(input);
-    Ok(st)
+/// the default of a macro parameter, an operand of the same kind the scanner would make
+fn default_value<'a>(param: &Symbol<'a>, text: &str) -> Symbol<'a> {
+    let mut token = match param {
+        Symbol::Param(token) => token.clone(),
+        x => return x.clone(),
+    };
+    token.lexeme = Cow::Owned(text.to_string());
+    match text.chars().next() {
+        Some('r') => Symbol::Register(token),
+        Some('#') => Symbol::Literal(token),
+        Some('_') => Symbol::Label(token),
+        Some('"') => Symbol::Str(token),
+        _ => Symbol::Ident(token),
+    }
 }
 
+/// # amacro
+///
+/// * `.macro NAME \a \b=default \rest:vararg` defines a substitution macro
+/// * the scanner keeps a default or `:vararg` in the parameter token, they are split off here
+///
 pub fn amacro<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
     let mut st = Vec::new();
 
@@ -70,14 +243,48 @@ pub fn amacro<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<S
     };
 
     let name = params.first();
-    let params = params[1..].to_vec();
-
-    let sub = SubMacro {
-        input: params,
+    let mut sub = SubMacro {
+        params: Vec::new(),
         body,
         offset: pc,
     };
 
+    for sym in params.iter().skip(1) {
+        if let Some(vararg) = sub.params.last().filter(|x| x.vararg) {
+            return Err(EsiuxErrorKind::Expected(
+                Box::new(format!("no parameter after {}:vararg", vararg.name)),
+                sym.lexeme().to_string(),
+            )
+            .at(sym.span()));
+        }
+        let lexeme = sym.lexeme();
+        let param = if let Some((name, default)) = lexeme.split_once('=') {
+            MacroParam {
+                name: Cow::Owned(name.to_string()),
+                default: Some(default_value(sym, default)),
+                vararg: false,
+            }
+        } else if let Some((name, kind)) = lexeme.split_once(':') {
+            if kind != "vararg" {
+                return Err(
+                    EsiuxErrorKind::Expected(Box::new("vararg"), kind.to_string()).at(sym.span()),
+                );
+            }
+            MacroParam {
+                name: Cow::Owned(name.to_string()),
+                default: None,
+                vararg: true,
+            }
+        } else {
+            MacroParam {
+                name: lexeme,
+                default: None,
+                vararg: false,
+            }
+        };
+        sub.params.push(param);
+    }
+
     pp.define_submacro(name.unwrap().lexeme(), sub);
 
     st.push(input);
@@ -85,32 +292,429 @@ pub fn amacro<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<S
     Ok(st)
 }
 
-pub fn section<'a>(_pp: &mut PreProcessor<'a>, _input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
-    // if input.is_empty() {
-    //     return Err(EsiuxErrorKind::Format(Box::new(
-    //         "format: .section <name>".to_string(),
-    //     )));
-    // }
-
-    // let Token { lexeme, .. } = if let Some(Symbol::Ident(label)) = input.first() {
-    //     label
-    // } else {
-    //     return Err(EsiuxErrorKind::DirectiveResolve(
-    //         format!(
-    //             "Failed to resolve .section: Expected an ident got {:?}",
-    //             input[0]
-    //         ),
-    //         input[0].line(),
-    //     ));
-    // };
-
-    // pp.section = lexeme.parse::<Section>()?;
-
-    // let resolved = vec![Symbol::Directive(
-    //     Token::from("section"),
-    //     vec![input[0].clone()],
-    // )];
-
-    // Ok(resolved)
-    todo!()
+/// # repeat
+///
+/// * `.rept N` assembles its body N times, N is a constant expression
+/// * `.irp \sym, a, b` once for every value, `.irpc \sym, "ab"` once for every character
+/// * `\sym` in the body is replaced with the value of the iteration
+///
+pub fn repeat<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let Statements::Directive {
+        name, params, body, ..
+    } = input
+    else {
+        return Err(EsiuxErrorKind::NotADirective(Box::new(input.to_string())));
+    };
+
+    let iterations = match name.lexeme().as_ref() {
+        "rept" => {
+            let count = constant(params.first(), &name)?;
+            if !(0..=REPEAT_MAX).contains(&count) {
+                return Err(EsiuxErrorKind::RepeatCount(count, REPEAT_MAX).at(params[0].span()));
+            }
+            vec![Vec::new(); count as usize]
+        }
+        directive => {
+            let sym = params.first().ok_or_else(|| {
+                EsiuxErrorKind::Expected(Box::new("a parameter name"), "end of line".to_string())
+                    .at(name.span())
+            })?;
+            let key: Cow<'a, str> =
+                Cow::Owned(format!("\\{}", sym.lexeme().trim_start_matches('\\')));
+            let values = match directive {
+                "irp" => params[1..].to_vec(),
+                _ => params[1..].iter().flat_map(characters).collect(),
+            };
+            values
+                .into_iter()
+                .map(|x| vec![(key.clone(), vec![x])])
+                .collect()
+        }
+    };
+
+    let mut st = Vec::new();
+    for args in iterations {
+        st.extend(pp.conditional_scope(|pp| {
+            body.iter()
+                .flat_map(|stmt| pp.statement(stmt.resolve(&args)))
+                .collect::<Vec<_>>()
+        }));
+    }
+
+    Ok(vec![Statements::Expansion {
+        name,
+        values: params,
+        body: st,
+    }])
+}
+
+/// every character of an `.irpc` argument, quotes removed
+fn characters<'a>(sym: &Symbol<'a>) -> Vec<Symbol<'a>> {
+    let mut base = sym.clone();
+    let Some(token) = base.token_mut() else {
+        return Vec::new();
+    };
+    sym.lexeme()
+        .trim_matches('"')
+        .chars()
+        .map(|c| {
+            let mut token = token.clone();
+            token.lexeme = Cow::Owned(c.to_string());
+            Symbol::Ident(token)
+        })
+        .collect()
+}
+
+/// # section
+///
+/// * `.section <name>` or one of the `.text`, `.data`, `.rodata`, `.bss` shorthands
+/// * every form comes out as `.section <name>` so later passes only look for one
+///
+pub fn section<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let Statements::Directive {
+        mut name, params, ..
+    } = input
+    else {
+        return Err(EsiuxErrorKind::NotADirective(Box::new(input.to_string())));
+    };
+
+    let target = match (name.lexeme().as_ref(), params.first()) {
+        ("section", Some(param)) => param
+            .lexeme()
+            .parse::<Section>()
+            .map_err(|e| e.at(param.span()))?,
+        ("section", None) => {
+            return Err(EsiuxErrorKind::Expected(
+                Box::new("a section name"),
+                "end of line".to_string(),
+            ))
+        }
+        (x, _) => x.parse::<Section>()?,
+    };
+
+    pp.switch_section(target);
+    if let Some(token) = name.token_mut() {
+        token.lexeme = Cow::Borrowed("section");
+    }
+
+    Ok(vec![Statements::Directive {
+        name,
+        params: vec![Symbol::Ident(Token::from(target.to_string()))],
+        body: Vec::new(),
+        marker: None,
+        pc: 0,
+    }])
+}
+
+/// # data
+///
+/// * `.word`, `.half`, `.byte`, `.ascii`, `.asciz`, `.space` and `.align`
+/// * only sizes the statement and advances the location counter, bytes are emitted
+///   once every label is known
+///
+pub fn data<'a>(pp: &mut PreProcessor<'a>, mut input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let size = input.data_size(pp.pc)?;
+    input.set_pc(pp.pc);
+    pp.pc += size;
+
+    Ok(vec![input])
+}
+
+/// `.ltorg` places the literal pool of the current section here
+pub fn ltorg<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let Statements::Directive { name, .. } = &input else {
+        return Err(EsiuxErrorKind::NotADirective(Box::new(input.to_string())));
+    };
+    Ok(pp.ltorg(Some(name)))
+}
+
+/// # equ
+///
+/// * `.equ NAME, value` and `.set NAME, value` define a constant
+/// * constants used in the value are substituted where it is defined
+/// * values that refer to labels are kept as an expression and evaluated where they are used
+///
+pub fn equ<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let Statements::Directive { name, params, .. } = &input else {
+        return Err(EsiuxErrorKind::NotADirective(Box::new(input.to_string())));
+    };
+
+    let (constant, value) = match params.as_slice() {
+        [constant, value] => (constant, value),
+        _ => {
+            return Err(EsiuxErrorKind::Expected(
+                Box::new(format!(".{name} NAME, value")),
+                input.to_string().trim().to_string(),
+            ))
+        }
+    };
+
+    let lexeme = constant.lexeme();
+    if !lexeme.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        || !lexeme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(
+            EsiuxErrorKind::Expected(Box::new("a name"), lexeme.to_string()).at(constant.span()),
+        );
+    }
+
+    let value = match eval(&value.lexeme(), |_| None) {
+        Ok(value) => value.to_string(),
+        Err(EsiuxErrorKind::UndefinedSymbol(_)) => value.lexeme().to_string(),
+        Err(e) => return Err(e.at(value.span())),
+    };
+    pp.define_variable(Cow::Owned(lexeme.to_string()), Cow::Owned(value));
+
+    Ok(vec![input])
+}
+
+/// file name argument of `.include` and `.incbin`
+fn file_name<'a, 'b>(name: &Symbol<'a>, params: &'b [Symbol<'a>]) -> Res<(&'b Symbol<'a>, String)> {
+    let param = params.first().ok_or_else(|| {
+        EsiuxErrorKind::Expected(Box::new("a file name"), "end of line".to_string()).at(name.span())
+    })?;
+    Ok((param, param.lexeme().trim_matches('"').to_string()))
+}
+
+/// # include
+///
+/// * `.include "file"` processes file in place of the directive
+/// * the file is looked up next to the including file, then in the -I directories
+/// * comes out as an expansion of the directive holding the statements of file
+///
+pub fn include<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let Statements::Directive { name, params, .. } = &input else {
+        return Err(EsiuxErrorKind::NotADirective(Box::new(input.to_string())));
+    };
+
+    let (param, path) = file_name(name, params)?;
+    let file = *pp
+        .sources
+        .includes
+        .get(&(name.span().file, path.clone()))
+        .ok_or_else(|| EsiuxErrorKind::FileNotFound(path).at(param.span()))?;
+
+    if let Some(pos) = pp.stack.iter().position(|x| *x == file) {
+        let chain = pp.stack[pos..]
+            .iter()
+            .chain([&file])
+            .filter_map(|x| pp.sources.get(*x))
+            .map(|x| x.path.as_str())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        return Err(EsiuxErrorKind::IncludeCycle(chain).at(param.span()));
+    }
+
+    let body = pp.process(file);
+    Ok(vec![Statements::Expansion {
+        name: name.clone(),
+        values: params.clone(),
+        body,
+    }])
+}
+
+/// # incbin
+///
+/// * `.incbin "file", offset, len` places the raw bytes of file into the current section
+/// * offset defaults to 0 and len to the rest of the file
+/// * comes out as a `.byte` directive holding the bytes
+///
+pub fn incbin<'a>(pp: &mut PreProcessor<'a>, input: Statements<'a>) -> Res<Vec<Statements<'a>>> {
+    let Statements::Directive {
+        mut name, params, ..
+    } = input
+    else {
+        return Err(EsiuxErrorKind::NotADirective(Box::new(input.to_string())));
+    };
+
+    let (param, path) = file_name(&name, &params)?;
+    let bytes = pp
+        .sources
+        .find(name.span().file, &path)
+        .ok_or_else(|| EsiuxErrorKind::FileNotFound(path.clone()))
+        .and_then(|x| Ok(fs::read(x)?))
+        .map_err(|e| e.at(param.span()))?;
+
+    let value = |i: usize, default: i64| match params.get(i) {
+        Some(x) => eval(&x.lexeme(), |_| None).map_err(|e| e.at(x.span())),
+        None => Ok(default),
+    };
+    let offset = value(1, 0)?;
+    let len = value(2, bytes.len() as i64 - offset)?;
+    let (start, end) = usize::try_from(offset)
+        .ok()
+        .zip(usize::try_from(offset + len).ok())
+        .filter(|(start, end)| start <= end && *end <= bytes.len())
+        .ok_or_else(|| {
+            EsiuxErrorKind::IncbinRange(offset, offset + len, path, bytes.len()).at(name.span())
+        })?;
+
+    if start == end {
+        return Ok(Vec::new());
+    }
+    if let Some(token) = name.token_mut() {
+        token.lexeme = Cow::Borrowed("byte");
+    }
+
+    let stmt = Statements::Directive {
+        name,
+        params: bytes[start..end]
+            .iter()
+            .map(|x| Symbol::Ident(Token::from(x.to_string())))
+            .collect(),
+        body: Vec::new(),
+        marker: None,
+        pc: 0,
+    };
+    data(pp, stmt)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembly::{Assembler, PreProcessor, SourceMap, Statements},
+        error::EsiuxErrorKind,
+    };
+
+    use super::{equ, incbin, include, repeat, section};
+
+    const MACROS: &str = ".macro PUSH \\reg, \\n=#1\n sub \\reg, \\reg, \\n\n.endm\n.macro SUM \\dst, \\first, \\rest:vararg\n mov \\dst, \\first\n .word \\rest\n.endm\n.macro LOOP \\reg\n_loop\\@:\n PUSH \\reg\n b _loop\\@\n.endm\n";
+
+    fn expand(calls: &str) -> Result<Vec<String>, Vec<String>> {
+        let mut asm = Assembler {
+            source: format!("{MACROS}{calls}"),
+            ..Default::default()
+        };
+        match asm.preprocess() {
+            Ok(out) => Ok(out
+                .split(".endm")
+                .last()
+                .unwrap_or_default()
+                .lines()
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect()),
+            Err(_) => Err(asm.diagnostics.iter().map(|x| x.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn macro_one() {
+        let out = expand("PUSH r1\nPUSH r2, #4\nPUSH n=#8, reg=r3\n").unwrap();
+
+        assert_eq!(
+            out,
+            vec![
+                "sub    r1, r1, #1",
+                "sub    r2, r2, #4",
+                "sub    r3, r3, #8"
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_two() {
+        let out = expand("SUM r4, r5, 1, 2, 3\nSUM first=r6, dst=r4, 5\n").unwrap();
+
+        assert_eq!(out[0], "mov    r4, r5");
+        assert_eq!(out[1], ".word    1, 2, 3");
+        assert_eq!(out[2], "mov    r4, r6");
+        assert_eq!(out[3], ".word    5");
+    }
+
+    #[test]
+    fn macro_three() {
+        let out = expand("LOOP r6\nLOOP r7\n").unwrap();
+
+        assert_eq!(out[0], "_loop0:");
+        assert_eq!(out[1], "sub    r6, r6, #1");
+        assert!(out[2].ends_with(" _loop0"));
+        assert_eq!(out[3], "_loop2:");
+        assert!(out[5].ends_with(" _loop2"));
+    }
+
+    #[test]
+    fn macro_four() {
+        let errors = expand(
+            "PUSH\nPUSH r1, #2, #3\nPUSH r1, cnt=#3\n.macro REC \\x\n REC \\x\n.endm\nREC r1\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Macro PUSH expects 1 to 2 arguments, found 0 @ 13:1",
+                "Macro PUSH expects 1 to 2 arguments, found 3 @ 14:1",
+                "Macro PUSH has no parameter named cnt @ 15:10",
+                "Macro REC is nested more than 64 levels deep, does it call itself? @ 17:2",
+            ]
+        );
+    }
+
+    #[test]
+    fn rept_one() {
+        let out = expand(".equ N, 3\n.rept N - 1\nadd r2, r2, #1\n.endr\n.irp reg, r3, r4\nmov \\reg, #0\n.endr\n.rept 2\n.irpc c, \"12\"\n.byte \\c\n.endr\n.endr\n")
+            .unwrap();
+
+        assert_eq!(
+            out,
+            vec![
+                ".equ    N, 3",
+                "add    r2, r2, #1",
+                "add    r2, r2, #1",
+                "mov    r3, #0",
+                "mov    r4, #0",
+                ".byte    1",
+                ".byte    2",
+                ".byte    1",
+                ".byte    2",
+            ]
+        );
+    }
+
+    #[test]
+    fn rept_two() {
+        let out = expand(".macro TABLE \\n\n.rept \\n\nPUSH r1, #\\n\n.endr\n.endm\nTABLE 2\n.rept 0\nmov r1, #1\n.endr\n")
+            .unwrap();
+        assert_eq!(out, vec!["sub    r1, r1, #2", "sub    r1, r1, #2"]);
+
+        let errors = expand(".rept -1\n.endr\n.rept 2\nmov r1, #1\n").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "Repeat count -1 is out of range, expected 0..=65535 @ 13:7",
+                "Unterminated .rept block, missing .endr @ 15:2",
+            ]
+        );
+    }
+
+    #[test]
+    fn directive_one() {
+        let sources = SourceMap::default();
+        let mut pp = PreProcessor::new(&sources);
+
+        // a directive function called with another statement reports it instead of panicking
+        assert!(matches!(
+            section(&mut pp, Statements::Eof),
+            Err(EsiuxErrorKind::NotADirective(_))
+        ));
+        assert!(matches!(
+            equ(&mut pp, Statements::Eof),
+            Err(EsiuxErrorKind::NotADirective(_))
+        ));
+        assert!(matches!(
+            include(&mut pp, Statements::Eof),
+            Err(EsiuxErrorKind::NotADirective(_))
+        ));
+        assert!(matches!(
+            incbin(&mut pp, Statements::Eof),
+            Err(EsiuxErrorKind::NotADirective(_))
+        ));
+        assert!(matches!(
+            repeat(&mut pp, Statements::Eof),
+            Err(EsiuxErrorKind::NotADirective(_))
+        ));
+    }
 }
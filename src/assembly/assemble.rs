@@ -1,28 +1,119 @@
-use std::{collections::HashMap, fs, io::Read, process};
+use std::{borrow::Cow, collections::HashMap, fs, io::Read, path::PathBuf};
 
-use crate::{format::EsiuxBin, Res};
+use crate::{
+    error::EsiuxErrorKind,
+    format::{
+        Binding, EsiuxBin, EsiuxObj, LinkMap, MapSection, MapSymbol, RelocKind, Relocation,
+        Section, SymbolEntry,
+    },
+    parser::ToNum,
+    Res,
+};
 
-use super::PreProcessor;
+use super::{is_literal, Fixup, PreProcessor, SourceMap, Statements, Target};
+
+/// # Assembly
+///
+/// * a program along with the listing and map asked for, see Assembler::build
+///
+#[derive(Debug)]
+pub struct Assembly {
+    pub program: EsiuxBin,
+    pub listing: Option<String>,
+    pub map: Option<LinkMap>,
+}
 
 #[derive(Debug, Default)]
 pub struct Assembler {
     pub labels: HashMap<String, u32>,
+    pub defines: HashMap<String, String>,
     pub source: String,
+    pub path: String,
+    pub include_paths: Vec<PathBuf>,
+    pub diagnostics: Vec<EsiuxErrorKind>,
 }
 
 impl Assembler {
-    pub fn new(file: &str) -> Res<Self> {
-        let mut file = fs::File::open(file)?;
+    pub fn new(path: &str) -> Res<Self> {
+        let mut file = fs::File::open(path)?;
         let mut s = String::new();
 
         file.read_to_string(&mut s)?;
 
         Ok(Self {
-            labels: HashMap::new(),
             source: s,
+            path: path.to_string(),
+            ..Default::default()
         })
     }
 
+    /// defines a variable before preprocessing, same as `-D NAME=VALUE`
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    /// adds a directory to search for included files, same as `-I DIR`
+    pub fn include_path(&mut self, dir: &str) {
+        self.include_paths.push(PathBuf::from(dir));
+    }
+
+    /// the source along with every file it includes
+    fn sources(&self) -> Res<SourceMap> {
+        let mut sources =
+            SourceMap::new(&self.path, self.source.clone(), self.include_paths.clone());
+        sources.load_includes()?;
+        Ok(sources)
+    }
+
+    fn preprocessor<'a>(
+        sources: &'a SourceMap,
+        defines: &'a HashMap<String, String>,
+        relocatable: bool,
+    ) -> Res<PreProcessor<'a>> {
+        let mut pp = PreProcessor::new(sources);
+        pp.relocatable = relocatable;
+        for (name, value) in defines {
+            pp.define_variable(Cow::Borrowed(name), Cow::Borrowed(value));
+        }
+        pp.handle()?;
+        Ok(pp)
+    }
+
+    /// renders collected errors into a single Diagnostics error
+    fn check(
+        diagnostics: &mut Vec<EsiuxErrorKind>,
+        mut errors: Vec<EsiuxErrorKind>,
+        sources: &SourceMap,
+    ) -> Res<()> {
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let report = super::report(&mut errors, sources);
+        *diagnostics = errors;
+        Err(report)
+    }
+
+    /// # preprocess
+    ///
+    /// * runs the preprocessor only
+    /// * returns the expanded source as text
+    ///
+    pub fn preprocess(&mut self) -> Res<String> {
+        let sources = self.sources()?;
+        let mut pp = Self::preprocessor(&sources, &self.defines, false)?;
+        let errors = std::mem::take(&mut pp.errors);
+        Self::check(&mut self.diagnostics, errors, &sources)?;
+
+        let mut out = String::new();
+        for stmt in pp.intern_buf {
+            out.push_str(&stmt.to_string());
+            out.push('\n');
+        }
+
+        Ok(out)
+    }
+
     pub fn collect_labels(&mut self) {
         let mut offset = 0;
 
@@ -51,17 +142,857 @@ impl Assembler {
         parts.join(" ")
     }
 
-    pub fn assemble(&mut self, preprocess: bool) -> Res<EsiuxBin> {
-        let mut pp = PreProcessor::new(&self.source);
-        pp.handle()?;
+    /// # listing
+    ///
+    /// * assembles the source and renders a listing of it
+    /// * see [`listing`](super::listing) for the layout
+    ///
+    pub fn listing(&mut self) -> Res<String> {
+        let sources = self.sources()?;
+        let mut pp = Self::preprocessor(&sources, &self.defines, false)?;
+        let errors = std::mem::take(&mut pp.errors);
+        Self::check(&mut self.diagnostics, errors, &sources)?;
+
+        super::listing(&pp).map_err(|e| super::report(&mut [e], &sources))
+    }
+
+    /// # map
+    ///
+    /// * assembles the source and lists where its sections and labels end up
+    /// * see [`link_map`](Self::link_map)
+    ///
+    pub fn map(&mut self) -> Res<LinkMap> {
+        let sources = self.sources()?;
+        let mut pp = Self::preprocessor(&sources, &self.defines, false)?;
+        let errors = std::mem::take(&mut pp.errors);
+        Self::check(&mut self.diagnostics, errors, &sources)?;
+
+        Ok(Self::link_map(&pp, &sources))
+    }
+
+    /// # link_map
+    ///
+    /// * where the sections and labels of a preprocessed source end up
+    /// * a label is global if it is named by `.global`, its file is the one that defines it
+    /// * numeric local labels are left out, pool entries are kept, as in an object
+    ///
+    fn link_map(pp: &PreProcessor, sources: &SourceMap) -> LinkMap {
+        let sections = Section::LAYOUT
+            .into_iter()
+            .filter(|x| pp.size(*x) > 0 || *x == Section::Text)
+            .map(|kind| MapSection {
+                kind,
+                address: pp.base(kind),
+                size: pp.size(kind),
+            })
+            .collect();
+
+        let symbols = pp
+            .labels
+            .iter()
+            .filter(|(name, _)| !name.contains('$') || is_literal(name))
+            .map(|(name, entry)| {
+                let binding = match pp.globals.iter().any(|x| x.lexeme == *name) {
+                    true => Binding::Global,
+                    false => Binding::Local,
+                };
+                let file = sources
+                    .get(entry.span.file)
+                    .map(|x| x.path.as_str())
+                    .unwrap_or_default();
+                MapSymbol::new(
+                    name,
+                    pp.base(entry.section) + entry.offset,
+                    entry.section,
+                    binding,
+                    file,
+                )
+            })
+            .collect();
+
+        LinkMap::new(sections, symbols)
+    }
 
-        if preprocess {
-            for stmt in pp.intern_buf {
-                println!("{stmt}");
+    /// # emit
+    ///
+    /// * lowers every instruction and emits data into the section it belongs to
+    /// * in an object a `.word` that depends on where a label ends up becomes a fixup
+    ///
+    fn emit(
+        pp: &PreProcessor,
+        errors: &mut Vec<EsiuxErrorKind>,
+    ) -> (HashMap<Section, Vec<u8>>, Vec<Fixup>) {
+        let mut sections = HashMap::from([(Section::Text, Vec::new())]);
+        let mut fixups = Vec::new();
+        let mut section = Section::Text;
+        for stmt in Statements::flatten(&pp.intern_buf) {
+            if let Some(next) = stmt.section() {
+                section = next;
+                continue;
+            }
+
+            let buf = sections.entry(section).or_default();
+            if stmt.is_instruction() {
+                match stmt.lower() {
+                    Ok(instruction) => buf.extend_from_slice(&instruction.mask().to_le_bytes()),
+                    Err(e) => errors.push(e),
+                }
+            } else if let Statements::Directive { name, .. } = stmt {
+                if !stmt.is_data() {
+                    continue;
+                }
+                let bytes = match pp.relocatable {
+                    true => {
+                        let offset = buf.len() as u32;
+                        Self::relocate(pp, stmt, section, offset, &mut fixups)
+                            .and_then(|x| x.data(offset, |_| None))
+                    }
+                    false => stmt.data(buf.len() as u32, |x| pp.address(x)),
+                };
+                match bytes.map_err(|e| pp.suggest(e)) {
+                    Ok(bytes) if section == Section::Bss && bytes.iter().any(|x| *x != 0) => {
+                        errors.push(EsiuxErrorKind::BssData.at(name.span()))
+                    }
+                    Ok(bytes) => buf.extend_from_slice(&bytes),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+
+        (sections, fixups)
+    }
+
+    /// # relocate
+    ///
+    /// * replaces the values of a `.word`, `.half` or `.byte` in an object with constants
+    /// * a `.word` that moves with a label is emitted as 0 and a fixup
+    ///
+    fn relocate<'a>(
+        pp: &PreProcessor<'a>,
+        stmt: &Statements<'a>,
+        section: Section,
+        offset: u32,
+        fixups: &mut Vec<Fixup>,
+    ) -> Res<Statements<'a>> {
+        let mut stmt = stmt.clone();
+        let Statements::Directive { name, params, .. } = &mut stmt else {
+            return Ok(stmt);
+        };
+        let width = match name.lexeme().as_ref() {
+            "word" => 4,
+            "half" => 2,
+            "byte" => 1,
+            _ => return Ok(stmt),
+        };
+
+        for (i, param) in params.iter_mut().enumerate() {
+            let lexeme = param.lexeme();
+            let value = match pp.relocatable(&lexeme).map_err(|e| e.at(param.span()))? {
+                (Some(_), _) if width != 4 => {
+                    return Err(EsiuxErrorKind::NotRelocatable(lexeme.to_string()).at(param.span()));
+                }
+                (Some(target), addend) => {
+                    fixups.push(Fixup {
+                        section,
+                        offset: offset + width * i as u32,
+                        kind: RelocKind::Word,
+                        target,
+                        addend,
+                    });
+                    0
+                }
+                (None, value) => value,
+            };
+            if let Some(token) = param.token_mut() {
+                token.lexeme = Cow::Owned(value.to_string());
             }
-            process::exit(0);
         }
 
-        todo!()
+        Ok(stmt)
+    }
+
+    /// reports every symbol `.global` exports that no label defines
+    fn undefined_globals(pp: &PreProcessor, errors: &mut Vec<EsiuxErrorKind>) {
+        for global in &pp.globals {
+            if !pp.labels.contains_key(&global.lexeme) {
+                let e = EsiuxErrorKind::UndefinedLabel(global.lexeme.to_string());
+                errors.push(pp.suggest(e).at(global.span()));
+            }
+        }
+    }
+
+    /// sections in Section::LAYOUT order, padded so the next one starts where layout placed it
+    fn segments(mut sections: HashMap<Section, Vec<u8>>, empty: bool) -> Vec<(Section, Vec<u8>)> {
+        let mut segments = Vec::new();
+        for kind in Section::LAYOUT {
+            let mut bytes = sections.remove(&kind).unwrap_or_default();
+            if bytes.is_empty() && kind != Section::Text && !empty {
+                continue;
+            }
+            if kind != Section::Bss {
+                bytes.resize(bytes.len().next_multiple_of(4), 0);
+            }
+            segments.push((kind, bytes));
+        }
+        segments
+    }
+
+    /// # assemble
+    ///
+    /// * preprocesses the source, lowers every instruction and emits data
+    /// * execution starts at the entry, see PreProcessor::entry, or at 0 if nothing is exported
+    /// * every section becomes its own segment, laid out in Section::LAYOUT order
+    /// * all errors are collected into diagnostics and returned as one report
+    ///
+    pub fn assemble(&mut self) -> Res<EsiuxBin> {
+        self.build(false, false).map(|x| x.program)
+    }
+
+    /// # build
+    ///
+    /// * same as assemble, also renders a listing and a map when asked for
+    /// * the source is preprocessed once for all of them
+    ///
+    pub fn build(&mut self, listing: bool, map: bool) -> Res<Assembly> {
+        let sources = self.sources()?;
+        let mut pp = Self::preprocessor(&sources, &self.defines, false)?;
+        let mut errors = std::mem::take(&mut pp.errors);
+        let (sections, _) = Self::emit(&pp, &mut errors);
+
+        Self::undefined_globals(&pp, &mut errors);
+
+        let entry = match pp.entry() {
+            Some(label) => match pp.address(&label.lexeme) {
+                Some(pc) => pc,
+                // an exported entry is already reported as undefined
+                None if pp.start.is_none() => 0,
+                None => {
+                    let e = EsiuxErrorKind::UndefinedLabel(label.lexeme.to_string());
+                    errors.push(pp.suggest(e).at(label.span()));
+                    0
+                }
+            },
+            // exporting several symbols without naming one of them the entry is most likely a
+            // mistake, unless the first one is undefined and `_start` misspelled
+            None => match pp
+                .globals
+                .first()
+                .filter(|x| pp.labels.contains_key(&x.lexeme))
+            {
+                Some(first) => {
+                    let e = EsiuxErrorKind::NoEntry(first.lexeme.to_string());
+                    errors.push(e.at(first.span()));
+                    0
+                }
+                None => 0,
+            },
+        };
+
+        Self::check(&mut self.diagnostics, errors, &sources)?;
+
+        self.labels = pp
+            .labels
+            .keys()
+            .filter_map(|label| Some((label.to_string(), pp.address(label)?)))
+            .collect();
+
+        let listing = match listing {
+            true => Some(super::listing(&pp).map_err(|e| super::report(&mut [e], &sources))?),
+            false => None,
+        };
+
+        Ok(Assembly {
+            program: EsiuxBin::from_segments(entry, Self::segments(sections, false))?,
+            listing,
+            map: map.then(|| Self::link_map(&pp, &sources)),
+        })
+    }
+
+    /// # object
+    ///
+    /// * assembles the source into a relocatable object, see EsiuxObj
+    /// * every section of the object starts at 0, the linker places them
+    /// * the symbol table starts with a symbol for each section in Section::LAYOUT order,
+    ///   fixups against labels refer to those, fixups against externs to the extern
+    /// * `.global` labels are exported, numeric local labels are left out
+    /// * every pool entry keeps its local symbol, see is_literal
+    ///
+    pub fn object(&mut self) -> Res<EsiuxObj> {
+        let sources = self.sources()?;
+        let mut pp = Self::preprocessor(&sources, &self.defines, true)?;
+        let mut errors = std::mem::take(&mut pp.errors);
+        let (sections, mut fixups) = Self::emit(&pp, &mut errors);
+        fixups.append(&mut pp.fixups);
+        fixups.sort_by_key(|x| (x.section.order(), x.offset));
+
+        Self::undefined_globals(&pp, &mut errors);
+        for external in &pp.externs {
+            if let Some(entry) = pp.labels.get(&external.lexeme) {
+                errors.push(
+                    EsiuxErrorKind::DuplicateLabel(external.lexeme.to_string(), external.span())
+                        .at(entry.span),
+                );
+            }
+        }
+
+        Self::check(&mut self.diagnostics, errors, &sources)?;
+
+        let mut symbols = Section::LAYOUT
+            .iter()
+            .map(|x| SymbolEntry::new(&format!(".{x}"), Binding::Local, Some(*x), 0))
+            .collect::<Vec<_>>();
+
+        let mut labels = pp
+            .labels
+            .iter()
+            .filter(|(name, _)| !name.contains('$') || is_literal(name))
+            .collect::<Vec<_>>();
+        labels.sort_by_key(|(name, entry)| (entry.section.order(), entry.offset, name.to_string()));
+        for (name, entry) in labels {
+            let binding = match pp.globals.iter().any(|x| x.lexeme == *name) {
+                true => Binding::Global,
+                false => Binding::Local,
+            };
+            symbols.push(SymbolEntry::new(
+                name,
+                binding,
+                Some(entry.section),
+                entry.offset,
+            ));
+        }
+        for external in &pp.externs {
+            if !symbols.iter().any(|x| x.name == external.lexeme) {
+                symbols.push(SymbolEntry::new(&external.lexeme, Binding::Extern, None, 0));
+            }
+        }
+
+        let relocations = fixups
+            .into_iter()
+            .map(|x| {
+                let symbol = match &x.target {
+                    Target::Section(section) => Some(section.order()),
+                    Target::Extern(name) => symbols.iter().position(|s| s.name == *name),
+                };
+                Relocation {
+                    section: x.section,
+                    offset: x.offset,
+                    kind: x.kind,
+                    symbol: symbol.unwrap_or_default() as u32,
+                    addend: x.addend as i32,
+                }
+            })
+            .collect();
+
+        Ok(EsiuxObj {
+            sections: Self::segments(sections, true),
+            symbols,
+            relocations,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        format::{Binding, RelocKind, Relocation, Section, SegmentHeader},
+        parser::ToNum,
+        processor::Instruction,
+    };
+
+    use super::Assembler;
+
+    /// writes files into a fresh directory under the system temp dir
+    fn fixture(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("esiux-{name}-{}", std::process::id()));
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn assemble_one() {
+        let mut asm = Assembler {
+            source: "mov r1, #1\nsvc #0xf0\n".to_string(),
+            ..Default::default()
+        };
+
+        let bin = asm.assemble().unwrap();
+
+        let mut ctrl = Vec::new();
+        for ins in ["mov r1, #1", "svc #0xf0"] {
+            let word = ins.parse::<Instruction>().unwrap().mask();
+            ctrl.extend_from_slice(&word.to_le_bytes());
+        }
+
+        assert_eq!(bin.header.entry, 0);
+        assert_eq!(
+            bin.section_headers,
+            vec![SegmentHeader::new(8, Section::Text, 24)]
+        );
+        assert_eq!(bin.data, ctrl);
+    }
+
+    #[test]
+    fn assemble_two() {
+        let mut asm = Assembler {
+            source: ".global _start\n\nloop:\n\tb loop\n\n_start:\n\tb.eq loop\n".to_string(),
+            ..Default::default()
+        };
+
+        let bin = asm.assemble().unwrap();
+
+        let ctrl = "b.eq #-1".parse::<Instruction>().unwrap().mask();
+
+        assert_eq!(bin.header.entry, 4);
+        assert_eq!(bin.data[4..], ctrl.to_le_bytes());
+        assert_eq!(asm.labels.get("loop"), Some(&0));
+    }
+
+    #[test]
+    fn assemble_three() {
+        let mut asm = Assembler {
+            source: "mov r8, #CODE\nsvc #0xf0\n".to_string(),
+            ..Default::default()
+        };
+        asm.define("CODE", "0x2a");
+
+        let bin = asm.assemble().unwrap();
+        let ctrl = "mov r8, #0x2a".parse::<Instruction>().unwrap().mask();
+
+        assert_eq!(bin.data[..4], ctrl.to_le_bytes());
+        assert!(asm.preprocess().unwrap().contains("#0x2a"));
+    }
+
+    #[test]
+    fn assemble_four() {
+        let mut asm = Assembler {
+            source:
+                "_start:\n\tmovv r1, #1\n\tadd r1, r2\n\tb nowhere\n\tmov r1, #5000\n\tsvc #0xf0\n"
+                    .to_string(),
+            path: "test.asm".to_string(),
+            ..Default::default()
+        };
+
+        let err = asm.assemble().unwrap_err().to_string();
+        let lines = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.span().unwrap().line)
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines, vec![2, 3, 4, 5]);
+        assert!(err.contains("--> test.asm:5:10"));
+        assert!(err.ends_with("aborting due to 4 previous error(s)"));
+    }
+
+    #[test]
+    fn assemble_five() {
+        let mut asm = Assembler {
+            source: ".global _start\n.data\nduck: .word 4, msg\n.section .rodata\nmsg: .asciz \"hi\"\n.text\n_start:\n\tmov r1, #1\n\tsvc #0xf0\n.bss\nbuf: .space 8\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        let bin = asm.assemble().unwrap();
+
+        assert_eq!(bin.header.entry, 0);
+        assert_eq!(
+            bin.section_headers,
+            vec![
+                SegmentHeader::new(8, Section::Text, 48),
+                SegmentHeader::new(4, Section::Rodata, 56),
+                SegmentHeader::new(8, Section::Data, 60),
+                SegmentHeader::new(8, Section::Bss, 68),
+            ]
+        );
+        assert_eq!(bin.data.len(), 20);
+        assert_eq!(bin.data[8..12], *b"hi\0\0");
+        assert_eq!(bin.data[12..], [4, 0, 0, 0, 8, 0, 0, 0]);
+        assert_eq!(asm.labels.get("duck"), Some(&12));
+        assert_eq!(asm.labels.get("buf"), Some(&20));
+    }
+
+    #[test]
+    fn assemble_six() {
+        let mut asm = Assembler {
+            source: ".bss\nbuf: .word 1\n.section .heap\n".to_string(),
+            ..Default::default()
+        };
+
+        asm.assemble().unwrap_err();
+        let errors = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("Section .bss can only hold zero initialised data"));
+        assert!(errors[1].starts_with("Unable to parse from string: failed to parse segment: heap"));
+    }
+
+    #[test]
+    fn assemble_seven() {
+        let mut asm = Assembler {
+            source: ".equ BUF_SIZE, 16\n.set COUNT, BUF_SIZE / 4\n_start:\n\tmov r1, #(BUF_SIZE*4)+1\n\tadd r2, r1, #COUNT << 2\n\tmov r3, #end - start\nstart:\n\tsvc #%lo(0x1f0) & 0xff\nend:\n.data\n.word end - start, %hi(0x12345678)\n.set COUNT, COUNT + 1\n.byte COUNT\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        let bin = asm.assemble().unwrap();
+
+        let mut ctrl = Vec::new();
+        for ins in ["mov r1, #65", "add r2, r1, #16", "mov r3, #4", "svc #0xf0"] {
+            let word = ins.parse::<Instruction>().unwrap().mask();
+            ctrl.extend_from_slice(&word.to_le_bytes());
+        }
+        ctrl.extend_from_slice(&[4, 0, 0, 0, 0x34, 0x12, 0, 0, 5, 0, 0, 0]);
+
+        assert_eq!(bin.data, ctrl);
+    }
+
+    #[test]
+    fn assemble_eight() {
+        let mut asm = Assembler {
+            source: ".equ SIZE, 300\n\tmov r1, #SIZE * 16\n\tb 0x400000\n\tsvc #SIZE\n\tmov r1, #SIZE / (4 - 4)\n\tb 6\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        asm.assemble().unwrap_err();
+        let errors = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Value 4800 does not fit in the l12 field, expected -2048..=2047 @ 2:10",
+                "Branch target 4194304 is 1048575 words away, a branch reaches -524288..=524287 words @ 3:4",
+                "Value 300 does not fit in the interrupt key field, expected 0..=255 @ 4:6",
+                "Division by zero in expression @ 5:10",
+                "Branch target 6 is not word aligned @ 6:4",
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_nine() {
+        let dir = fixture(
+            "include",
+            &[
+                (
+                    "main.asm",
+                    b".include \"lib.s\"\n_start:\n\tINC r1\n\tsvc #0xf0\n.data\n.incbin \"blob.bin\", 1, 2\n",
+                ),
+                (
+                    "inc/lib.s",
+                    b".equ STEP, 2\n.macro INC \\reg\n add \\reg, \\reg, #STEP\n.endm\n",
+                ),
+                ("blob.bin", &[1, 2, 3, 4]),
+            ],
+        );
+
+        let mut asm = Assembler::new(dir.join("main.asm").to_str().unwrap()).unwrap();
+        asm.include_path(dir.join("inc").to_str().unwrap());
+        let bin = asm.assemble().unwrap();
+
+        let mut ctrl = Vec::new();
+        for ins in ["add r1, r1, #2", "svc #0xf0"] {
+            let word = ins.parse::<Instruction>().unwrap().mask();
+            ctrl.extend_from_slice(&word.to_le_bytes());
+        }
+        ctrl.extend_from_slice(&[2, 3, 0, 0]);
+
+        assert_eq!(bin.data, ctrl);
+        assert!(asm.listing().unwrap().contains("inc/lib.s"));
+    }
+
+    #[test]
+    fn assemble_ten() {
+        let dir = fixture(
+            "cycle",
+            &[
+                ("a.s", b".include \"b.s\"\n"),
+                (
+                    "b.s",
+                    b"\tmovv r1, #1\n.include \"a.s\"\n.include \"c.s\"\n",
+                ),
+            ],
+        );
+
+        let mut asm = Assembler::new(dir.join("a.s").to_str().unwrap()).unwrap();
+        let err = asm.assemble().unwrap_err().to_string();
+        let errors = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert!(err.contains(&format!("--> {}:1:2", dir.join("b.s").display())));
+        assert!(errors[0].starts_with("Unknown macro called: movv"));
+        assert!(errors[1].starts_with("Include cycle: "));
+        assert!(errors[1].contains("a.s -> "));
+        assert!(errors[2].starts_with("Cannot find file: c.s"));
+    }
+
+    #[test]
+    fn assemble_eleven() {
+        let mut asm = Assembler {
+            source: ".global _start, count\n.extern print\n_start:\n\tb print\nloop:\n\tmov r1, #count\n\tb loop\n.data\n\t.word 7\ncount:\n\t.word loop + 4, 12\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        let obj = asm.object().unwrap();
+        let symbols = obj
+            .symbols
+            .iter()
+            .map(|x| (x.name.as_str(), x.binding, x.section, x.value))
+            .collect::<Vec<_>>();
+        let word = |ins: &str| ins.parse::<Instruction>().unwrap().mask().to_le_bytes();
+
+        assert_eq!(
+            symbols[4..],
+            [
+                ("_start", Binding::Global, Some(Section::Text), 0),
+                ("loop", Binding::Local, Some(Section::Text), 4),
+                ("count", Binding::Global, Some(Section::Data), 4),
+                ("print", Binding::Extern, None, 0),
+            ]
+        );
+        assert_eq!(
+            obj.relocations,
+            vec![
+                Relocation {
+                    section: Section::Text,
+                    offset: 0,
+                    kind: RelocKind::Branch,
+                    symbol: 7,
+                    addend: 0,
+                },
+                Relocation {
+                    section: Section::Text,
+                    offset: 4,
+                    kind: RelocKind::Imm12,
+                    symbol: 2,
+                    addend: 4,
+                },
+                Relocation {
+                    section: Section::Data,
+                    offset: 4,
+                    kind: RelocKind::Word,
+                    symbol: 0,
+                    addend: 8,
+                },
+            ]
+        );
+        assert_eq!(obj.section(Section::Text).unwrap()[8..], word("b #-1"));
+        assert_eq!(
+            obj.section(Section::Data).unwrap(),
+            [7, 0, 0, 0, 0, 0, 0, 0, 12, 0, 0, 0]
+        );
+        assert_eq!(obj.section(Section::Bss), Some(&[][..]));
+    }
+
+    #[test]
+    fn assemble_twelve() {
+        let mut asm = Assembler {
+            source: ".global _strat\n.extern print\n_start:\n\tb 8\n\tsvc #print\n.data\n\t.half _start\nprint:\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        asm.object().unwrap_err();
+        let errors = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Undefined label: _strat, did you mean _start? @ 1:9",
+                "Cannot relocate 8, expected a symbol plus or minus a constant @ 4:4",
+                "Cannot relocate print, expected a symbol plus or minus a constant @ 5:6",
+                "Cannot relocate _start, expected a symbol plus or minus a constant @ 7:8",
+                "Label print is already defined at 2:9 @ 8:1",
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_thirteen() {
+        let dir = fixture(
+            "map",
+            &[
+                (
+                    "main.asm",
+                    b".global _start
+_start:
+	b print
+1:
+	svc #0xf0
+.include \"io.s\"
+.data
+count:
+	.word 3
+",
+                ),
+                (
+                    "io.s",
+                    b"print:
+	ldr r1, =count
+	b 1b
+",
+                ),
+            ],
+        );
+        let main = dir.join("main.asm");
+        let io = dir.join("io.s");
+
+        let mut asm = Assembler::new(main.to_str().unwrap()).unwrap();
+        let map = asm.map().unwrap();
+        let symbols = map
+            .symbols
+            .iter()
+            .map(|x| {
+                (
+                    x.name.as_str(),
+                    x.address,
+                    x.size,
+                    x.binding,
+                    x.file.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let sections = map
+            .sections
+            .iter()
+            .map(|x| (x.kind, x.address, x.size))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            sections,
+            [(Section::Text, 0, 0x14), (Section::Data, 0x14, 4)]
+        );
+        assert_eq!(
+            symbols,
+            [
+                ("_start", 0, 8, Binding::Global, main.to_str().unwrap()),
+                ("print", 8, 8, Binding::Local, io.to_str().unwrap()),
+                (".Lpool$0", 0x10, 4, Binding::Local, io.to_str().unwrap()),
+                ("count", 0x14, 4, Binding::Local, main.to_str().unwrap()),
+            ]
+        );
+        assert_eq!(map.lookup(0xc).unwrap().name, "print");
+    }
+
+    #[test]
+    fn assemble_fourteen() {
+        let mut asm = Assembler {
+            source: ".equ BIG, 0x800\n\tmov r1, #0x800\n\tadd r1, r1, #BIG\n\tmov r1, #0x7ff\n\tmov r1, #-0x800\n\tmov r1, #-0x801\n\tldr r1, [r2, #-0xfff]\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        asm.assemble().unwrap_err();
+        let errors = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Value 2048 does not fit in the l12 field, expected -2048..=2047 @ 2:10",
+                "Value 2048 does not fit in the l12 field, expected -2048..=2047 @ 3:14",
+                "Value -2049 does not fit in the l12 field, expected -2048..=2047 @ 6:10",
+            ]
+        );
+
+        // a load or store offset is a magnitude of up to 0xfff either way
+        asm.source = "\tldr r1, [r2, #-0xfff]\n\tldr r1, [r2, #-4095]\n".to_string();
+        let bin = asm.assemble().unwrap();
+        assert_eq!(bin.data[..4], bin.data[4..8]);
+    }
+
+    #[test]
+    fn assemble_fifteen() {
+        let source =
+            "_start:\n\tldr r1, =count\n\tb done\ndone:\n\tsvc #0xf0\n.data\ncount:\n\t.word 3\n";
+        let mut asm = Assembler {
+            source: source.to_string(),
+            ..Default::default()
+        };
+
+        let built = asm.build(true, true).unwrap();
+
+        assert_eq!(built.listing.unwrap(), asm.listing().unwrap());
+        assert_eq!(
+            built.map.unwrap().to_string(),
+            asm.map().unwrap().to_string()
+        );
+        assert_eq!(built.program, asm.assemble().unwrap());
+
+        let built = asm.build(false, false).unwrap();
+        assert!(built.listing.is_none() && built.map.is_none());
+    }
+
+    #[test]
+    fn assemble_sixteen() {
+        let mut asm = Assembler {
+            source: ".global print, main\nprint:\n\tbr lr\nmain:\n\tsvc #0xf0\n".to_string(),
+            ..Default::default()
+        };
+
+        asm.assemble().unwrap_err();
+        assert_eq!(
+            asm.diagnostics[0].to_string(),
+            "No entry point, print and other symbols are exported but _start is not, name the entry with .entry @ 1:9"
+        );
+
+        asm.source.push_str(".entry main\n");
+        assert_eq!(asm.assemble().unwrap().header.entry, 4);
+
+        asm.source = asm.source.replace("main", "_start");
+        asm.source = asm.source.replace(".entry _start\n", "");
+        assert_eq!(asm.assemble().unwrap().header.entry, 4);
+
+        // a single exported symbol is the entry
+        asm.source = ".global main\nprint:\n\tbr lr\nmain:\n\tsvc #0xf0\n".to_string();
+        assert_eq!(asm.assemble().unwrap().header.entry, 4);
+    }
+
+    #[test]
+    fn assemble_seventeen() {
+        let source = ".macro SPIN \\reg\n_spin\\@:\n\tsub \\reg, \\reg, #1\n\tb.ne _spin\\@\n.endm\n.global _start\n_start:\n\tmov r1, #3\n\tSPIN r1\n\tb #2\nloop:\n\tb loop\n1:\n\tldr r2, =0x12345\n\tb 1b\n\tsvc #0xf0\n.data\nvalue:\n\t.word 1, value\n";
+        let mut asm = Assembler {
+            source: source.to_string(),
+            ..Default::default()
+        };
+        let bin = asm.assemble().unwrap();
+
+        // `b #2` is a word offset, the same as the Instruction parser reads it
+        let ctrl = "b #2".parse::<Instruction>().unwrap().mask();
+        assert_eq!(bin.data[12..16], ctrl.to_le_bytes());
+
+        // the preprocessed source assembles to the same program, pools and numeric labels
+        // included
+        let mut again = Assembler {
+            source: asm.preprocess().unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(again.assemble().unwrap(), bin);
     }
 }
@@ -0,0 +1,221 @@
+use crate::{
+    error::{EsiuxErrorKind, Span},
+    Res,
+};
+
+use super::{eval, PreProcessor, Statements};
+
+/// directives that open, switch or close a conditional block
+pub const CONDITIONALS: [&str; 6] = ["if", "ifdef", "ifndef", "elseif", "else", "endif"];
+
+/// # Conditional
+///
+/// * an open `.if` / `.ifdef` / `.ifndef` block
+/// * only one branch of a block is assembled, the first one whose condition holds
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Conditional {
+    // statements of the current branch are assembled
+    pub active: bool,
+    // a branch was taken already, the ones after it are skipped
+    pub taken: bool,
+    pub else_seen: bool,
+    // the opening directive, reported if the block is never closed
+    pub span: Span,
+}
+
+impl Statements<'_> {
+    pub fn is_conditional(&self) -> bool {
+        matches!(self, Self::Directive { name, .. } if CONDITIONALS.contains(&name.lexeme().as_ref()))
+    }
+}
+
+impl<'a> PreProcessor<'a> {
+    /// true if statements are assembled, every open block is in a branch that is taken
+    pub fn assembling(&self) -> bool {
+        self.conditionals.iter().all(|x| x.active)
+    }
+
+    /// # conditional_scope
+    ///
+    /// * runs f with its own conditional scope, used for files and macro bodies
+    /// * blocks f opens have to be closed by f, the ones left open are reported
+    ///
+    pub fn conditional_scope<T, F>(&mut self, f: F) -> T
+    where
+        F: FnOnce(&mut Self) -> T,
+    {
+        let floor = std::mem::replace(&mut self.floor, self.conditionals.len());
+        let out = f(self);
+
+        while self.conditionals.len() > self.floor {
+            if let Some(open) = self.conditionals.pop() {
+                self.errors.push(
+                    EsiuxErrorKind::Unterminated("if".to_string(), "endif".to_string())
+                        .at(open.span),
+                );
+            }
+        }
+        self.floor = floor;
+
+        out
+    }
+
+    /// evaluates the condition of `.if`, `.elseif`, `.ifdef` and `.ifndef`
+    fn condition(&self, stmt: &Statements<'a>) -> Res<bool> {
+        let (name, params) = match stmt {
+            Statements::Directive { name, params, .. } => (name, params),
+            _ => return Ok(false),
+        };
+        let param = params.first().ok_or_else(|| {
+            EsiuxErrorKind::Expected(Box::new("a condition"), "end of line".to_string())
+                .at(name.span())
+        })?;
+
+        match name.lexeme().as_ref() {
+            "ifdef" | "ifndef" => {
+                let defined = self.variables.contains_key(&param.lexeme())
+                    || self.labels.contains_key(&param.lexeme());
+                Ok(defined == (name.lexeme() == "ifdef"))
+            }
+            _ => eval(&param.lexeme(), |_| None)
+                .map(|x| x != 0)
+                .map_err(|e| e.at(param.span())),
+        }
+    }
+
+    /// # conditional
+    ///
+    /// * opens, switches or closes a conditional block
+    /// * conditions inside a skipped block are never evaluated
+    ///
+    pub fn conditional(&mut self, stmt: &Statements<'a>) -> Res<()> {
+        let name = match stmt {
+            Statements::Directive { name, .. } => name,
+            _ => return Ok(()),
+        };
+        let directive = name.lexeme();
+        let span = name.span();
+
+        if matches!(directive.as_ref(), "if" | "ifdef" | "ifndef") {
+            let outer = self.assembling();
+            // a block whose condition fails to evaluate is still opened, its .endif matches it
+            let holds = if outer {
+                self.condition(stmt)
+            } else {
+                Ok(false)
+            };
+            let active = *holds.as_ref().unwrap_or(&false);
+            self.conditionals.push(Conditional {
+                active,
+                taken: !outer || holds.is_err() || active,
+                else_seen: false,
+                span,
+            });
+            return holds.map(|_| ());
+        }
+
+        if self.conditionals.len() <= self.floor {
+            return Err(EsiuxErrorKind::UnmatchedConditional(directive.to_string()).at(span));
+        }
+        let open = self.conditionals.len() - 1;
+        if directive != "endif" && self.conditionals[open].else_seen {
+            return Err(EsiuxErrorKind::AfterElse(directive.to_string()).at(span));
+        }
+
+        match directive.as_ref() {
+            "elseif" if self.conditionals[open].taken => self.conditionals[open].active = false,
+            "elseif" => {
+                let holds = self.condition(stmt)?;
+                self.conditionals[open].active = holds;
+                self.conditionals[open].taken = holds;
+            }
+            "else" => {
+                let block = &mut self.conditionals[open];
+                block.active = !block.taken;
+                block.taken = true;
+                block.else_seen = true;
+            }
+            _ => {
+                self.conditionals.pop();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assembly::Assembler;
+
+    fn assemble(source: &str, defines: &[(&str, &str)]) -> String {
+        let mut asm = Assembler {
+            source: source.to_string(),
+            ..Default::default()
+        };
+        for (name, value) in defines {
+            asm.define(name, value);
+        }
+        asm.preprocess()
+            .unwrap()
+            .lines()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    #[test]
+    fn conditional_one() {
+        let source = ".ifdef DEBUG\nmov r1, #1\n.else\nmov r1, #2\n.endif\n";
+
+        assert!(assemble(source, &[("DEBUG", "1")]).starts_with("mov    r1, #1"));
+        assert!(assemble(source, &[]).starts_with("mov    r1, #2"));
+    }
+
+    #[test]
+    fn conditional_two() {
+        let source = ".equ MEM, 64\n.if MEM > 128\nmov r1, #1\n.elseif MEM == 64 && !LARGE\n.ifndef SMALL\nmov r1, #2\n.else\nmov r1, #3\n.endif\n.else\nmov r1, #4\n.endif\n";
+
+        assert!(assemble(source, &[("LARGE", "0")]).ends_with("mov    r1, #2"));
+        assert!(assemble(source, &[("LARGE", "0"), ("SMALL", "1")]).ends_with("mov    r1, #3"));
+        assert!(assemble(source, &[("LARGE", "1")]).ends_with("mov    r1, #4"));
+    }
+
+    #[test]
+    fn conditional_three() {
+        let source =
+            ".macro LOG \\v\n.ifdef DEBUG\n mov r8, \\v\n.endif\n add r1, r1, #1\n.endm\nLOG r2\n";
+
+        assert!(assemble(source, &[]).ends_with(".endm; add    r1, r1, #1"));
+        assert!(assemble(source, &[("DEBUG", "1")])
+            .ends_with(".endm; mov    r8, r2; add    r1, r1, #1"));
+    }
+
+    #[test]
+    fn conditional_four() {
+        let mut asm = Assembler {
+            source: ".if 1\n.else\n.else\n.endif\n.endif\n.if UNDEFINED\n.endif\n.if 1\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        asm.preprocess().unwrap_err();
+        let errors = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                ".else after .else @ 3:2",
+                ".endif without a matching .if @ 5:2",
+                "Undefined symbol: UNDEFINED @ 6:5",
+                "Unterminated .if block, missing .endif @ 8:2",
+            ]
+        );
+    }
+}
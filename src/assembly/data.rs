@@ -0,0 +1,266 @@
+use crate::{error::EsiuxErrorKind, format::Section, Res};
+
+use super::{eval, Statements, Symbol};
+
+/// directives that place bytes into the current section
+pub const DATA_DIRECTIVES: [&str; 7] = ["word", "half", "byte", "ascii", "asciz", "space", "align"];
+
+/// # parse_number
+///
+/// * decimal, `0x` hex, `0o` octal and `0b` binary literals
+/// * an optional leading `#` and `-` are accepted
+///
+pub fn parse_number(s: &str) -> Option<i64> {
+    let s = s.trim_start_matches('#');
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+
+    let value = match s.get(..2) {
+        Some("0x" | "0X") => i64::from_str_radix(&s[2..], 16),
+        Some("0o" | "0O") => i64::from_str_radix(&s[2..], 8),
+        Some("0b" | "0B") => i64::from_str_radix(&s[2..], 2),
+        _ => s.parse::<i64>(),
+    }
+    .ok()?;
+
+    Some(if negative { -value } else { value })
+}
+
+/// bytes of a string literal with its quotes removed and escapes applied
+fn unescape(sym: &Symbol) -> Res<Vec<u8>> {
+    let lexeme = sym.lexeme();
+    let inner = lexeme
+        .strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .ok_or_else(|| {
+            EsiuxErrorKind::Expected(Box::new("a string"), lexeme.to_string()).at(sym.span())
+        })?;
+
+    let mut out = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        let byte = match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('0') => 0,
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            Some('x') => {
+                let hex = chars.by_ref().take(2).collect::<String>();
+                u8::from_str_radix(&hex, 16).map_err(|_| {
+                    EsiuxErrorKind::InvalidEscape(format!("\\x{hex}")).at(sym.span())
+                })?
+            }
+            x => {
+                let esc = x.map(String::from).unwrap_or_default();
+                return Err(EsiuxErrorKind::InvalidEscape(format!("\\{esc}")).at(sym.span()));
+            }
+        };
+        out.push(byte);
+    }
+
+    Ok(out)
+}
+
+/// a value that has to be known while preprocessing, labels are not placed yet
+pub(super) fn constant(sym: Option<&Symbol>, directive: &Symbol) -> Res<i64> {
+    let sym = sym.ok_or_else(|| {
+        EsiuxErrorKind::Expected(Box::new("a constant"), "end of line".to_string())
+            .at(directive.span())
+    })?;
+    eval(&sym.lexeme(), |_| None).map_err(|e| match e {
+        EsiuxErrorKind::UndefinedSymbol(_) => {
+            EsiuxErrorKind::Expected(Box::new("a constant"), sym.lexeme().to_string())
+                .at(sym.span())
+        }
+        e => e.at(sym.span()),
+    })
+}
+
+impl<'a> Statements<'a> {
+    pub fn is_data(&self) -> bool {
+        matches!(self, Self::Directive { name, .. } if DATA_DIRECTIVES.contains(&name.lexeme().as_ref()))
+    }
+
+    /// the section a `.section` statement switches to
+    pub fn section(&self) -> Option<Section> {
+        match self {
+            Self::Directive { name, params, .. } if name.lexeme() == "section" => {
+                params.first()?.lexeme().parse().ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// # data_size
+    ///
+    /// * number of bytes data emits at pc
+    /// * `.word`, `.half` and `.byte` are sized without evaluating their values
+    ///
+    pub fn data_size(&self, pc: u32) -> Res<u32> {
+        match self {
+            Self::Directive { name, params, .. } if !params.is_empty() => {
+                let width = match name.lexeme().as_ref() {
+                    "word" => 4,
+                    "half" => 2,
+                    "byte" => 1,
+                    _ => return Ok(self.data(pc, |_| None)?.len() as u32),
+                };
+                Ok(width * params.len() as u32)
+            }
+            _ => Ok(self.data(pc, |_| None)?.len() as u32),
+        }
+    }
+
+    /// # data
+    ///
+    /// * bytes emitted by a data directive placed at pc
+    /// * labels are looked up through resolve, values are little endian
+    /// * the size never depends on label values, see data_size
+    ///
+    pub fn data<F>(&self, pc: u32, resolve: F) -> Res<Vec<u8>>
+    where
+        F: Fn(&str) -> Option<u32>,
+    {
+        let (name, params) = match self {
+            Self::Directive { name, params, .. } if self.is_data() => (name, params),
+            x => return Err(EsiuxErrorKind::NotAnInstruction(Box::new(x.to_string()))),
+        };
+        let directive = name.lexeme();
+        let mut out = Vec::new();
+
+        match directive.as_ref() {
+            "word" | "half" | "byte" => {
+                let width = match directive.as_ref() {
+                    "word" => 4,
+                    "half" => 2,
+                    _ => 1,
+                };
+                let bits = width * 8;
+                let (min, max) = (-(1i64 << (bits - 1)), (1i64 << bits) - 1);
+
+                if params.is_empty() {
+                    constant(None, name)?;
+                }
+                for param in params {
+                    let value = eval(&param.lexeme(), |x| resolve(x).map(i64::from))
+                        .map_err(|e| e.at(param.span()))?;
+                    if value < min || value > max {
+                        return Err(EsiuxErrorKind::DataRange(value, directive.to_string())
+                            .at(param.span()));
+                    }
+                    out.extend_from_slice(&value.to_le_bytes()[..width]);
+                }
+            }
+            "ascii" | "asciz" => {
+                if params.is_empty() {
+                    return Err(EsiuxErrorKind::Expected(
+                        Box::new("a string"),
+                        "end of line".to_string(),
+                    )
+                    .at(name.span()));
+                }
+                for param in params {
+                    out.extend(unescape(param)?);
+                    if directive == "asciz" {
+                        out.push(0);
+                    }
+                }
+            }
+            "space" => {
+                let size = constant(params.first(), name)?;
+                let fill = match params.get(1) {
+                    Some(_) => constant(params.get(1), name)?,
+                    None => 0,
+                };
+                if !(0..=u16::MAX as i64).contains(&size) {
+                    return Err(
+                        EsiuxErrorKind::DataRange(size, directive.to_string()).at(params[0].span())
+                    );
+                }
+                if !(-128..=255).contains(&fill) {
+                    return Err(
+                        EsiuxErrorKind::DataRange(fill, directive.to_string()).at(params[1].span())
+                    );
+                }
+                out.resize(size as usize, fill as u8);
+            }
+            _ => {
+                let align = constant(params.first(), name)?;
+                if align <= 0 || align.count_ones() != 1 || align > u16::MAX as i64 {
+                    return Err(EsiuxErrorKind::Alignment(align).at(params[0].span()));
+                }
+                let align = align as u32;
+                out.resize(((align - pc % align) % align) as usize, 0);
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assembly::{Scanner, Statements};
+
+    use super::parse_number;
+
+    fn data(src: &str, pc: u32) -> Vec<u8> {
+        let stmt = Scanner::new(src).analyze().next().unwrap();
+        stmt.data(pc, |x| (x == "duck").then_some(0x40)).unwrap()
+    }
+
+    #[test]
+    fn data_one() {
+        assert_eq!(parse_number("#0x2a"), Some(42));
+        assert_eq!(parse_number("-0b101"), Some(-5));
+        assert_eq!(parse_number("0o17"), Some(15));
+        assert_eq!(parse_number("duck"), None);
+    }
+
+    #[test]
+    fn data_two() {
+        assert_eq!(data(".word 1, duck", 0), vec![1, 0, 0, 0, 0x40, 0, 0, 0]);
+        assert_eq!(data(".half 0xbeef, -1", 0), vec![0xef, 0xbe, 0xff, 0xff]);
+        assert_eq!(data(".byte 255, -128", 0), vec![0xff, 0x80]);
+    }
+
+    #[test]
+    fn data_three() {
+        assert_eq!(data(".ascii \"hi\\n\"", 0), b"hi\n".to_vec());
+        assert_eq!(data(".asciz \"a, b\" \"\\x41\"", 0), b"a, b\0A\0".to_vec());
+        assert_eq!(data(".space 3, 0xff", 0), vec![0xff; 3]);
+        assert_eq!(data(".align 4", 5), vec![0; 3]);
+        assert_eq!(data(".align 4", 8), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn data_four() {
+        let errors = [".byte 256", ".word nowhere", ".align 3", ".ascii \"\\q\""]
+            .into_iter()
+            .map(|src| {
+                let stmt = Scanner::new(src).analyze().next().unwrap();
+                stmt.data(0, |_| None).unwrap_err().to_string()
+            })
+            .collect::<Vec<_>>();
+
+        assert!(errors[0].starts_with("Value 256 does not fit in .byte"));
+        assert!(errors[1].starts_with("Undefined symbol: nowhere"));
+        assert!(errors[2].starts_with(".align expects a power of two, found 3"));
+        assert!(errors[3].starts_with("Invalid escape sequence in string: \\q"));
+
+        assert!(matches!(
+            Scanner::new(".ascii \"open").analyze().next(),
+            None | Some(Statements::Eof)
+        ));
+    }
+}
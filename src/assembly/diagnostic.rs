@@ -0,0 +1,121 @@
+use crate::error::EsiuxErrorKind;
+
+use super::SourceMap;
+
+/// # render
+///
+/// * renders a single error rustc style
+/// * spanned errors show the offending line with a caret under the token
+/// * the file of the span is looked up in sources
+/// * errors without a location only print the message
+/// * a suggestion attached with EsiuxErrorKind::help is printed as a help line
+///
+pub fn render(err: &EsiuxErrorKind, sources: &SourceMap) -> String {
+    let (inner, span, file) = match err {
+        EsiuxErrorKind::Spanned(inner, span) if span.line > 0 => match sources.get(span.file) {
+            Some(file) => (inner, span, file),
+            None => return format!("error: {inner}\n"),
+        },
+        EsiuxErrorKind::Spanned(inner, _) => return format!("error: {inner}\n"),
+        x => return format!("error: {x}\n"),
+    };
+    let (path, source) = (&file.path, &file.source);
+
+    let (inner, hint) = match inner.as_ref() {
+        EsiuxErrorKind::Help(inner, hint) => (inner, Some(hint)),
+        _ => (inner, None),
+    };
+
+    let gutter = span.line.to_string().len();
+    let mut out = format!("error: {inner}\n");
+    out.push_str(&format!(
+        "{:>gutter$}--> {path}:{}:{}\n",
+        "", span.line, span.column
+    ));
+
+    if let Some(line) = source.lines().nth(span.line - 1) {
+        // keep tabs so the caret lines up with the source
+        let pad = line
+            .chars()
+            .take(span.column.saturating_sub(1))
+            .map(|x| if x == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        out.push_str(&format!("{:>gutter$} |\n", ""));
+        out.push_str(&format!("{} | {}\n", span.line, line));
+        out.push_str(&format!(
+            "{:>gutter$} | {}{}\n",
+            "",
+            pad,
+            "^".repeat(span.len.max(1))
+        ));
+    }
+    if let Some(hint) = hint {
+        out.push_str(&format!("{:>gutter$} = help: did you mean `{hint}`?\n", ""));
+    }
+
+    out
+}
+
+/// # report
+///
+/// * sorts errors by location and renders all of them
+/// * wraps the result up as a single Diagnostics error
+///
+pub fn report(errors: &mut [EsiuxErrorKind], sources: &SourceMap) -> EsiuxErrorKind {
+    errors.sort_by_key(|x| x.span());
+
+    let rendered = errors
+        .iter()
+        .map(|x| render(x, sources))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    EsiuxErrorKind::Diagnostics(Box::new(format!("{rendered}\n")), errors.len())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembly::SourceMap,
+        error::{EsiuxErrorKind, Span},
+    };
+
+    use super::render;
+
+    #[test]
+    fn render_one() {
+        let source = "_start:\n\tmovv r1, #1\n";
+        let err = EsiuxErrorKind::UnknownInstruction("movv".to_string()).at(Span {
+            line: 2,
+            column: 2,
+            len: 4,
+            ..Default::default()
+        });
+
+        let sources = SourceMap::new("test.asm", source.to_string(), Vec::new());
+        let rendered = render(&err, &sources);
+        let ctrl = "error: Unrecognized instruction: movv\n --> test.asm:2:2\n  |\n2 | \tmovv r1, #1\n  | \t^^^^\n";
+
+        assert_eq!(rendered, ctrl);
+    }
+
+    #[test]
+    fn render_two() {
+        let source = "\tb lopo\n";
+        let err = EsiuxErrorKind::UndefinedSymbol("lopo".to_string())
+            .at(Span {
+                line: 1,
+                column: 4,
+                len: 4,
+                ..Default::default()
+            })
+            .help("loop".to_string());
+
+        let sources = SourceMap::new("test.asm", source.to_string(), Vec::new());
+        let rendered = render(&err, &sources);
+        let ctrl = "error: Undefined symbol: lopo\n --> test.asm:1:4\n  |\n1 | \tb lopo\n  | \t  ^^^^\n  = help: did you mean `loop`?\n";
+
+        assert_eq!(rendered, ctrl);
+    }
+}
@@ -0,0 +1,389 @@
+use std::iter::Peekable;
+
+use crate::{error::EsiuxErrorKind, Res};
+
+use super::{parse_number, Statements, Symbol};
+
+/// directives whose arguments are comma separated expressions
+pub const EXPR_DIRECTIVES: [&str; 11] = [
+    "word", "half", "byte", "space", "align", "equ", "set", "incbin", "if", "elseif", "rept",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tok<'s> {
+    Num(i64),
+    Ident(&'s str),
+    // %hi / %lo
+    Func(&'s str),
+    Op(&'s str),
+    Open,
+    Close,
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || matches!(c, '_' | '.' | '$')
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$')
+}
+
+/// length of a `%hi` / `%lo` at the start of s
+fn func(s: &str) -> Option<usize> {
+    let name = s.strip_prefix('%')?;
+    let len = run(name, is_ident);
+    matches!(&name[..len], "hi" | "lo").then_some(len + 1)
+}
+
+/// byte length of the run at the start of s whose chars satisfy f
+fn run(s: &str, f: fn(char) -> bool) -> usize {
+    s.find(|c| !f(c)).unwrap_or(s.len())
+}
+
+fn tokenize(src: &str) -> Res<Vec<Tok<'_>>> {
+    let mut out = Vec::new();
+    let mut rest = src.trim_start_matches('#');
+
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            ' ' | '\t' => 1,
+            '(' => {
+                out.push(Tok::Open);
+                1
+            }
+            ')' => {
+                out.push(Tok::Close);
+                1
+            }
+            '0'..='9' => {
+                let len = run(rest, is_ident);
+                let num = parse_number(&rest[..len]).ok_or_else(|| {
+                    EsiuxErrorKind::Expected(Box::new("a number"), rest[..len].to_string())
+                })?;
+                out.push(Tok::Num(num));
+                len
+            }
+            '%' if func(rest).is_some() => {
+                let len = func(rest).unwrap_or_default();
+                out.push(Tok::Func(&rest[1..len]));
+                len
+            }
+            x if is_ident_start(x) => {
+                let len = run(rest, is_ident);
+                out.push(Tok::Ident(&rest[..len]));
+                len
+            }
+            _ => {
+                let op = [
+                    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "<", ">", "+", "-", "*", "/",
+                    "%", "&", "|", "^", "~", "!",
+                ]
+                .into_iter()
+                .find(|x| rest.starts_with(x))
+                .ok_or_else(|| {
+                    EsiuxErrorKind::Expected(Box::new("an expression"), rest.to_string())
+                })?;
+                out.push(Tok::Op(op));
+                op.len()
+            }
+        };
+        rest = &rest[len..];
+    }
+
+    Ok(out)
+}
+
+/// binding power of a binary operator, C precedence
+fn precedence(op: &str) -> Option<u8> {
+    match op {
+        "||" => Some(1),
+        "&&" => Some(2),
+        "|" => Some(3),
+        "^" => Some(4),
+        "&" => Some(5),
+        "==" | "!=" => Some(6),
+        "<" | "<=" | ">" | ">=" => Some(7),
+        "<<" | ">>" => Some(8),
+        "+" | "-" => Some(9),
+        "*" | "/" | "%" => Some(10),
+        _ => None,
+    }
+}
+
+struct Parser<'s, I: Iterator<Item = Tok<'s>>, F> {
+    tokens: Peekable<I>,
+    resolve: F,
+}
+
+impl<'s, I, F> Parser<'s, I, F>
+where
+    I: Iterator<Item = Tok<'s>>,
+    F: Fn(&str) -> Option<i64>,
+{
+    fn expected(&mut self, what: &str) -> EsiuxErrorKind {
+        let found = match self.tokens.next() {
+            Some(Tok::Num(x)) => x.to_string(),
+            Some(Tok::Ident(x) | Tok::Op(x)) => x.to_string(),
+            Some(Tok::Func(x)) => format!("%{x}"),
+            Some(Tok::Open) => "(".to_string(),
+            Some(Tok::Close) => ")".to_string(),
+            None => "end of expression".to_string(),
+        };
+        EsiuxErrorKind::Expected(Box::new(what.to_string()), found)
+    }
+
+    fn close(&mut self) -> Res<()> {
+        match self.tokens.next() {
+            Some(Tok::Close) => Ok(()),
+            _ => Err(self.expected("')'")),
+        }
+    }
+
+    fn primary(&mut self) -> Res<i64> {
+        match self.tokens.peek().copied() {
+            Some(Tok::Num(x)) => {
+                self.tokens.next();
+                Ok(x)
+            }
+            Some(Tok::Ident(name)) => {
+                self.tokens.next();
+                (self.resolve)(name)
+                    .ok_or_else(|| EsiuxErrorKind::UndefinedSymbol(name.to_string()))
+            }
+            Some(Tok::Open) => {
+                self.tokens.next();
+                let value = self.binary(0)?;
+                self.close()?;
+                Ok(value)
+            }
+            Some(Tok::Func(func @ ("hi" | "lo"))) => {
+                self.tokens.next();
+                if self.tokens.next() != Some(Tok::Open) {
+                    return Err(self.expected("'('"));
+                }
+                let value = self.binary(0)?;
+                self.close()?;
+                Ok(match func {
+                    "hi" => (value >> 16) & 0xffff,
+                    _ => value & 0xffff,
+                })
+            }
+            Some(Tok::Op(op @ ("-" | "~" | "!" | "+"))) => {
+                self.tokens.next();
+                let value = self.primary()?;
+                Ok(match op {
+                    "-" => value.wrapping_neg(),
+                    "~" => !value,
+                    "!" => i64::from(value == 0),
+                    _ => value,
+                })
+            }
+            _ => Err(self.expected("a value")),
+        }
+    }
+
+    fn binary(&mut self, min: u8) -> Res<i64> {
+        let mut lhs = self.primary()?;
+
+        while let Some(Tok::Op(op)) = self.tokens.peek().copied() {
+            let prec = match precedence(op) {
+                Some(prec) if prec > min => prec,
+                _ => break,
+            };
+            self.tokens.next();
+            let rhs = self.binary(prec)?;
+
+            lhs = match op {
+                "||" => i64::from(lhs != 0 || rhs != 0),
+                "&&" => i64::from(lhs != 0 && rhs != 0),
+                "==" => i64::from(lhs == rhs),
+                "!=" => i64::from(lhs != rhs),
+                "<" => i64::from(lhs < rhs),
+                "<=" => i64::from(lhs <= rhs),
+                ">" => i64::from(lhs > rhs),
+                ">=" => i64::from(lhs >= rhs),
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "<<" | ">>" => {
+                    let shift = u32::try_from(rhs)
+                        .ok()
+                        .filter(|x| *x < 64)
+                        .ok_or_else(|| EsiuxErrorKind::TryFrom(Box::new(rhs)))?;
+                    if op == "<<" {
+                        lhs << shift
+                    } else {
+                        lhs >> shift
+                    }
+                }
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err(EsiuxErrorKind::DivisionByZero),
+                "/" => lhs.wrapping_div(rhs),
+                _ => lhs.wrapping_rem(rhs),
+            };
+        }
+
+        Ok(lhs)
+    }
+}
+
+/// # eval
+///
+/// * evaluates a constant expression, a leading `#` is ignored
+/// * numbers as in parse_number, symbols are looked up through resolve
+/// * `|| && | ^ & == != < <= > >= << >> + - * / %` with C precedence, unary `- ~ !`, parentheses
+/// * comparisons and logic operators evaluate to 1 or 0
+/// * `%hi(x)` and `%lo(x)` extract the upper and lower 16 bits of x
+///
+pub fn eval<F>(src: &str, resolve: F) -> Res<i64>
+where
+    F: Fn(&str) -> Option<i64>,
+{
+    let tokens = tokenize(src)?;
+    let mut parser = Parser {
+        tokens: tokens.into_iter().peekable(),
+        resolve,
+    };
+
+    let value = parser.binary(0)?;
+    match parser.tokens.peek() {
+        None => Ok(value),
+        Some(_) => Err(parser.expected("an operator")),
+    }
+}
+
+/// true if the expression is a single number, nothing to evaluate
+pub fn is_number(src: &str) -> bool {
+    parse_number(src).is_some()
+}
+
+/// # substitute
+///
+/// * replaces every symbol that lookup knows with its value
+/// * values that are not a single number or symbol are put in parentheses
+/// * everything else is copied as is
+///
+pub fn substitute<F>(src: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::new();
+    let mut rest = src;
+
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '0'..='9' => run(rest, is_ident),
+            '%' if func(rest).is_some() => func(rest).unwrap_or_default(),
+            x if is_ident_start(x) => {
+                let len = run(rest, is_ident);
+                match lookup(&rest[..len]) {
+                    Some(value) if run(&value, is_ident) == value.len() || is_number(&value) => {
+                        out.push_str(&value)
+                    }
+                    Some(value) => out.push_str(&format!("({value})")),
+                    None => out.push_str(&rest[..len]),
+                }
+                rest = &rest[len..];
+                continue;
+            }
+            x => x.len_utf8(),
+        };
+        out.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+
+    out
+}
+
+impl<'a> Statements<'a> {
+    /// # expressions_mut
+    ///
+    /// * immediates and branch targets of an instruction
+    /// * arguments of a directive in EXPR_DIRECTIVES, except the name of a constant
+    ///   and the file of `.incbin`
+    ///
+    pub fn expressions_mut(&mut self) -> Vec<&mut Symbol<'a>> {
+        match self {
+            Self::DPI { op1, op2, op3, .. } => [Some(op1), Some(op2), op3.as_mut()]
+                .into_iter()
+                .flatten()
+                .filter(|x| matches!(x, Symbol::Literal(_)))
+                .collect(),
+            Self::LSI { op1, op2, op3, .. } => [op1, op2, op3]
+                .into_iter()
+                .filter(|x| matches!(x, Symbol::Literal(_)))
+                .collect(),
+            Self::BRI { label, .. } if !matches!(label, Symbol::Register(_)) => vec![label],
+            Self::SCI { vector, .. } => vec![vector],
+            Self::Directive { name, params, .. } => match name.lexeme().as_ref() {
+                "equ" | "set" | "incbin" => params.iter_mut().skip(1).collect(),
+                x if EXPR_DIRECTIVES.contains(&x) => params.iter_mut().collect(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{eval, substitute};
+
+    fn lookup(name: &str) -> Option<i64> {
+        match name {
+            "BUF_SIZE" => Some(16),
+            "start" => Some(0x40),
+            "end" => Some(0x5c),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expr_one() {
+        assert_eq!(eval("#(BUF_SIZE*4)+1", lookup).unwrap(), 65);
+        assert_eq!(eval("end - start", lookup).unwrap(), 0x1c);
+        assert_eq!(eval("1 + 2 * 3 << 1", lookup).unwrap(), 14);
+        assert_eq!(eval("0xff & ~0x0f | 1 ^ 3", lookup).unwrap(), 0xf2);
+        assert_eq!(eval("-4 % 3", lookup).unwrap(), -1);
+    }
+
+    #[test]
+    fn expr_two() {
+        assert_eq!(eval("%hi(0x12345678)", lookup).unwrap(), 0x1234);
+        assert_eq!(eval("%lo(0x12345678)", lookup).unwrap(), 0x5678);
+        assert_eq!(eval("%lo(end) >> 2", lookup).unwrap(), 0x17);
+    }
+
+    #[test]
+    fn expr_three() {
+        let err = |src| eval(src, lookup).unwrap_err().to_string();
+
+        assert_eq!(err("nowhere + 1"), "Undefined symbol: nowhere");
+        assert_eq!(err("4 / (start - 0x40)"), "Division by zero in expression");
+        assert_eq!(err("(1 + 2"), "Expected ')', found 'end of expression'");
+        assert_eq!(err("1 2"), "Expected an operator, found '2'");
+    }
+
+    #[test]
+    fn expr_five() {
+        assert_eq!(eval("BUF_SIZE >= 16 && end > start", lookup).unwrap(), 1);
+        assert_eq!(eval("1 << 2 == 4 || 0", lookup).unwrap(), 1);
+        assert_eq!(eval("!(start != 0x40) + (1 < 0)", lookup).unwrap(), 1);
+        assert_eq!(eval("3 & 1 == 1", lookup).unwrap(), 1);
+    }
+
+    #[test]
+    fn expr_four() {
+        let lookup = |name: &str| match name {
+            "SIZE" => Some("0x10".to_string()),
+            "TOTAL" => Some("SIZE*2".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(substitute("#SIZE", lookup), "#0x10");
+        assert_eq!(substitute("#TOTAL+1", lookup), "#(SIZE*2)+1");
+        assert_eq!(substitute("%lo(SIZE) + x10", lookup), "%lo(0x10) + x10");
+    }
+}
@@ -0,0 +1,213 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use super::{PreProcessor, Statements};
+
+/// # LocalLabels
+///
+/// * numeric labels like `1:` can be defined any number of times, `1b` refers to the
+///   closest one before the reference and `1f` to the closest one after it
+/// * labels starting with a '.', like `.loop` or `.Lloop`, belong to the global label
+///   before them and are stored as `global.loop`, which also works from other scopes
+/// * a global label defined by a macro expansion does not open a new scope
+/// * a name with a `$` is already stored, numeric labels and pool entries are written that
+///   way by -E, and is left alone
+///
+#[derive(Debug, Default, Clone)]
+pub struct LocalLabels {
+    // the global label scoped labels attach to
+    pub scope: Option<String>,
+    // number of times every numeric label was defined so far
+    pub numeric: HashMap<String, usize>,
+}
+
+fn is_numeric(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|x| x.is_ascii_digit())
+}
+
+/// name a numeric label is stored under, the `$` is only written by the assembler itself
+fn numeric_name(label: &str, count: usize) -> String {
+    format!(".L{label}${count}")
+}
+
+/// # numeric_reference
+///
+/// * the reference an undefined stored name was written as and the direction it looked in
+/// * a `1b` resolves to a defined label or to `.L1$b`, so an undefined `.L1$3` was a `1f`
+///
+pub fn numeric_reference(name: &str) -> Option<(String, String, &'static str)> {
+    let (label, count) = name.strip_prefix(".L")?.split_once('$')?;
+    match count {
+        "b" => Some((format!("{label}b"), label.to_string(), "before")),
+        x if is_numeric(x) => Some((format!("{label}f"), label.to_string(), "after")),
+        _ => None,
+    }
+}
+
+impl LocalLabels {
+    /// # define
+    ///
+    /// * the name a label defined here is stored under, None for a global label
+    /// * a global label becomes the scope of the labels after it, unless in_macro
+    ///
+    pub fn define(&mut self, name: &str, in_macro: bool) -> Option<String> {
+        if is_numeric(name) {
+            let count = self.numeric.entry(name.to_string()).or_default();
+            *count += 1;
+            return Some(numeric_name(name, *count - 1));
+        }
+        if name.contains('$') {
+            return None;
+        }
+        if name.starts_with('.') {
+            return self.scope.as_ref().map(|scope| format!("{scope}{name}"));
+        }
+        if !in_macro {
+            self.scope = Some(name.to_string());
+        }
+        None
+    }
+
+    /// # reference
+    ///
+    /// * the stored name of the label a reference here means, None if it is not local
+    /// * `1b` before any `1:` becomes `.L1$b` which is never defined, see numeric_reference
+    ///
+    pub fn reference(&self, name: &str) -> Option<String> {
+        if let Some(label) = name.strip_suffix(['b', 'f']).filter(|x| is_numeric(x)) {
+            let count = self.numeric.get(label).copied().unwrap_or_default();
+            return match name.ends_with('b') {
+                true => match count.checked_sub(1) {
+                    Some(x) => Some(numeric_name(label, x)),
+                    None => Some(format!(".L{label}$b")),
+                },
+                false => Some(numeric_name(label, count)),
+            };
+        }
+        match (&self.scope, name.starts_with('.') && !name.contains('$')) {
+            (Some(scope), true) => Some(format!("{scope}{name}")),
+            _ => None,
+        }
+    }
+
+    /// replaces every local label an expression refers to with its stored name
+    pub fn rewrite(&self, src: &str) -> String {
+        let mut out = String::new();
+        let mut rest = src;
+
+        while let Some(c) = rest.chars().next() {
+            let word = |x: char| x.is_ascii_alphanumeric() || matches!(x, '_' | '.' | '$');
+            if !word(c) {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            let len = rest.find(|x| !word(x)).unwrap_or(rest.len());
+            match self.reference(&rest[..len]) {
+                Some(name) => out.push_str(&name),
+                None => out.push_str(&rest[..len]),
+            }
+            rest = &rest[len..];
+        }
+
+        out
+    }
+}
+
+impl<'a> PreProcessor<'a> {
+    /// the name a label is stored under in labels, see LocalLabels
+    pub fn label_name(&mut self, name: Cow<'a, str>) -> Cow<'a, str> {
+        match self.locals.define(&name, self.depth > 0) {
+            Some(local) => Cow::Owned(local),
+            None => name,
+        }
+    }
+
+    /// points the local labels an instruction or directive refers to at their definitions
+    pub fn local_labels(&self, mut stmt: Statements<'a>) -> Statements<'a> {
+        for sym in stmt.expressions_mut() {
+            let lexeme = sym.lexeme();
+            let value = self.locals.rewrite(&lexeme);
+            if let Some(token) = sym.token_mut().filter(|_| value != lexeme) {
+                token.lexeme = Cow::Owned(value);
+            }
+        }
+        stmt
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assembly::Assembler, parser::ToNum, processor::Instruction};
+
+    use super::LocalLabels;
+
+    #[test]
+    fn labels_one() {
+        let mut locals = LocalLabels::default();
+
+        assert_eq!(locals.define("1", false).as_deref(), Some(".L1$0"));
+        assert_eq!(locals.rewrite("1b + 1f"), ".L1$0 + .L1$1");
+        assert_eq!(locals.define("1", false).as_deref(), Some(".L1$1"));
+        assert_eq!(locals.rewrite("1b - 0x1f - 2b"), ".L1$1 - 0x1f - .L2$b");
+    }
+
+    #[test]
+    fn labels_two() {
+        let mut locals = LocalLabels::default();
+
+        assert_eq!(locals.define(".loop", false), None);
+        assert_eq!(locals.define("main", false), None);
+        assert_eq!(locals.define(".loop", false).as_deref(), Some("main.loop"));
+        assert_eq!(locals.define("_expanded", true), None);
+        assert_eq!(locals.rewrite(".Lend - main.loop"), "main.Lend - main.loop");
+    }
+
+    #[test]
+    fn labels_three() {
+        let mut asm = Assembler {
+            source: ".macro SPIN \\reg\n1:\n sub \\reg, \\reg, #1\n b 1b\n.endm\n_start:\n1:\n b 1f\n SPIN r2\n b .done\n.done:\n b 1b\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        let bin = asm.assemble().unwrap();
+        let words = bin.data[..20]
+            .chunks(4)
+            .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect::<Vec<_>>();
+        let branch = |offset: i32| {
+            format!("b #{offset}")
+                .parse::<Instruction>()
+                .unwrap()
+                .mask()
+        };
+
+        assert_eq!(words[0], branch(1));
+        assert_eq!(words[2], branch(-1));
+        assert_eq!(words[3], branch(1));
+        assert_eq!(words[4], branch(-3));
+    }
+
+    #[test]
+    fn labels_four() {
+        let mut asm = Assembler {
+            source: "_start:\n b 1f\n b 2b\n2:\n b 2b\n".to_string(),
+            ..Default::default()
+        };
+
+        asm.assemble().unwrap_err();
+        let errors = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Undefined label 1f, there is no numeric label 1: after it @ 2:4",
+                "Undefined label 2b, there is no numeric label 2: before it @ 3:4",
+            ]
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{format::Section, parser::ToNum, Res};
+
+use super::{PreProcessor, Statements};
+
+fn row(line: impl std::fmt::Display, addr: &str, word: &str, src: &str) -> String {
+    format!("{:>5}  {:<8}  {:<8}  {}", line, addr, word, src)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+/// the address and bytes of an instruction or data directive, None for anything else
+fn encoded(pp: &PreProcessor, stmt: &Statements) -> Res<Option<(u32, Vec<u8>)>> {
+    match stmt {
+        x if x.is_instruction() => {
+            let pc = x.instruction().map(|x| x.pc()).unwrap_or_default();
+            Ok(Some((pc, x.lower()?.mask().to_le_bytes().to_vec())))
+        }
+        Statements::Directive { name, .. } if stmt.is_data() => {
+            let bytes = stmt.data(name.pc(), |x| pp.address(x))?;
+            Ok(Some((name.pc(), bytes)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// the rows after the first four bytes of data, a run of rows equal to the one before is one `*`
+fn continued(addr: u32, bytes: &[u8], out: &mut Vec<String>) {
+    let mut chunks = bytes.chunks(4).enumerate();
+    let mut last = chunks.next().map(|(_, x)| x);
+    let mut repeated = false;
+    for (i, chunk) in chunks {
+        if last == Some(chunk) {
+            if !repeated {
+                out.push(row("", "", "*", "").trim_end().to_string());
+                repeated = true;
+            }
+            continue;
+        }
+        (last, repeated) = (Some(chunk), false);
+        let addr = format!("{:08x}", addr + 4 * i as u32);
+        out.push(row("", &addr, &hex(chunk), "").trim_end().to_string());
+    }
+}
+
+/// # listing
+///
+/// * renders every source line next to its address and encoded word
+/// * statements expanded from a substitution macro are indented below the call site, a
+///   macro called by the macro is named along with the chain of calls that led to it
+/// * included files are listed below their `.include` line, headed by their path
+/// * literal pools are indented below the `.ltorg` or branch that placed them, the pool at
+///   the end of the source comes last
+/// * the word column shows four bytes in memory order, instructions included, data longer
+///   than that continues on the rows below it
+/// * lines without code (comments, macro definitions, blanks) are printed as is
+///
+pub fn listing(pp: &PreProcessor) -> Res<String> {
+    let mut out = vec![row("line", "addr", "word", "source")];
+    let mut section = Section::Text;
+
+    list(pp, 0, &pp.intern_buf, &mut section, &mut out)?;
+    out.push(String::new());
+
+    Ok(out.join("\n"))
+}
+
+/// lists the lines of one file along with the statements scanned from it
+fn list(
+    pp: &PreProcessor,
+    file: usize,
+    stmts: &[Statements],
+    section: &mut Section,
+    out: &mut Vec<String>,
+) -> Res<()> {
+    let source = pp.sources.get(file).map(|x| x.source.as_str());
+    let lines = source.unwrap_or_default().lines().collect::<Vec<_>>();
+    let mut next = 1;
+
+    let flush = |out: &mut Vec<String>, next: &mut usize, upto: usize| {
+        while *next < upto && *next <= lines.len() {
+            out.push(row(*next, "", "", lines[*next - 1]));
+            *next += 1;
+        }
+    };
+
+    for stmt in stmts {
+        if let Some(next) = stmt.section() {
+            *section = next;
+        }
+        let base = pp.base(*section);
+        let line = stmt.line();
+        flush(out, &mut next, line);
+
+        let src = if line >= next && line <= lines.len() {
+            next = line + 1;
+            lines[line - 1]
+        } else {
+            ""
+        };
+
+        match stmt {
+            Statements::Expansion { name, values, body } if name.lexeme() == "include" => {
+                out.push(row(line, "", "", src));
+                let path = values
+                    .first()
+                    .map(|x| x.lexeme().trim_matches('"').to_string())
+                    .unwrap_or_default();
+                if let Some(inc) = pp.sources.includes.get(&(file, path)) {
+                    let path = pp.sources.get(*inc).map(|x| x.path.as_str());
+                    out.push(row("", "", "", &format!("; {}", path.unwrap_or_default())));
+                    list(pp, *inc, body, section, out)?;
+                }
+            }
+            Statements::Expansion { name, body, .. } => {
+                // a literal pool is listed below the `.ltorg` or branch that placed it
+                let pool = name.lexeme() == "ltorg";
+                let origin = match (pool, line) {
+                    (true, 0) => {
+                        flush(out, &mut next, lines.len() + 1);
+                        "literal pool".to_string()
+                    }
+                    (true, _) => format!("literal pool @ line {line}"),
+                    (false, _) => format!("{name} @ line {line}"),
+                };
+                if !pool || !src.is_empty() {
+                    out.push(row(line, "", "", src));
+                }
+                expansion(pp, body, &origin, section, out)?;
+            }
+            x if x.is_instruction() || x.is_data() => {
+                let (pc, bytes) = encoded(pp, x)?.unwrap_or_default();
+                let word = hex(&bytes[..bytes.len().min(4)]);
+                out.push(format!(
+                    "{:>5}  {:08x}  {:<8}  {}",
+                    line,
+                    base + pc,
+                    word,
+                    src
+                ));
+                continued(base + pc, &bytes, out);
+            }
+            Statements::Label { name } => {
+                out.push(format!(
+                    "{:>5}  {:08x}  {:<8}  {}",
+                    line,
+                    base + name.pc(),
+                    "",
+                    src
+                ));
+            }
+            Statements::Eof => {}
+            _ if !src.is_empty() => out.push(row(line, "", "", src)),
+            _ => {}
+        }
+    }
+
+    flush(out, &mut next, lines.len() + 1);
+    Ok(())
+}
+
+/// # expansion
+///
+/// * lists the statements of an expansion below its call site, origin names the call
+/// * a macro called by the macro adds its own call to the origin of its rows, so every row
+///   names the innermost macro first, followed by the macros that called it
+///
+fn expansion(
+    pp: &PreProcessor,
+    body: &[Statements],
+    origin: &str,
+    section: &mut Section,
+    out: &mut Vec<String>,
+) -> Res<()> {
+    for inner in body {
+        if let Some(next) = inner.section() {
+            *section = next;
+        }
+        if let Statements::Expansion { name, body, .. } = inner {
+            let origin = match name.lexeme() == "ltorg" {
+                true => format!("literal pool @ line {} in {origin}", inner.line()),
+                false => format!("{name} @ line {} in {origin}", inner.line()),
+            };
+            expansion(pp, body, &origin, section, out)?;
+            continue;
+        }
+        let Some((pc, bytes)) = encoded(pp, inner)? else {
+            continue;
+        };
+        let addr = pp.base(*section) + pc;
+        out.push(format!(
+            "{:>5}  {:08x}  {:<8}    + {:<24}; {}",
+            "",
+            addr,
+            hex(&bytes[..bytes.len().min(4)]),
+            inner.to_string().trim(),
+            origin
+        ));
+        continued(addr, &bytes, out);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assembly::Assembler, parser::ToNum, processor::Instruction};
+
+    use super::hex;
+
+    #[test]
+    fn listing_one() {
+        let mut asm = Assembler {
+            source: ".macro INC \\reg\n add \\reg, \\reg, #1\n.endm\n\n_start:\n\tmov r1, #1\n\tINC r1\n\tsvc #0xf0\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        let listing = asm.listing().unwrap();
+        let lines = listing.lines().collect::<Vec<_>>();
+
+        assert!(lines[5].starts_with("    5  00000000"));
+        assert!(lines[6].starts_with("    6  00000000  "));
+        assert!(lines[6].ends_with("\tmov r1, #1"));
+        assert!(lines[7].ends_with("\tINC r1"));
+        assert!(lines[8].contains("00000004"));
+        assert!(lines[8].contains("+ add"));
+        assert!(lines[8].ends_with("; INC @ line 7"));
+        assert!(lines[9].starts_with("    8  00000008"));
+    }
+
+    #[test]
+    fn listing_two() {
+        let mut asm = Assembler {
+            source: "\tldr r1, =0x12345\n\t.ltorg\n\tldr r2, =0x6789a\n\tb 1f\n1:\n\tldr r3, =0xbcdef\n\tsvc #0xf0\n; done\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        let listing = asm.listing().unwrap();
+        let lines = listing.lines().collect::<Vec<_>>();
+
+        assert!(lines[2].starts_with("    2  "));
+        assert!(lines[2].ends_with("\t.ltorg"));
+        assert!(lines[3].starts_with("       00000004  45230100    + .word"));
+        assert!(lines[3].ends_with("; literal pool @ line 2"));
+        assert!(lines[5].starts_with("    4  0000000c"));
+        assert!(lines[6].starts_with("       00000010  9a780600    + .word"));
+        assert!(lines[6].ends_with("; literal pool @ line 4"));
+        assert!(lines[10].ends_with("; done"));
+        assert!(lines[11].starts_with("       0000001c  efcd0b00    + .word"));
+        assert!(lines[11].ends_with("; literal pool"));
+        assert!(!listing.lines().any(|x| x.starts_with("    0  ")));
+    }
+
+    #[test]
+    fn listing_three() {
+        let mut asm = Assembler {
+            source: ".macro INC \\reg\n add \\reg, \\reg, #1\n.endm\n.macro TWICE \\reg\n INC \\reg\n mov r3, #2\n INC \\reg\n.endm\n_start:\n\tTWICE r1\n\tsvc #0xf0\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        let listing = asm.listing().unwrap();
+        let lines = listing.lines().collect::<Vec<_>>();
+
+        assert!(lines[10].ends_with("\tTWICE r1"));
+        assert!(lines[11].starts_with("       00000000"));
+        assert!(lines[11].ends_with("; INC @ line 5 in TWICE @ line 10"));
+        assert!(lines[12].ends_with("; TWICE @ line 10"));
+        assert!(lines[13].ends_with("; INC @ line 7 in TWICE @ line 10"));
+        assert!(lines[14].starts_with("   11  0000000c"));
+    }
+
+    #[test]
+    fn listing_four() {
+        let mut asm = Assembler {
+            source: "_start:\n\tmov r1, #1\n.data\n\t.ascii \"hello world!!\"\n\t.word 1, 2, 3\n\t.space 32\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        let listing = asm.listing().unwrap();
+        let lines = listing.lines().collect::<Vec<_>>();
+        let mov = "mov r1, #1".parse::<Instruction>().unwrap().mask();
+
+        assert!(lines[2].starts_with(&format!("    2  00000000  {}", hex(&mov.to_le_bytes()))));
+        assert!(lines[4].starts_with("    4  00000004  68656c6c"));
+        assert_eq!(lines[5], "       00000008  6f20776f");
+        assert_eq!(lines[6], "       0000000c  726c6421");
+        assert_eq!(lines[7], "       00000010  21");
+        assert!(lines[8].starts_with("    5  00000011  01000000"));
+        assert_eq!(lines[9], "       00000015  02000000");
+        assert_eq!(lines[10], "       00000019  03000000");
+        assert!(lines[11].starts_with("    6  0000001d  00000000"));
+        assert_eq!(lines[12], "                 *");
+        assert_eq!(lines.len(), 13);
+    }
+}
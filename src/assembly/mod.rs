@@ -1,10 +1,22 @@
 mod asm_macros;
 mod assemble;
+mod conditional;
+mod data;
+mod diagnostic;
+mod expr;
+mod labels;
+mod listing;
+mod pool;
 mod preprocessor;
+mod relocation;
 mod scanner;
+mod source;
 mod statements;
+mod suggest;
 mod symbols;
 
 pub use self::{
-    asm_macros::*, assemble::*, preprocessor::*, scanner::*, statements::*, symbols::*,
+    asm_macros::*, assemble::*, conditional::*, data::*, diagnostic::*, expr::*, labels::*,
+    listing::*, pool::*, preprocessor::*, relocation::*, scanner::*, source::*, statements::*,
+    suggest::*, symbols::*,
 };
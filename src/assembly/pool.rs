@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+
+use crate::{error::EsiuxErrorKind, format::Section, types::l12, Res};
+
+use super::{eval, LabelEntry, PreProcessor, Statements, Symbol, Token};
+
+/// name a pool entry is stored under, the `$` is only written by the assembler itself
+fn literal_name(count: usize) -> String {
+    format!(".Lpool${count}")
+}
+
+/// a symbol naming a pool entry, kept in objects and maps so pools can be told from code
+pub fn is_literal(name: &str) -> bool {
+    name.starts_with(".Lpool$")
+}
+
+/// # Literal
+///
+/// * a value loaded by `ldr rX, =value`, kept in the pool of the section of the ldr
+/// * key is the value with constants folded, so `=16` and `=0x10` share an entry
+/// * a pool waits until it is placed, a value loaded again after that gets a new entry
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Literal {
+    pub key: String,
+    pub value: String,
+    pub label: String,
+    pub section: Section,
+    pub placed: bool,
+}
+
+impl<'a> PreProcessor<'a> {
+    /// # literal
+    ///
+    /// * the label of the pool entry holding value, in the pool of the current section
+    /// * a value already waiting in that pool is shared
+    ///
+    pub fn literal(&mut self, value: &str) -> String {
+        let value = value.trim();
+        let key = match eval(value, |_| None) {
+            Ok(x) => (x as u32).to_string(),
+            Err(_) => value.to_string(),
+        };
+        let waiting = self
+            .literals
+            .iter()
+            .find(|x| !x.placed && x.section == self.section && x.key == key);
+        if let Some(entry) = waiting {
+            return entry.label.clone();
+        }
+
+        let label = literal_name(self.literals.len());
+        self.literals.push(Literal {
+            key,
+            value: value.to_string(),
+            label: label.clone(),
+            section: self.section,
+            placed: false,
+        });
+        label
+    }
+
+    /// # ltorg
+    ///
+    /// * places the waiting pool of the current section here, one word per entry
+    /// * `.ltorg`, an unconditional branch and the end of the source place a pool
+    /// * the pool comes out as an `ltorg` expansion at the line of at, the `.ltorg` or branch
+    ///   that placed it, a pool at the end of the source has no line
+    ///
+    pub fn ltorg(&mut self, at: Option<&Symbol<'a>>) -> Vec<Statements<'a>> {
+        let mut pool = Vec::new();
+        for entry in &mut self.literals {
+            if !entry.placed && entry.section == self.section {
+                entry.placed = true;
+                pool.push((entry.label.clone(), entry.value.clone()));
+            }
+        }
+        if pool.is_empty() {
+            return Vec::new();
+        }
+
+        let directive = |name: &'a str, param: String, pc: u32| {
+            let mut stmt = Statements::Directive {
+                name: Symbol::Directive(Token::from(name)),
+                params: vec![Symbol::Ident(Token::from(param))],
+                body: Vec::new(),
+                marker: None,
+                pc: 0,
+            };
+            stmt.set_pc(pc);
+            stmt
+        };
+
+        // the values were resolved where they were loaded, they do not go through statement again
+        let mut st = Vec::new();
+        if !self.pc.is_multiple_of(4) {
+            st.push(directive("align", "4".to_string(), self.pc));
+            self.pc = self.pc.next_multiple_of(4);
+        }
+        for (label, value) in pool {
+            self.labels.insert(
+                Cow::Owned(label.clone()),
+                LabelEntry {
+                    section: self.section,
+                    offset: self.pc,
+                    span: at.map(|x| x.span()).unwrap_or_default(),
+                },
+            );
+            let mut name = Symbol::Label(Token::from(label));
+            name.set_pc(self.pc);
+            st.push(Statements::Label { name });
+            st.push(directive("word", value, self.pc));
+            self.pc += 4;
+        }
+
+        let mut name = at
+            .cloned()
+            .unwrap_or_else(|| Symbol::Directive(Token::from("ltorg")));
+        if let Some(token) = name.token_mut() {
+            token.lexeme = Cow::Borrowed("ltorg");
+        }
+        vec![Statements::Expansion {
+            name,
+            values: Vec::new(),
+            body: st,
+        }]
+    }
+
+    /// places the pools still waiting at the end of their sections
+    pub fn pools(&mut self) -> Vec<Statements<'a>> {
+        let mut st = self.ltorg(None);
+        for section in Section::LAYOUT {
+            if self
+                .literals
+                .iter()
+                .any(|x| !x.placed && x.section == section)
+            {
+                st.extend(self.statement(Statements::Directive {
+                    name: Symbol::Directive(Token::from("section")),
+                    params: vec![Symbol::Ident(Token::from(section.to_string()))],
+                    body: Vec::new(),
+                    marker: None,
+                    pc: 0,
+                }));
+                st.extend(self.ltorg(None));
+            }
+        }
+        st
+    }
+
+    /// # literal_offset
+    ///
+    /// * distance from the pc an ldr at pc reads to the pool entry called label
+    /// * the pc reads as the address of the next instruction, the pool is always after it
+    ///
+    pub fn literal_offset(&self, label: &str, pc: u32) -> Res<i64> {
+        let entry = self
+            .labels
+            .get(label)
+            .ok_or_else(|| EsiuxErrorKind::UndefinedSymbol(label.to_string()))?;
+        let offset = i64::from(entry.offset) - i64::from(pc) - 4;
+        // the magnitude of a load offset is encoded, see fold
+        let max = l12::UMAX;
+        match (0..=max).contains(&offset) {
+            true => Ok(offset),
+            false => Err(EsiuxErrorKind::PoolRange(offset, self.value(label), max)),
+        }
+    }
+
+    /// the value a pool entry was created for
+    fn value(&self, label: &str) -> String {
+        self.literals
+            .iter()
+            .find(|x| x.label == label)
+            .map(|x| x.value.clone())
+            .unwrap_or_else(|| label.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assembly::Assembler, parser::ToNum, processor::Instruction};
+
+    fn words(source: &str) -> Vec<u32> {
+        let mut asm = Assembler {
+            source: source.to_string(),
+            ..Default::default()
+        };
+        let bin = asm.assemble().unwrap();
+        bin.data[bin.header.entry as usize..]
+            .chunks(4)
+            .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect()
+    }
+
+    fn ins(src: &str) -> u32 {
+        src.parse::<Instruction>().unwrap().mask()
+    }
+
+    #[test]
+    fn pool_one() {
+        let words = words(
+            "_start:\n\tldr r1, =0x12345678\n\tldr r2, =16\n\tldr r3, =0x10\n\tb next\nnext:\n\tldr r4, =0x12345678\n\t.ltorg\n\tldr r5, =_start + 4\n",
+        );
+
+        assert_eq!(
+            words,
+            vec![
+                ins("ldr r1, [pc, #12]"),
+                ins("ldr r2, [pc, #12]"),
+                ins("ldr r3, [pc, #8]"),
+                ins("b #3"),
+                0x12345678,
+                16,
+                ins("ldr r4, [pc, #0]"),
+                0x12345678,
+                ins("ldr r5, [pc, #0]"),
+                4,
+            ]
+        );
+    }
+
+    #[test]
+    fn pool_two() {
+        let mut asm = Assembler {
+            source: "_start:\n\tldr r1, =1\n\t.space 4096\n".to_string(),
+            ..Default::default()
+        };
+
+        asm.assemble().unwrap_err();
+
+        assert_eq!(
+            asm.diagnostics[0].to_string(),
+            "Literal pool is 4096 bytes past the ldr of 1, an ldr reaches 4095 bytes, add a .ltorg closer to it @ 2:10"
+        );
+
+        // a load offset reaches twice as far as an operand
+        let words = words("_start:\n\tldr r1, =1\n\t.space 4092\n");
+        assert_eq!(words[0], ins("ldr r1, [pc, #4092]"));
+        assert_eq!(words[1024], 1);
+    }
+}
@@ -1,28 +1,99 @@
 use std::{borrow::Cow, collections::HashMap};
 
-use crate::{assembly::Scanner, error::EsiuxErrorKind, format::Section, Res};
+use crate::{
+    assembly::Scanner,
+    error::{EsiuxErrorKind, Span},
+    format::{RelocKind, Section},
+    processor::{Condition, Op},
+    types::{l12, l16, l20},
+    Res,
+};
 
-use super::{Function, Macros, Statements, SubMacro, Token};
+use super::{
+    eval, half, is_number, substitute, Conditional, Fixup, Function, Literal, LocalLabels, Macros,
+    SourceMap, Statements, SubMacro, Symbol, Target, Token,
+};
 
 pub const DEFAULT_WHITESPACE: &str = "    ";
+/// how deep substitution macros can call each other
+pub const MACRO_DEPTH: usize = 64;
 
-#[derive(Debug, Default, Clone)]
+/// # LabelEntry
+///
+/// * where a label was defined, the offset is relative to the start of its section
+/// * the address is only known once every section is laid out, see PreProcessor::address
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LabelEntry {
+    pub section: Section,
+    pub offset: u32,
+    // where the label is defined, reported when it is defined again
+    pub span: Span,
+}
+
+#[derive(Debug)]
 pub struct PreProcessor<'a> {
-    pub labels: HashMap<Cow<'a, str>, u32>,
+    pub labels: HashMap<Cow<'a, str>, LabelEntry>,
     pub variables: HashMap<Cow<'a, str>, Cow<'a, str>>,
     pub macros: HashMap<Cow<'a, str>, Macros<'a>>,
     pub pc: u32,
-    pub source: &'a str,
+    pub sources: &'a SourceMap,
+    // files currently being processed, innermost include last
+    pub stack: Vec<usize>,
+    // open conditional blocks, innermost last
+    pub conditionals: Vec<Conditional>,
+    // number of conditional blocks opened outside the current file or macro body
+    pub floor: usize,
+    // substitution macros being expanded right now
+    pub depth: usize,
+    // number of expansions so far, the value of \@
+    pub expansions: usize,
+    pub locals: LocalLabels,
     pub section: Section,
-    pub entry: Option<Token<'a>>,
+    // location counters of the sections that are not current, pc holds the current one
+    pub counters: HashMap<Section, u32>,
+    // start address of every section, filled in by layout
+    pub bases: HashMap<Section, u32>,
+    // symbols exported by .global
+    pub globals: Vec<Token<'a>>,
+    // symbol named by .entry, execution starts there instead of _start
+    pub start: Option<Token<'a>>,
+    // symbols declared by .extern, defined by another object
+    pub externs: Vec<Token<'a>>,
+    // assembling into an object file, values that depend on labels become fixups
+    pub relocatable: bool,
+    pub fixups: Vec<Fixup>,
+    // values loaded by `ldr rX, =value`, every one of them is placed in a literal pool
+    pub literals: Vec<Literal>,
     pub intern_buf: Vec<Statements<'a>>,
+    pub errors: Vec<EsiuxErrorKind>,
 }
 
 impl<'a> PreProcessor<'a> {
-    pub fn new(source: &'a str) -> Self {
+    pub fn new(sources: &'a SourceMap) -> Self {
         let mut pp = Self {
-            source,
-            ..Default::default()
+            labels: HashMap::new(),
+            variables: HashMap::new(),
+            macros: HashMap::new(),
+            pc: 0,
+            sources,
+            stack: Vec::new(),
+            conditionals: Vec::new(),
+            floor: 0,
+            depth: 0,
+            expansions: 0,
+            locals: LocalLabels::default(),
+            section: Section::default(),
+            counters: HashMap::new(),
+            bases: HashMap::new(),
+            globals: Vec::new(),
+            start: None,
+            externs: Vec::new(),
+            relocatable: false,
+            fixups: Vec::new(),
+            literals: Vec::new(),
+            intern_buf: Vec::new(),
+            errors: Vec::new(),
         };
         pp.standard_directives();
         pp
@@ -41,85 +112,404 @@ impl<'a> PreProcessor<'a> {
         self.macros.get(name).cloned()
     }
 
+    pub fn define_variable(&mut self, name: Cow<'a, str>, value: Cow<'a, str>) {
+        self.variables.insert(name, value);
+    }
+
+    /// replaces defined constants inside the expressions of a statement
+    fn substitute_variables(&self, mut stmt: Statements<'a>) -> Statements<'a> {
+        if self.variables.is_empty() {
+            return stmt;
+        }
+
+        for sym in stmt.expressions_mut() {
+            let lexeme = sym.lexeme();
+            let value = substitute(&lexeme, |name| {
+                self.variables.get(name).map(|x| x.to_string())
+            });
+            if let Some(token) = sym.token_mut().filter(|_| value != lexeme) {
+                token.lexeme = Cow::Owned(value);
+            }
+        }
+
+        stmt
+    }
+
+    /// saves the location counter of the current section and continues with section
+    pub fn switch_section(&mut self, section: Section) {
+        self.counters.insert(self.section, self.pc);
+        self.pc = self.counters.get(&section).copied().unwrap_or(0);
+        self.section = section;
+    }
+
+    /// size of a section in bytes
+    pub fn size(&self, section: Section) -> u32 {
+        if section == self.section {
+            self.pc
+        } else {
+            self.counters.get(&section).copied().unwrap_or(0)
+        }
+    }
+
+    /// # layout
+    ///
+    /// * places the sections one after another in Section::LAYOUT order
+    /// * every section starts on a 4 byte boundary
+    ///
+    pub fn layout(&mut self) {
+        let mut base = 0;
+        for section in Section::LAYOUT {
+            self.bases.insert(section, base);
+            base += self.size(section).next_multiple_of(4);
+        }
+    }
+
+    /// start address of a section, valid after layout
+    pub fn base(&self, section: Section) -> u32 {
+        self.bases.get(&section).copied().unwrap_or(0)
+    }
+
+    /// # entry
+    ///
+    /// * the symbol execution starts at, the one named by .entry or an exported `_start`
+    /// * otherwise the exported symbol if there is only one, None if there are several
+    ///
+    pub fn entry(&self) -> Option<&Token<'a>> {
+        self.start
+            .as_ref()
+            .or_else(|| self.globals.iter().find(|x| x.lexeme == "_start"))
+            .or(match self.globals.as_slice() {
+                [only] => Some(only),
+                _ => None,
+            })
+    }
+
+    /// address of a label, valid after layout
+    pub fn address(&self, label: &str) -> Option<u32> {
+        let entry = self.labels.get(label)?;
+        Some(self.base(entry.section) + entry.offset)
+    }
+
+    /// # fold
+    ///
+    /// * evaluates the immediates and branch target of an instruction once every label is placed
+    /// * a branch target becomes a word offset from the branch, section is where it is placed
+    /// * a branch to `#n` is a word offset already, as Instruction and the disassembler read it
+    /// * the `=value` of an ldr becomes the offset of its pool entry from the pc
+    /// * values are range checked against the field they are encoded in
+    /// * a value that fails is replaced by 0 so lowering does not report it again
+    /// * in an object a value that depends on where a label ends up becomes a fixup and
+    ///   is encoded as 0, a branch within its own section does not
+    /// * a movw or movt only relocates the `%lo` or `%hi` of an address
+    ///
+    fn fold(
+        &self,
+        stmt: &mut Statements<'a>,
+        section: Section,
+        fixups: &mut Vec<Fixup>,
+    ) -> Res<()> {
+        let (field, min, max, kind) = match &*stmt {
+            // signed word offset
+            Statements::BRI { .. } => ("l20", l20::MIN, l20::MAX >> 1, Some(RelocKind::Branch)),
+            Statements::SCI { .. } => ("interrupt key", 0, u8::MAX as i64, None),
+            // only `%lo` and `%hi` of an address fit in a movw or movt, see half
+            _ if stmt.is_wide() => ("l16", l16::MIN, l16::MAX, None),
+            // the magnitude of the offset is encoded, the negative bit holds its sign
+            Statements::LSI { .. } => ("offset", -l12::UMAX, l12::UMAX, Some(RelocKind::Imm12)),
+            _ if stmt.is_instruction() => ("l12", l12::MIN, l12::MAX, Some(RelocKind::Imm12)),
+            _ => return Ok(()),
+        };
+        let pc = stmt.instruction().map(|x| x.pc()).unwrap_or_default();
+        let branch = match &*stmt {
+            Statements::BRI { .. } if self.relocatable => Some(pc),
+            Statements::BRI { .. } => Some(self.base(section) + pc),
+            _ => None,
+        };
+
+        let wide = stmt.is_wide();
+
+        for sym in stmt.expressions_mut() {
+            let lexeme = sym.lexeme();
+            let mut kind = kind;
+            let value = match lexeme.strip_prefix('=') {
+                // the pool is in the section of the ldr, the distance to it is known already
+                Some(label) => self.literal_offset(label, pc).map(|x| (None, x)),
+                None if self.relocatable => match half(&lexeme).filter(|_| wide) {
+                    Some((half, expr)) => {
+                        self.relocatable(expr).and_then(|(target, x)| match target {
+                            Some(_) => {
+                                kind = Some(half);
+                                Ok((target, x))
+                            }
+                            None => self.relocatable(&lexeme),
+                        })
+                    }
+                    None => self.relocatable(&lexeme),
+                },
+                None => eval(&lexeme, |name| self.address(name).map(i64::from)).map(|x| (None, x)),
+            };
+            let not_relocatable =
+                || EsiuxErrorKind::NotRelocatable(lexeme.trim_start_matches('#').to_string());
+            // `b #n` is a word offset from the branch, the way Instruction reads it
+            let offset = branch.is_some() && lexeme.starts_with('#');
+            let value = value.and_then(|(target, x)| {
+                // an object is placed by the linker, an absolute branch target is unknown
+                if self.relocatable && branch.is_some() && target.is_none() && !offset {
+                    return Err(not_relocatable());
+                }
+                // the distance to a label in the same section is known already
+                if let Some(target) =
+                    target.filter(|x| branch.is_none() || *x != Target::Section(section))
+                {
+                    let kind = kind.ok_or_else(not_relocatable)?;
+                    fixups.push(Fixup {
+                        section,
+                        offset: pc,
+                        kind,
+                        target,
+                        addend: x,
+                    });
+                    return Ok(0);
+                }
+                let Some(pc) = branch else {
+                    return match (min..=max).contains(&x) {
+                        true => Ok(x),
+                        false => Err(EsiuxErrorKind::ImmRange(x, field.to_string(), min, max)),
+                    };
+                };
+                let distance = match offset {
+                    true => x * 4,
+                    false => x - i64::from(pc),
+                };
+                if distance % 4 != 0 {
+                    return Err(EsiuxErrorKind::BranchAlignment(x));
+                }
+                match (min..=max).contains(&(distance / 4)) {
+                    true => Ok(distance / 4),
+                    false => Err(EsiuxErrorKind::BranchRange(x, distance / 4, min, max)),
+                }
+            });
+
+            let (value, result) = match value {
+                Ok(value) => (value, Ok(())),
+                Err(e) => (0, Err(self.suggest(e).at(sym.span()))),
+            };
+            if branch.is_some() {
+                sym.set_pc(value as u32);
+            } else if let Some(token) = sym.token_mut().filter(|_| !is_number(&lexeme)) {
+                token.lexeme = Cow::Owned(format!("#{value}"));
+            } else if result.is_err() {
+                sym.token_mut()
+                    .into_iter()
+                    .for_each(|x| x.lexeme = Cow::Borrowed("#0"));
+            }
+            result?;
+        }
+
+        Ok(())
+    }
+
     pub fn define_submacro(&mut self, name: Cow<'a, str>, value: SubMacro<'a>) {
         self.macros.insert(name, Macros::Substitution(value));
     }
 
+    /// # handle
+    ///
+    /// * processes the main file and expands directives and macros into intern_buf
+    /// * errors are collected into errors and processing carries on with the next statement
+    /// * once every label is placed the immediates and branch targets are evaluated
+    ///
     pub fn handle(&mut self) -> Res<()> {
-        let mut st = Vec::new();
-        let mut scan = Scanner::new(self.source);
-        for stmt in scan.analyze() {
-            // print!("{}\npc: {}", stmt, self.pc);
-            match stmt.clone() {
-                Statements::DPI { .. }
-                | Statements::LSI { .. }
-                | Statements::SCI { .. }
-                | Statements::BRI { .. } => {
-                    self.pc += 4;
-                    st.push(stmt);
-                }
-                Statements::Comment { .. } => {
-                    st.push(stmt);
+        let mut st = self.process(0);
+        let mut pools = self.pools();
+        st.append(&mut pools);
+        self.layout();
+
+        let mut errors = Vec::new();
+        let mut fixups = Vec::new();
+        let mut section = Section::default();
+        Statements::for_each_mut(&mut st, &mut |x| {
+            if let Some(next) = x.section() {
+                section = next;
+            }
+            if let Err(e) = self.fold(x, section, &mut fixups) {
+                errors.push(e);
+            }
+        });
+        self.errors.append(&mut errors);
+        self.fixups.append(&mut fixups);
+
+        self.intern_buf.extend_from_slice(&st);
+        Ok(())
+    }
+
+    /// # process
+    ///
+    /// * scans a single file of sources and expands its statements
+    /// * `.include` comes back here for the included file
+    ///
+    pub fn process(&mut self, file: usize) -> Vec<Statements<'a>> {
+        let sources = self.sources;
+        let source = sources
+            .get(file)
+            .map(|x| x.source.as_str())
+            .unwrap_or_default();
+
+        let mut scan = Scanner::new(source);
+        scan.file = file;
+        self.stack.push(file);
+        let st = self.conditional_scope(|pp| {
+            let mut st = Vec::new();
+            for stmt in scan.analyze() {
+                st.extend(pp.statement(stmt));
+            }
+            st
+        });
+        self.errors.append(&mut scan.errors);
+        self.stack.pop();
+
+        st
+    }
+
+    /// # statement
+    ///
+    /// * handles a single statement and returns what it expands to
+    /// * statements inside a conditional block that is not taken expand to nothing
+    ///
+    pub fn statement(&mut self, stmt: Statements<'a>) -> Vec<Statements<'a>> {
+        if stmt.is_conditional() {
+            let stmt = self.substitute_variables(stmt);
+            if let Err(e) = self.conditional(&stmt) {
+                self.errors.push(e);
+            }
+            return Vec::new();
+        }
+        if !self.assembling() {
+            return Vec::new();
+        }
+
+        match stmt.clone() {
+            Statements::DPI { .. }
+            | Statements::LSI { .. }
+            | Statements::SCI { .. }
+            | Statements::BRI { .. } => {
+                let mut stmt = self.local_labels(self.substitute_variables(stmt));
+                if let Statements::LSI { op3, .. } = &mut stmt {
+                    let value = op3.lexeme().strip_prefix('=').map(str::to_string);
+                    if let Some(value) = value {
+                        let label = self.literal(&value);
+                        op3.token_mut().into_iter().for_each(|x| {
+                            x.lexeme = Cow::Owned(format!("={label}"));
+                        });
+                    }
                 }
-                Statements::Directive { name, .. } | Statements::Substitution { name, .. } => {
-                    let mac = self.get_macro(name.lexeme().trim_start_matches("."));
-                    let resolved = match mac {
-                        Some(Macros::Directive(func)) => func(self, stmt),
-                        Some(Macros::Substitution(sub)) => {
-                            let SubMacro {
-                                input,
-                                body,
-                                offset,
-                            } = sub;
-                            let val = if let Statements::Substitution { values, .. } = stmt.clone()
-                            {
-                                values
-                            } else {
-                                panic!("This shudnt happend: submacro");
-                            };
-
-                            let mut st_inner = Vec::new();
-                            for stmt in body {
-                                let res = stmt.resolve(input.clone(), val.clone());
-                                st_inner.push(res);
-                            }
+                stmt.set_pc(self.pc);
+                self.pc += 4;
 
-                            self.pc += offset;
-                            Ok(st_inner)
-                        }
-                        _ => {
-                            return Err(EsiuxErrorKind::UnknownDirective(
-                                name.lexeme().to_string(),
-                                name.line(),
-                            ))
-                        }
-                    }?;
-                    st.extend_from_slice(&resolved);
+                // nothing runs into the words placed after an unconditional branch, a call returns
+                let jump = matches!(&stmt, Statements::BRI { instruction, .. }
+                    if matches!(instruction.lexeme().parse::<Op>(), Ok(Op::Branch | Op::Br))
+                        && instruction.lexeme().parse::<Condition>().is_ok_and(|x| x == Condition::Al));
+                let at = stmt.instruction().cloned().filter(|_| jump);
+                let mut st = vec![stmt];
+                if let Some(at) = at {
+                    st.extend(self.ltorg(Some(&at)));
+                }
+                st
+            }
+            Statements::Directive { ref name, .. } | Statements::Substitution { ref name, .. } => {
+                let stmt = self.local_labels(self.substitute_variables(stmt.clone()));
+                let mac = self.get_macro(name.lexeme().trim_start_matches("."));
+                let resolved = match (mac, &stmt) {
+                    (Some(Macros::Directive(func)), _) => func(self, stmt),
+                    (Some(Macros::Substitution(sub)), _) => {
+                        let values = match stmt {
+                            Statements::Substitution { values, .. } => values,
+                            Statements::Directive { params, .. } => params,
+                            _ => unreachable!(),
+                        };
+                        self.expand(name, sub, values)
+                    }
+                    (_, Statements::Substitution { .. }) => Err(
+                        EsiuxErrorKind::UnknownSubstitution(name.lexeme().to_string()),
+                    ),
+                    _ => Err(EsiuxErrorKind::UnknownDirective(name.lexeme().to_string())),
+                };
+                match resolved {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        let e = self.suggest(e);
+                        self.errors.push(e.at(name.span()));
+                        Vec::new()
+                    }
                 }
-                Statements::Label { name } => {
-                    self.labels.insert(name.lexeme(), name.pc());
-                    st.push(stmt);
+            }
+            Statements::Label { mut name } => {
+                name.set_pc(self.pc);
+                let entry = LabelEntry {
+                    section: self.section,
+                    offset: self.pc,
+                    span: name.span(),
+                };
+                let key = self.label_name(name.lexeme());
+                // the first definition stays, so references do not move with the mistake
+                match self.labels.get(&key) {
+                    Some(first) => self.errors.push(
+                        EsiuxErrorKind::DuplicateLabel(key.to_string(), first.span).at(name.span()),
+                    ),
+                    None => {
+                        self.labels.insert(key, entry);
+                    }
                 }
-                Statements::Eof => st.push(stmt),
+                vec![Statements::Label { name }]
+            }
+            Statements::Comment { .. } | Statements::Expansion { .. } | Statements::Eof => {
+                vec![stmt]
             }
-            // println!("new: {}", self.pc);
         }
+    }
+
+    /// # expand
+    ///
+    /// * substitutes the values of a call into the body of a substitution macro
+    /// * `\@` is replaced with a number unique to the expansion, for labels in the body
+    /// * the body goes through statement like any other source, so it can call other macros
+    ///
+    fn expand(
+        &mut self,
+        name: &Symbol<'a>,
+        sub: SubMacro<'a>,
+        values: Vec<Symbol<'a>>,
+    ) -> Res<Vec<Statements<'a>>> {
+        if self.depth >= MACRO_DEPTH {
+            return Err(EsiuxErrorKind::MacroDepth(
+                name.lexeme().to_string(),
+                MACRO_DEPTH,
+            ));
+        }
+        let mut args = sub.bind(name, values.clone())?;
 
-        st.iter_mut().for_each(|x| {
-            if let Statements::BRI { label, .. } = x {
-                let new_pc = self.labels.get(&label.lexeme()).unwrap();
-                let nlabel = super::Symbol::Label(Token {
-                    lexeme: label.lexeme(),
-                    offset: 0,
-                    len: 0,
-                    line: 0,
-                    pc: Some(*new_pc),
-                });
-                *label = nlabel;
+        let mut counter = name.clone();
+        if let Some(token) = counter.token_mut() {
+            token.lexeme = Cow::Owned(self.expansions.to_string());
+        }
+        args.push((Cow::Borrowed("\\@"), vec![counter]));
+        self.expansions += 1;
+
+        self.depth += 1;
+        let body = self.conditional_scope(|pp| {
+            let mut st = Vec::new();
+            for stmt in sub.body {
+                st.extend(pp.statement(stmt.resolve(&args)));
             }
+            st
         });
+        self.depth -= 1;
 
-        self.intern_buf.extend_from_slice(&st);
-        Ok(())
+        Ok(vec![Statements::Expansion {
+            name: name.clone(),
+            values,
+            body,
+        }])
     }
 }
@@ -0,0 +1,152 @@
+use std::cell::RefCell;
+
+use crate::{
+    error::EsiuxErrorKind,
+    format::{RelocKind, Section},
+    Res,
+};
+
+use super::{eval, PreProcessor};
+
+/// how far a target is moved to see how an expression depends on it, the low bits
+/// make sure `%hi`, `%lo` and masks do not hide the move
+const SHIFT: i64 = 0x1_0004;
+
+/// # Target
+///
+/// * what the value of an expression in an object file is relative to
+/// * labels move with their section, externs with the object that defines them
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    Section(Section),
+    Extern(String),
+}
+
+/// # Fixup
+///
+/// * a field of an object the linker patches with the address of target plus addend
+/// * offset is relative to the start of section
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixup {
+    pub section: Section,
+    pub offset: u32,
+    pub kind: RelocKind,
+    pub target: Target,
+    pub addend: i64,
+}
+
+/// # half
+///
+/// * a `%lo(expr)` or `%hi(expr)` spanning a whole operand, and the half of expr it takes
+/// * in an object the linker takes the half once the address expr depends on is known
+///
+pub fn half(operand: &str) -> Option<(RelocKind, &str)> {
+    let operand = operand.trim_start_matches('#').trim();
+    let (kind, rest) = match operand.strip_prefix("%lo(") {
+        Some(rest) => (RelocKind::Lo16, rest),
+        None => (RelocKind::Hi16, operand.strip_prefix("%hi(")?),
+    };
+    let inner = rest.strip_suffix(')')?;
+
+    // `%lo(a) + %lo(b)` is not a single call
+    let mut depth = 0usize;
+    for c in inner.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+    }
+    (depth == 0).then_some((kind, inner))
+}
+
+impl PreProcessor<'_> {
+    /// the target a symbol moves with and its offset from it
+    fn target(&self, name: &str) -> Option<(Target, i64)> {
+        if let Some(entry) = self.labels.get(name) {
+            return Some((Target::Section(entry.section), i64::from(entry.offset)));
+        }
+        self.externs
+            .iter()
+            .any(|x| x.lexeme == name)
+            .then(|| (Target::Extern(name.to_string()), 0))
+    }
+
+    /// # relocatable
+    ///
+    /// * evaluates an expression of an object file into a target plus an addend
+    /// * the expression is evaluated again with each target it uses moved, the result
+    ///   may move along with one target at most, `end - start` moves with none
+    /// * None as the target means the value is absolute
+    ///
+    pub fn relocatable(&self, expr: &str) -> Res<(Option<Target>, i64)> {
+        let targets = RefCell::new(Vec::new());
+        let value = |moved: Option<&Target>| {
+            eval(expr, |name| {
+                let (target, offset) = self.target(name)?;
+                let shift = if moved == Some(&target) { SHIFT } else { 0 };
+                targets.borrow_mut().push(target);
+                Some(offset + shift)
+            })
+        };
+
+        let addend = value(None)?;
+        let mut found = None;
+        for target in targets.take() {
+            if found.as_ref() == Some(&target) {
+                continue;
+            }
+            match value(Some(&target))?.wrapping_sub(addend) {
+                0 => {}
+                SHIFT if found.is_none() => found = Some(target),
+                _ => return Err(EsiuxErrorKind::NotRelocatable(expr.to_string())),
+            }
+        }
+
+        Ok((found, addend))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembly::{PreProcessor, SourceMap},
+        format::{RelocKind, Section},
+    };
+
+    use super::{half, Target};
+
+    #[test]
+    fn relocation_one() {
+        let sources = SourceMap::new(
+            "test.asm",
+            ".extern print\n_start:\n\tb print\nend:\n.data\nbuf:\n.word 0\n".to_string(),
+            Vec::new(),
+        );
+        let mut pp = PreProcessor::new(&sources);
+        pp.relocatable = true;
+        pp.handle().unwrap();
+
+        let text = Some(Target::Section(Section::Text));
+        let data = Some(Target::Section(Section::Data));
+        let print = Some(Target::Extern("print".to_string()));
+
+        assert_eq!(pp.relocatable("end + 8").unwrap(), (text, 12));
+        assert_eq!(pp.relocatable("buf").unwrap(), (data, 0));
+        assert_eq!(pp.relocatable("print - 4").unwrap(), (print, -4));
+        assert_eq!(pp.relocatable("end - _start").unwrap(), (None, 4));
+        assert!(pp.relocatable("end + buf").is_err());
+        assert!(pp.relocatable("%lo(buf)").is_err());
+    }
+
+    #[test]
+    fn relocation_two() {
+        assert_eq!(half("#%lo(buf + 4)"), Some((RelocKind::Lo16, "buf + 4")));
+        assert_eq!(half("%hi((buf))"), Some((RelocKind::Hi16, "(buf)")));
+        assert_eq!(half("#%lo(a) + %lo(b)"), None);
+        assert_eq!(half("#%lo(a) + 1"), None);
+        assert_eq!(half("#buf"), None);
+    }
+}
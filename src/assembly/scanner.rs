@@ -1,11 +1,14 @@
-use core::panic;
-use std::{collections::HashMap, process};
+use std::{borrow::Cow, collections::HashMap};
 
 use eparser::lexer::Lexer;
 
-use crate::processor::{get_all_op, Op};
+use crate::{
+    error::{EsiuxErrorKind, Span},
+    processor::{get_all_op, Op, Register},
+    Res,
+};
 
-use super::{Statements, Symbol, Token};
+use super::{Statements, Symbol, Token, EXPR_DIRECTIVES, REPEATS};
 
 #[derive(Debug)]
 pub struct Scanner<'a> {
@@ -13,6 +16,11 @@ pub struct Scanner<'a> {
     pub(super) source: &'a str,
     pub(super) offset: u32,
     pub(super) map: HashMap<&'a str, u32>,
+    // offset of source inside the file, non zero for macro bodies
+    pub(super) base: usize,
+    // file id of the source in the SourceMap
+    pub(super) file: usize,
+    pub errors: Vec<EsiuxErrorKind>,
 }
 
 impl<'a> Scanner<'a> {
@@ -22,6 +30,9 @@ impl<'a> Scanner<'a> {
             source: content,
             offset: 0,
             map: HashMap::new(),
+            base: 0,
+            file: 0,
+            errors: Vec::new(),
         }
     }
 
@@ -32,25 +43,56 @@ impl<'a> Scanner<'a> {
         &self.source[start..end]
     }
 
-    pub(super) fn token(&self) -> Token<'a> {
-        let start = self.lexer.token_start;
-        let end = self.lexer.pos();
+    fn column(&self, pos: usize) -> usize {
+        let line_start = self.source[..pos].rfind('\n').map_or(0, |x| x + 1);
+        self.source[line_start..pos].chars().count() + 1
+    }
 
-        let str = &self.source[start..end];
-        Token::from_str(str.trim_start_matches("."), start, self.lexer.line, None)
+    fn locate(&self, str: &'a str, pc: Option<u32>) -> Token<'a> {
+        let trimmed = str.trim_start_matches(".");
+        let start = self.lexer.token_start + (str.len() - trimmed.len());
+        self.token_at(trimmed, start, pc)
+    }
+
+    fn token_at(&self, str: &'a str, start: usize, pc: Option<u32>) -> Token<'a> {
+        let mut token = Token::from_str(str, self.base + start, self.lexer.line, pc);
+        token.column = self.column(start);
+        token.file = self.file;
+        token
+    }
+
+    pub(super) fn token(&self) -> Token<'a> {
+        self.locate(self.content(), None)
     }
 
     pub(super) fn pc_token(&self) -> Token<'a> {
+        self.locate(self.content(), Some(self.offset))
+    }
+
+    /// span of the current token, at least one char wide
+    pub(super) fn span(&self) -> Span {
         let start = self.lexer.token_start;
-        let end = self.lexer.pos();
+        Span {
+            file: self.file,
+            line: self.lexer.line,
+            column: self.column(start),
+            len: self.content().chars().count().max(1),
+        }
+    }
 
-        let str = &self.source[start..end];
-        Token::from_str(
-            str.trim_start_matches("."),
-            start,
-            self.lexer.line,
-            Some(self.offset),
-        )
+    /// error for whatever comes next in the source
+    fn unexpected(&mut self, expected: &str) -> EsiuxErrorKind {
+        self.whitespace_noln();
+        self.lexer.reset_ptr();
+        let found = match self.lexer.peek() {
+            None | Some('\n') => "end of line".to_string(),
+            Some(_) => {
+                self.lexer.advance();
+                self.lexer.advance_word();
+                self.content().to_string()
+            }
+        };
+        EsiuxErrorKind::Expected(Box::new(expected.to_string()), found).at(self.span())
     }
 
     pub(super) fn whitespace(&mut self) {
@@ -62,65 +104,283 @@ impl<'a> Scanner<'a> {
         self.lexer.advance_while(|x| matches!(x, ' ' | '\t' | '\r'));
     }
 
-    fn parse_operand(&mut self, branch: bool) -> Symbol<'a> {
+    fn is_end_of_statement(&mut self) -> bool {
+        self.whitespace_noln();
+        matches!(self.lexer.peek(), None | Some('\n') | Some(';'))
+    }
+
+    fn end_of_statement(&mut self) -> Res<()> {
+        if self.is_end_of_statement() {
+            Ok(())
+        } else {
+            Err(self.unexpected("end of statement"))
+        }
+    }
+
+    /// # advance_name
+    ///
+    /// * advances over a word that can have macro parameters in it, like `loop_\@` or `\reg`
+    /// * the parameters are replaced with their values when the macro is expanded
+    ///
+    fn advance_name(&mut self) {
+        loop {
+            self.lexer.advance_word();
+            let mut rest = self.lexer.chars.as_str().chars();
+            match (rest.next(), rest.next()) {
+                (Some('\\'), Some('@')) => {
+                    self.lexer.advance();
+                    self.lexer.advance();
+                }
+                (Some('\\'), Some(x)) if x.is_ascii_alphanumeric() || x == '_' => {
+                    self.lexer.advance();
+                }
+                // the names of numeric labels and pool entries, as -E writes them
+                (Some('$'), _) => {
+                    self.lexer.advance();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// a parameter of a macro definition, `\name`, `\name=default` or `\name:vararg`
+    fn parse_macro_param(&mut self) -> Res<Symbol<'a>> {
         self.whitespace_noln();
         self.lexer.reset_ptr();
-        let char = self.lexer.advance();
+        if self.lexer.eat_char('\\').is_err() {
+            return Err(self.unexpected("a macro parameter"));
+        }
         self.lexer.advance_word();
-        match char {
-            Some('r') if !branch => Symbol::Register(self.token()),
-            Some('#') if !branch => Symbol::Literal(self.token()),
-            Some('\\') if !branch => Symbol::Param(self.token()),
-            Some(_) if branch => Symbol::Label(self.pc_token()),
-            Some('_') => Symbol::Label(self.pc_token()),
-            Some(_) => Symbol::Ident(self.token()),
-            x => {
-                let word = {
-                    self.lexer.advance_word();
-                    self.content()
-                };
-                panic! {
-                "Unexpected char: line {}: '{:?}' {}",
-                self.lexer.line, x, word
+        match self.lexer.peek() {
+            Some('=') => self
+                .lexer
+                .advance_while(|x| !matches!(x, ' ' | '\t' | '\r' | '\n' | ',' | ';')),
+            Some(':') => {
+                self.lexer.advance();
+                self.lexer.advance_word();
+            }
+            _ => {}
+        }
+        Ok(Symbol::Param(self.token()))
+    }
+
+    /// # parse_argument
+    ///
+    /// * an argument of a macro call, `name=value` passes value to the parameter name
+    /// * the name comes out as an Input symbol in front of its value
+    ///
+    fn parse_argument(&mut self, values: &mut Vec<Symbol<'a>>) -> Res<()> {
+        self.whitespace_noln();
+        self.lexer.reset_ptr();
+        let rest = self.lexer.chars.as_str();
+        let name = rest.trim_start_matches('\\');
+        let len = name
+            .find(|x: char| !(x.is_ascii_alphanumeric() || x == '_'))
+            .unwrap_or(name.len());
+        let named = len > 0
+            && name[len..].starts_with('=')
+            && !name[len..].starts_with("==")
+            && !name.starts_with(|x: char| x.is_ascii_digit());
+
+        if named {
+            self.lexer.advance_while(|x| x != '=');
+            values.push(Symbol::Input(self.token()));
+            self.lexer.advance();
+        }
+        values.push(self.parse_operand(false)?);
+        Ok(())
+    }
+
+    /// # advance_repeat
+    ///
+    /// * advances over the body of a `.rept`, `.irp` or `.irpc` and its `.endr`
+    /// * repetitions can nest, every one of them is closed by its own `.endr`
+    ///
+    fn advance_repeat(&mut self) -> bool {
+        let mut depth = 0usize;
+        while !self.lexer.is_eof() {
+            let rest = self.lexer.chars.as_str();
+            let indent = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+            let word = rest[indent..]
+                .split(|x: char| !(x.is_ascii_alphanumeric() || x == '.'))
+                .next()
+                .unwrap_or_default();
+
+            match word.trim_start_matches('.') {
+                x if word.starts_with('.') && REPEATS.contains(&x) => depth += 1,
+                "endr" if word.starts_with('.') && depth == 0 => {
+                    (0..indent + word.len()).for_each(|_| {
+                        self.lexer.advance();
+                    });
+                    return true;
                 }
+                "endr" if word.starts_with('.') => depth -= 1,
+                _ => {}
             }
+            self.lexer.advance_line();
+            self.lexer.advance();
         }
+        false
     }
 
-    fn parse_punctuation(&mut self, char: char) {
-        self.lexer
-            .eat_char(char)
-            .map_err(|_| {
-                println!(
-                    "Expected a punctuation char '{}' @ {}",
-                    char, self.lexer.line
-                )
-            })
-            .unwrap();
+    /// directive and macro arguments can be separated by whitespace or a comma
+    fn separator(&mut self) {
+        self.whitespace_noln();
+        if self.lexer.peek() == Some(',') {
+            self.lexer.advance();
+        }
+    }
+
+    /// advances over an expression, stops at a top level ',' or ']' and at the end of the statement
+    fn scan_expression(&mut self) -> &'a str {
+        let mut depth = 0usize;
+        while let Some(c) = self.lexer.peek() {
+            match c {
+                '\n' | ';' => break,
+                ',' | ']' if depth == 0 => break,
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.lexer.advance();
+        }
+        self.content().trim_end()
+    }
+
+    /// an expression argument of a directive, see EXPR_DIRECTIVES
+    fn parse_expression(&mut self) -> Res<Symbol<'a>> {
+        self.whitespace_noln();
+        self.lexer.reset_ptr();
+        match self.lexer.peek() {
+            None | Some('\n' | ',' | ';' | ']') => return Err(self.unexpected("an expression")),
+            Some('"') => {
+                self.lexer.advance();
+                return self.parse_string();
+            }
+            _ => {}
+        }
+        let expr = self.scan_expression();
+        Ok(Symbol::Ident(self.token_at(
+            expr,
+            self.lexer.token_start,
+            None,
+        )))
+    }
+
+    fn parse_operand(&mut self, branch: bool) -> Res<Symbol<'a>> {
+        self.whitespace_noln();
+        self.lexer.reset_ptr();
+        let char = match self.lexer.peek() {
+            Some(x) if !matches!(x, '\n' | ',' | ';' | ']') => x,
+            _ => return Err(self.unexpected("an operand")),
+        };
+        // immediates and branch targets are expressions
+        if branch || char == '#' {
+            let expr = self.scan_expression();
+            let start = self.lexer.token_start;
+            return Ok(match branch {
+                true => Symbol::Label(self.token_at(expr, start, Some(self.offset))),
+                false => Symbol::Literal(self.token_at(expr, start, None)),
+            });
+        }
+        self.lexer.advance();
+        if char == '"' {
+            return self.parse_string();
+        }
+        if char == '\\' && self.lexer.peek() == Some('@') {
+            self.lexer.advance();
+        }
+        self.advance_name();
+        let sym = match char {
+            'r' => Symbol::Register(self.token()),
+            '\\' => Symbol::Param(self.token()),
+            '_' => Symbol::Label(self.pc_token()),
+            _ => Symbol::Ident(self.token()),
+        };
+        Ok(sym)
     }
 
-    fn parse_instruction(&mut self) -> Statements<'a> {
+    /// an operand that has to be a register, `lr` and `sp` scan as names
+    fn parse_register(&mut self) -> Res<Symbol<'a>> {
+        match self.parse_operand(false)? {
+            Symbol::Register(token) | Symbol::Ident(token)
+                if token.lexeme.parse::<Register>().is_ok() =>
+            {
+                Ok(Symbol::Register(token))
+            }
+            x => Err(
+                EsiuxErrorKind::Expected(Box::new("a register"), x.lexeme().to_string())
+                    .at(x.span()),
+            ),
+        }
+    }
+
+    /// `ret` is `br lr`, a condition carries over from `ret.eq`
+    fn parse_ret(&mut self) -> Res<Statements<'a>> {
+        let mut token = self.token();
+        token.lexeme = Cow::Owned(format!("br{}", &self.content()[3..]));
+        self.offset += 4;
+        self.end_of_statement()?;
+        Ok(Statements::BRI {
+            instruction: Symbol::Instruction(token),
+            label: Symbol::Register(Token::from("lr")),
+        })
+    }
+
+    /// scans the rest of a string literal, the opening quote is already consumed
+    fn parse_string(&mut self) -> Res<Symbol<'a>> {
+        loop {
+            match self.lexer.peek() {
+                Some('"') => {
+                    self.lexer.advance();
+                    return Ok(Symbol::Str(self.token()));
+                }
+                None | Some('\n') => {
+                    return Err(EsiuxErrorKind::Expected(
+                        Box::new("a closing '\"'"),
+                        "end of line".to_string(),
+                    )
+                    .at(self.span()))
+                }
+                Some('\\') => {
+                    self.lexer.advance();
+                    if self.lexer.peek().is_some_and(|x| x != '\n') {
+                        self.lexer.advance();
+                    }
+                }
+                Some(_) => {
+                    self.lexer.advance();
+                }
+            }
+        }
+    }
+
+    fn parse_punctuation(&mut self, char: char) -> Res<()> {
+        self.whitespace_noln();
+        if self.lexer.eat_char(char).is_err() {
+            return Err(self.unexpected(&format!("'{char}'")));
+        }
+        Ok(())
+    }
+
+    fn parse_instruction(&mut self) -> Res<Statements<'a>> {
         let token = self.content();
+        let instruction = Symbol::Instruction(self.token());
         let op = token
             .parse::<Op>()
-            .map_err(|_| println!("Unrecognized instruction: {} @ {}", token, self.lexer.line))
-            .unwrap();
+            .map_err(|_| EsiuxErrorKind::UnknownInstruction(token.to_string()).at(self.span()))?;
         let masked = ((op as u8) >> 4) & 0b111;
 
-        let instruction = Symbol::Instruction(self.token());
-        if self.lexer.eat_char(' ').is_err() {
-            self.lexer
-                .eat_char('\t')
-                .map_err(|_| println!("Expected a whitespace char ' ', '\t' @ {}", self.lexer.line))
-                .unwrap();
+        if self.lexer.eat_char(' ').is_err() && self.lexer.eat_char('\t').is_err() {
+            return Err(self.unexpected("whitespace after the instruction"));
         }
 
         self.offset += 4;
-        match masked {
+        let stmt = match masked {
             1 => {
-                let op1 = self.parse_operand(false);
-                self.parse_punctuation(',');
-                let op2 = self.parse_operand(false);
+                let op1 = self.parse_operand(false)?;
+                self.parse_punctuation(',')?;
+                let op2 = self.parse_operand(false)?;
                 if op == Op::Cmp || op == Op::Mov {
                     Statements::DPI {
                         instruction,
@@ -129,8 +389,8 @@ impl<'a> Scanner<'a> {
                         op3: None,
                     }
                 } else {
-                    self.parse_punctuation(',');
-                    let op3 = self.parse_operand(false);
+                    self.parse_punctuation(',')?;
+                    let op3 = self.parse_operand(false)?;
                     Statements::DPI {
                         instruction,
                         op1,
@@ -139,33 +399,79 @@ impl<'a> Scanner<'a> {
                     }
                 }
             }
-            3 => {
-                let op1 = self.parse_operand(false);
-                self.whitespace_noln();
-                self.lexer.reset_ptr();
-                let obracket = matches!(self.lexer.peek(), Some('['));
-
-                let op2 = self.parse_operand(false);
-                let mut cbracket = false;
-                match self.lexer.peek() {
-                    Some(',') => self.parse_punctuation(','),
-                    Some(']') => cbracket = true,
-                    _ => cbracket = false,
-                }
-
-                let op3 = self.parse_operand(false);
-
-                Statements::LSI {
+            // wide immediates share the two operand statement of mov
+            2 => {
+                let op1 = self.parse_operand(false)?;
+                self.parse_punctuation(',')?;
+                let op2 = self.parse_operand(false)?;
+                Statements::DPI {
                     instruction,
                     op1,
-                    obracket,
                     op2,
-                    cbracket,
-                    op3,
+                    op3: None,
+                }
+            }
+            3 | 6 => {
+                let op1 = self.parse_operand(false)?;
+                self.parse_punctuation(',')?;
+                self.whitespace_noln();
+                self.lexer.reset_ptr();
+
+                // `ldr rX, =value` loads value from a literal pool, relative to the pc
+                if op == Op::Ldr && self.lexer.peek() == Some('=') {
+                    let start = self.lexer.token_start;
+                    let expr = self.scan_expression();
+                    if expr.len() == 1 {
+                        return Err(self.unexpected("an expression"));
+                    }
+                    Statements::LSI {
+                        instruction,
+                        op1,
+                        obracket: true,
+                        op2: Symbol::Register(Token::from("pc")),
+                        cbracket: false,
+                        writeback: false,
+                        op3: Symbol::Literal(self.token_at(expr, start, None)),
+                    }
+                } else {
+                    self.parse_punctuation('[')?;
+                    let op2 = self.parse_operand(false)?;
+                    self.whitespace_noln();
+                    let mut cbracket = self.lexer.eat_char(']').is_ok();
+
+                    let mut writeback = false;
+                    let op3 = if cbracket && self.is_end_of_statement() {
+                        // `[rn]` is `[rn, #0]`
+                        cbracket = false;
+                        Symbol::Literal(Token::from("#0"))
+                    } else {
+                        self.parse_punctuation(',')?;
+                        let op3 = self.parse_operand(false)?;
+                        if !cbracket {
+                            self.parse_punctuation(']')?;
+                            // `[rn, off]!` writes the address back to rn
+                            writeback = self.lexer.eat_char('!').is_ok();
+                        }
+                        op3
+                    };
+
+                    Statements::LSI {
+                        instruction,
+                        op1,
+                        obracket: true,
+                        op2,
+                        cbracket,
+                        writeback,
+                        op3,
+                    }
                 }
             }
             5 => {
-                let op1 = self.parse_operand(true);
+                // `br` and `blr` jump to the address in a register
+                let op1 = match op.is_indirect() {
+                    true => self.parse_register()?,
+                    false => self.parse_operand(true)?,
+                };
 
                 Statements::BRI {
                     instruction,
@@ -173,18 +479,22 @@ impl<'a> Scanner<'a> {
                 }
             }
             7 => {
-                let op1 = self.parse_operand(false);
+                let op1 = self.parse_operand(false)?;
 
                 Statements::SCI {
                     instruction,
                     vector: op1,
                 }
             }
-            _ => panic!(
-                "Unknown instruction type: {token} op: 0x{:02x} @ {}",
-                op as u8, self.lexer.line
-            ),
-        }
+            _ => {
+                return Err(
+                    EsiuxErrorKind::UnknownInstruction(token.to_string()).at(instruction.span())
+                )
+            }
+        };
+
+        self.end_of_statement()?;
+        Ok(stmt)
     }
 
     fn parse_comment(&mut self) -> Statements<'a> {
@@ -197,23 +507,34 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn parse_label(&mut self) -> Statements<'a> {
+    /// true if the word right after the current position is followed by a ':'
+    fn is_label(&self) -> bool {
+        let rest = self.lexer.chars.as_str();
+        let len = rest
+            .find(|x: char| {
+                !(x.is_ascii_alphanumeric() || matches!(x, '_' | '.' | '\\' | '@' | '$'))
+            })
+            .unwrap_or(rest.len());
+        len > 0 && rest[len..].starts_with(':')
+    }
+
+    fn parse_label(&mut self) -> Res<Statements<'a>> {
         let token = {
-            self.lexer.advance_word();
-            self.pc_token()
+            self.advance_name();
+            // unlike directives the leading '.' of a scoped label is part of its name
+            self.token_at(self.content(), self.lexer.token_start, Some(self.offset))
         };
         let tok = self.content();
-        if let Err(e) = self.lexer.eat_char(':') {
-            println!("{e}");
-            process::exit(-1);
+        if self.lexer.eat_char(':').is_err() {
+            return Err(self.unexpected("':' after the label"));
         };
         self.map.insert(tok, self.offset);
-        Statements::Label {
+        Ok(Statements::Label {
             name: Symbol::Label(token),
-        }
+        })
     }
 
-    fn parse_directive(&mut self) -> Statements<'a> {
+    fn parse_directive(&mut self) -> Res<Statements<'a>> {
         let mut params = Vec::new();
         let mut body = Vec::new();
         let mut pc = 0;
@@ -233,6 +554,9 @@ impl<'a> Scanner<'a> {
                 self.lexer.advance_word();
                 self.content()
             };
+            if macro_name.is_empty() {
+                return Err(self.unexpected("a macro name"));
+            }
             let macro_name_sym = Symbol::Ident(self.token());
             params.push(macro_name_sym);
             self.whitespace_noln();
@@ -242,20 +566,46 @@ impl<'a> Scanner<'a> {
             None
         };
 
-        while !self.lexer.is_eof() && self.lexer.peek().map(|x| x != '\n').unwrap_or_default() {
-            let op = self.parse_operand(false);
+        let expressions = EXPR_DIRECTIVES.contains(&directive.trim_start_matches("."));
+        while !self.is_end_of_statement() {
+            let op = match (in_macro, expressions) {
+                (true, _) => self.parse_macro_param()?,
+                (_, true) => self.parse_expression()?,
+                _ => self.parse_operand(false)?,
+            };
             params.push(op);
+            self.separator();
         }
 
         self.whitespace_noln();
         self.lexer.reset_ptr();
-        if in_macro {
+        let end = match directive.trim_start_matches(".") {
+            "macro" => Some(".endm"),
+            x if REPEATS.contains(&x) => Some(".endr"),
+            _ => None,
+        };
+        if let Some(end) = end {
+            let line = self.lexer.line;
+            let start = self.lexer.token_start;
             let slice = {
-                self.lexer.advance_untill(".endm");
-                let end = self.lexer.pos();
-                self.source[self.lexer.token_start..end].trim_end_matches(".endm")
+                let found = match in_macro {
+                    true => self.lexer.advance_untill(end),
+                    false => self.advance_repeat(),
+                };
+                if !found {
+                    return Err(EsiuxErrorKind::Unterminated(
+                        directive.trim_start_matches(".").to_string(),
+                        end.trim_start_matches(".").to_string(),
+                    )
+                    .at(directive_token.span()));
+                }
+                let end_pos = self.lexer.pos();
+                self.source[start..end_pos].trim_end_matches(end)
             };
             let mut inner_scanner = Self::new(slice);
+            inner_scanner.lexer.line = line;
+            inner_scanner.base = self.base + start;
+            inner_scanner.file = self.file;
             for stmt in inner_scanner.analyze() {
                 match &stmt {
                     Statements::DPI { .. }
@@ -268,70 +618,83 @@ impl<'a> Scanner<'a> {
                     _ => body.push(stmt),
                 }
             }
+            self.errors.append(&mut inner_scanner.errors);
         }
-        let marker = if in_macro {
-            Some(Symbol::Marker(Token::from(".endm")))
-        } else {
-            None
-        };
+        let marker = end.map(|x| Symbol::Marker(Token::from(x)));
 
-        if in_macro {
-            self.map.insert(mac_name.unwrap(), pc);
+        if let Some(mac_name) = mac_name {
+            self.map.insert(mac_name, pc);
         }
 
-        Statements::Directive {
+        Ok(Statements::Directive {
             name: Symbol::Ident(directive_token),
             params,
             body,
             pc,
             marker,
-        }
+        })
     }
 
-    fn parse_substitution(&mut self) -> Statements<'a> {
+    fn parse_substitution(&mut self) -> Res<Statements<'a>> {
         let token = {
             self.lexer.advance_word();
             self.token()
         };
         let mac_name = self.content();
         let mut values = Vec::new();
-        while !self.lexer.is_eof() && self.lexer.peek().map(|x| x != '\n').unwrap_or_default() {
-            let op = self.parse_operand(false);
-            values.push(op);
+        while !self.is_end_of_statement() {
+            self.parse_argument(&mut values)?;
+            self.separator();
         }
 
-        let pc = self.map.get(mac_name);
-        self.offset += pc.unwrap();
+        // unknown macros are reported by the preprocessor
+        if let Some(pc) = self.map.get(mac_name) {
+            self.offset += pc;
+        }
 
-        Statements::Substitution {
+        Ok(Statements::Substitution {
             name: Symbol::Ident(token),
             values,
-        }
+        })
     }
 
-    pub(super) fn parse(&mut self) -> Statements<'a> {
+    pub(super) fn parse(&mut self) -> Res<Statements<'a>> {
         self.whitespace();
         self.lexer.reset_ptr();
 
         let c = if let Some(char) = self.lexer.advance() {
             char
         } else {
-            return Statements::Eof;
+            return Ok(Statements::Eof);
         };
 
         match c {
-            ';' => self.parse_comment(),
+            ';' => Ok(self.parse_comment()),
+            // `.loop:` is a scoped label, not a directive
+            '.' if self.is_label() => self.parse_label(),
             '.' => self.parse_directive(),
+            x if !(x.is_ascii_alphanumeric() || x == '_') => Err(EsiuxErrorKind::Expected(
+                Box::new("a statement"),
+                x.to_string(),
+            )
+            .at(self.span())),
             _ => {
-                self.lexer.advance_word();
+                self.advance_name();
                 let kw = get_all_op();
                 let word = self.content();
                 // TODO: handle if moveq instructions
-                let s = word.split_once(".").unwrap_or((word, "")).0;
+                let s = word.split_once(".").unwrap_or((word, "")).0.to_lowercase();
+                let is_op = |x: &str| kw.contains(format!("_{x}_").as_str());
+                // `adds` is add setting the flags
+                let sets_flags = s.strip_suffix('s').is_some_and(|x| {
+                    is_op(x) && x.parse::<Op>().is_ok_and(|op| op.can_set_flags())
+                });
                 // println!("{word}");
-                if kw.contains(format!("_{}_", s).to_lowercase().as_str()) {
+                if is_op(&s) || sets_flags {
                     // println!("i");
                     self.parse_instruction()
+                } else if s == "ret" {
+                    self.parse_ret()
                 } else if word.starts_with("_")
                     || self.lexer.peek().map(|x| x == ':').unwrap_or_default()
                 {
@@ -345,13 +708,21 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    /// # analyze
+    ///
+    /// * yields the statements of the source in order
+    /// * a statement that fails to parse is recorded in errors
+    /// * scanning recovers at the start of the next line
+    ///
     pub fn analyze(&mut self) -> impl Iterator<Item = Statements<'a>> + '_ {
-        std::iter::from_fn(move || {
-            let stmt = self.parse();
-            if stmt != Statements::Eof {
-                Some(stmt)
-            } else {
-                None
+        std::iter::from_fn(move || loop {
+            match self.parse() {
+                Ok(Statements::Eof) => return None,
+                Ok(stmt) => return Some(stmt),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.lexer.advance_line();
+                }
             }
         })
     }
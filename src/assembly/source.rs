@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::Res;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub path: String,
+    pub source: String,
+}
+
+/// # SourceMap
+///
+/// * every file taking part in an assembly, file 0 is the main file
+/// * token and span file ids index into files
+/// * included files are loaded up front so statements can borrow from all of them
+///
+#[derive(Debug, Default, Clone)]
+pub struct SourceMap {
+    pub files: Vec<SourceFile>,
+    // -I directories, searched after the directory of the including file
+    pub search: Vec<PathBuf>,
+    // (including file, name as written) -> included file
+    pub includes: HashMap<(usize, String), usize>,
+}
+
+/// names of the files a source includes, in order
+fn include_names(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let line = line.split(';').next()?.trim();
+            let rest = line.strip_prefix(".include")?;
+            rest.starts_with([' ', '\t'])
+                .then(|| rest.trim().trim_matches('"').to_string())
+        })
+        .collect()
+}
+
+impl SourceMap {
+    pub fn new(path: &str, source: String, search: Vec<PathBuf>) -> Self {
+        Self {
+            files: vec![SourceFile {
+                path: path.to_string(),
+                source,
+            }],
+            search,
+            includes: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, file: usize) -> Option<&SourceFile> {
+        self.files.get(file)
+    }
+
+    /// # find
+    ///
+    /// * looks for name next to the file that mentions it, then in every search directory
+    /// * returns the first path that exists
+    ///
+    pub fn find(&self, from: usize, name: &str) -> Option<PathBuf> {
+        let dir = self
+            .get(from)
+            .and_then(|x| Path::new(&x.path).parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        std::iter::once(dir)
+            .chain(self.search.iter().cloned())
+            .map(|x| x.join(name))
+            .find(|x| x.is_file())
+    }
+
+    /// # load_includes
+    ///
+    /// * follows every `.include` of the loaded files and reads the files they name
+    /// * a file is only loaded once, so cycles end here and are reported by the preprocessor
+    /// * names that can not be found are left out, the preprocessor reports them once reached
+    ///
+    pub fn load_includes(&mut self) -> Res<()> {
+        let mut loaded = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(id, x)| Some((fs::canonicalize(&x.path).ok()?, id)))
+            .collect::<HashMap<_, _>>();
+
+        let mut next = 0;
+        while next < self.files.len() {
+            for name in include_names(&self.files[next].source) {
+                let Some(path) = self.find(next, &name) else {
+                    continue;
+                };
+                let canonical = fs::canonicalize(&path)?;
+                let id = match loaded.get(&canonical) {
+                    Some(id) => *id,
+                    None => {
+                        self.files.push(SourceFile {
+                            path: path.to_string_lossy().to_string(),
+                            source: fs::read_to_string(&path)?,
+                        });
+                        loaded.insert(canonical, self.files.len() - 1);
+                        self.files.len() - 1
+                    }
+                };
+                self.includes.insert((next, name), id);
+            }
+            next += 1;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::include_names;
+
+    #[test]
+    fn source_one() {
+        let source = ".include \"a.s\"\n  .include\tb.s ; lib\n; .include \"c.s\"\n.includes d\n";
+
+        assert_eq!(include_names(source), vec!["a.s", "b.s"]);
+    }
+}
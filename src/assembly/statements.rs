@@ -1,7 +1,13 @@
-use crate::assembly::DEFAULT_WHITESPACE;
+use crate::{
+    assembly::DEFAULT_WHITESPACE,
+    error::EsiuxErrorKind,
+    processor::{Instruction, Op, Register},
+    types::{l12, l16},
+    Res,
+};
 
-use super::Symbol;
-use std::fmt;
+use super::{Symbol, EXPR_DIRECTIVES};
+use std::{borrow::Cow, fmt};
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Statements<'a> {
@@ -11,12 +17,14 @@ pub enum Statements<'a> {
         op2: Symbol<'a>,
         op3: Option<Symbol<'a>>,
     },
+    // `[rn, off]`, `[rn, off]!` when writeback is set and `[rn], off` when cbracket is set
     LSI {
         instruction: Symbol<'a>,
         op1: Symbol<'a>,
         obracket: bool,
         op2: Symbol<'a>,
         cbracket: bool,
+        writeback: bool,
         op3: Symbol<'a>,
     },
     BRI {
@@ -38,6 +46,12 @@ pub enum Statements<'a> {
         name: Symbol<'a>,
         values: Vec<Symbol<'a>>,
     },
+    // substitution macro call site along with the statements it expanded to
+    Expansion {
+        name: Symbol<'a>,
+        values: Vec<Symbol<'a>>,
+        body: Vec<Statements<'a>>,
+    },
     Label {
         name: Symbol<'a>,
     },
@@ -49,27 +63,239 @@ pub enum Statements<'a> {
 }
 
 impl<'a> Statements<'a> {
-    pub fn resolve(&self, params: Vec<Symbol<'a>>, values: Vec<Symbol<'a>>) -> Self {
-        assert_eq!(params.len(), values.len());
+    pub fn is_instruction(&self) -> bool {
+        matches!(
+            self,
+            Self::DPI { .. } | Self::LSI { .. } | Self::BRI { .. } | Self::SCI { .. }
+        )
+    }
+
+    /// the instruction symbol of an instruction statement
+    pub fn instruction(&self) -> Option<&Symbol<'a>> {
+        match self {
+            Self::DPI { instruction, .. }
+            | Self::LSI { instruction, .. }
+            | Self::BRI { instruction, .. }
+            | Self::SCI { instruction, .. } => Some(instruction),
+            _ => None,
+        }
+    }
+
+    /// movw and movt, their immediate is 16 bits wide
+    pub fn is_wide(&self) -> bool {
+        self.instruction()
+            .and_then(|x| x.lexeme().parse::<Op>().ok())
+            .is_some_and(|x| matches!(x, Op::Movw | Op::Movt))
+    }
+
+    /// stamps the offset of an instruction or data statement onto its first token
+    pub fn set_pc(&mut self, pc: u32) {
+        match self {
+            Self::DPI { instruction, .. }
+            | Self::LSI { instruction, .. }
+            | Self::BRI { instruction, .. }
+            | Self::SCI { instruction, .. } => instruction.set_pc(pc),
+            Self::Directive { name, .. } => name.set_pc(pc),
+            _ => {}
+        }
+    }
+
+    /// source line the statement starts on
+    pub fn line(&self) -> usize {
+        match self {
+            Self::DPI { instruction, .. }
+            | Self::LSI { instruction, .. }
+            | Self::BRI { instruction, .. }
+            | Self::SCI { instruction, .. } => instruction.line(),
+            Self::Directive { name, .. }
+            | Self::Substitution { name, .. }
+            | Self::Expansion { name, .. }
+            | Self::Label { name }
+            | Self::Comment { name } => name.line(),
+            Self::Eof => 0,
+        }
+    }
+
+    /// # flatten
+    ///
+    /// * walks the statements in order
+    /// * descends into the bodies of macro expansions
+    ///
+    pub fn flatten(stmts: &[Self]) -> Vec<&Self> {
+        let mut out = Vec::new();
+        for stmt in stmts {
+            match stmt {
+                Self::Expansion { body, .. } => out.extend(Self::flatten(body)),
+                x => out.push(x),
+            }
+        }
+        out
+    }
+
+    /// mutable counterpart of flatten
+    pub fn for_each_mut<F>(stmts: &mut [Self], f: &mut F)
+    where
+        F: FnMut(&mut Self),
+    {
+        for stmt in stmts {
+            match stmt {
+                Self::Expansion { body, .. } => Self::for_each_mut(body, f),
+                x => f(x),
+            }
+        }
+    }
+
+    /// # lower
+    ///
+    /// * lowers an instruction statement into an Instruction
+    /// * builds the canonical text form and hands it to the Codable parser
+    /// * branch labels need to be resolved beforehand, their pc is used as the offset
+    ///
+    pub fn lower(&self) -> Res<Instruction> {
+        for op in self.operands() {
+            match op {
+                Symbol::Register(_) => {
+                    op.lexeme()
+                        .parse::<Register>()
+                        .map_err(|e| e.at(op.span()))?;
+                }
+                Symbol::Literal(_) if self.is_wide() => {
+                    op.lexeme()[1..]
+                        .parse::<l16>()
+                        .map_err(|e| e.at(op.span()))?;
+                }
+                Symbol::Literal(_) => {
+                    op.lexeme()[1..]
+                        .parse::<l12>()
+                        .map_err(|e| e.at(op.span()))?;
+                }
+                _ => {}
+            }
+        }
+
+        let src = match self {
+            Self::DPI {
+                instruction,
+                op1,
+                op2,
+                op3: Some(op3),
+            } => format!("{instruction} {op1}, {op2}, {op3}"),
+            Self::DPI {
+                instruction,
+                op1,
+                op2,
+                op3: None,
+            } => format!("{instruction} {op1}, {op2}"),
+            Self::LSI {
+                instruction,
+                op1,
+                op2,
+                cbracket,
+                writeback,
+                op3,
+                ..
+            } => {
+                let rn = op2.lexeme();
+                let rn = rn.trim_matches(|c| c == '[' || c == ']');
+                let bang = if *writeback { "!" } else { "" };
+                if *cbracket {
+                    format!("{instruction} {op1}, [{rn}], {op3}")
+                } else {
+                    format!("{instruction} {op1}, [{rn}, {op3}]{bang}")
+                }
+            }
+            Self::BRI {
+                instruction,
+                label: label @ Symbol::Register(_),
+            } => format!("{instruction} {label}"),
+            Self::BRI { instruction, label } => format!("{instruction} #{}", label.pc() as i32),
+            Self::SCI {
+                instruction,
+                vector,
+            } => format!("{instruction} {vector}"),
+            x => return Err(EsiuxErrorKind::NotAnInstruction(Box::new(x.to_string()))),
+        };
+
+        let span = self.instruction().map(|x| x.span()).unwrap_or_default();
+        src.parse::<Instruction>().map_err(|e| e.at(span))
+    }
+
+    /// operands of an instruction statement in source order
+    pub fn operands(&self) -> Vec<&Symbol<'a>> {
+        match self {
+            Self::DPI { op1, op2, op3, .. } => {
+                let mut ops = vec![op1, op2];
+                ops.extend(op3.as_ref());
+                ops
+            }
+            Self::LSI { op1, op2, op3, .. } => vec![op1, op2, op3],
+            Self::BRI { label, .. } => vec![label],
+            Self::SCI { vector, .. } => vec![vector],
+            _ => Vec::new(),
+        }
+    }
+
+    /// # resolve
+    ///
+    /// * replaces the parameters of a substitution macro in one statement of its body
+    /// * args binds every parameter, like `\reg`, to its values, a `:vararg` tail binds several
+    /// * an operand that is only a parameter becomes its value, registers stay registers
+    /// * parameters inside a longer operand, like `loop_\@` or `#\n + 1`, are replaced as text
+    /// * arguments of directives and macro calls that are only a parameter take all its values
+    ///
+    pub fn resolve(&self, args: &[(Cow<'a, str>, Vec<Symbol<'a>>)]) -> Self {
+        fn joined(values: &[Symbol]) -> String {
+            values
+                .iter()
+                .map(|x| x.lexeme())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
 
         fn resolve_field<'a>(
             sym: &Symbol<'a>,
-            replacement: &[(Symbol<'a>, Symbol<'a>)],
+            args: &[(Cow<'a, str>, Vec<Symbol<'a>>)],
         ) -> Symbol<'a> {
-            for (param, value) in replacement {
-                // println!("param: {param:#?}\nval: {value:#?}\nsym: {sym:#?}");
-                if sym.lexeme() == param.lexeme() {
-                    return value.clone();
+            let lexeme = sym.lexeme();
+            let mut out = match args.iter().find(|(name, _)| *name == lexeme) {
+                Some((_, values)) if values.len() == 1 => return values[0].clone(),
+                Some((_, values)) => {
+                    let mut out = sym.clone();
+                    if let Some(token) = out.token_mut() {
+                        token.lexeme = Cow::Owned(joined(values));
+                    }
+                    return out;
                 }
+                None => sym.clone(),
+            };
+
+            let value = args.iter().fold(lexeme.to_string(), |acc, (name, values)| {
+                acc.replace(name.as_ref(), &joined(values))
+            });
+            if let Some(token) = out.token_mut().filter(|_| value != lexeme) {
+                token.lexeme = Cow::Owned(value);
             }
-            sym.clone()
+            out
+        }
+
+        fn splice<'a>(
+            syms: &[Symbol<'a>],
+            args: &[(Cow<'a, str>, Vec<Symbol<'a>>)],
+        ) -> Vec<Symbol<'a>> {
+            syms.iter()
+                .flat_map(
+                    |sym| match args.iter().find(|(name, _)| *name == sym.lexeme()) {
+                        Some((_, values)) => values.clone(),
+                        None => vec![resolve_field(sym, args)],
+                    },
+                )
+                .collect()
         }
 
-        let fields = params
-            .iter()
-            .zip(values.iter())
-            .map(|x| (x.0.clone(), x.1.clone()))
-            .collect::<Vec<(Symbol<'a>, Symbol<'a>)>>();
+        // longest first, so \value is not replaced as \v followed by alue
+        let mut args = args.to_vec();
+        args.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        let fields = args.as_slice();
 
         match self {
             Self::DPI {
@@ -78,11 +304,9 @@ impl<'a> Statements<'a> {
                 op2,
                 op3,
             } => {
-                let op_1 = resolve_field(op1, fields.as_slice());
-                let op_2 = resolve_field(op2, fields.as_slice());
-                let op_3 = op3
-                    .as_ref()
-                    .map(|op3_val| resolve_field(op3_val, fields.as_slice()));
+                let op_1 = resolve_field(op1, fields);
+                let op_2 = resolve_field(op2, fields);
+                let op_3 = op3.as_ref().map(|op3_val| resolve_field(op3_val, fields));
                 // println!("{op_1:#?}\n{op_2:#?}\n{op_3:#?}");
                 Statements::DPI {
                     instruction: instruction.clone(),
@@ -97,22 +321,24 @@ impl<'a> Statements<'a> {
                 op1,
                 op2,
                 cbracket,
+                writeback,
                 op3,
             } => {
-                let op_1 = resolve_field(op1, fields.as_slice());
-                let op_2 = resolve_field(op2, fields.as_slice());
-                let op_3 = resolve_field(op3, fields.as_slice());
+                let op_1 = resolve_field(op1, fields);
+                let op_2 = resolve_field(op2, fields);
+                let op_3 = resolve_field(op3, fields);
                 Self::LSI {
                     instruction: instruction.clone(),
                     op1: op_1,
                     obracket: *obracket,
                     op2: op_2,
                     cbracket: *cbracket,
+                    writeback: *writeback,
                     op3: op_3,
                 }
             }
             Self::BRI { instruction, label } => {
-                let op_1 = resolve_field(label, fields.as_slice());
+                let op_1 = resolve_field(label, fields);
                 Self::BRI {
                     instruction: instruction.clone(),
                     label: op_1,
@@ -122,13 +348,33 @@ impl<'a> Statements<'a> {
                 instruction,
                 vector,
             } => {
-                let vector = resolve_field(vector, fields.as_slice());
+                let vector = resolve_field(vector, fields);
                 Self::SCI {
                     instruction: instruction.clone(),
                     vector,
                 }
             }
-            _ => unreachable!("substitution macros are only able to expand to instructions"),
+            Self::Directive {
+                name,
+                params,
+                body,
+                marker,
+                pc,
+            } => Self::Directive {
+                name: name.clone(),
+                params: splice(params, fields),
+                body: body.iter().map(|x| x.resolve(fields)).collect(),
+                marker: marker.clone(),
+                pc: *pc,
+            },
+            Self::Substitution { name, values } => Self::Substitution {
+                name: name.clone(),
+                values: splice(values, fields),
+            },
+            Self::Label { name } => Self::Label {
+                name: resolve_field(name, fields),
+            },
+            x => x.clone(),
         }
     }
 }
@@ -164,28 +410,39 @@ impl<'a> fmt::Display for Statements<'a> {
                 op1,
                 op2,
                 cbracket,
+                writeback,
                 op3,
                 ..
             } => {
-                if *cbracket {
+                if op3.lexeme().starts_with('=') {
+                    write!(
+                        f,
+                        "\t{:<6}{DEFAULT_WHITESPACE}{}, {}",
+                        instruction, op1, op3
+                    )
+                } else if *cbracket {
                     write!(
                         f,
                         "\t{:<6}{DEFAULT_WHITESPACE}{}, [{}], {}",
                         instruction, op1, op2, op3
                     )
                 } else {
+                    let bang = if *writeback { "!" } else { "" };
                     write!(
                         f,
-                        "\t{:<6}{DEFAULT_WHITESPACE}{}, [{}, {}]",
+                        "\t{:<6}{DEFAULT_WHITESPACE}{}, [{}, {}]{bang}",
                         instruction, op1, op2, op3
                     )
                 }
             }
+            Self::BRI {
+                instruction,
+                label: label @ Symbol::Register(_),
+            } => write!(f, "\t{:>06}{DEFAULT_WHITESPACE}{}", instruction, label),
             Self::BRI { instruction, label } => write!(
                 f,
-                "\t{:>06}{DEFAULT_WHITESPACE}#0x{:02x}\t; {}",
+                "\t{:>06}{DEFAULT_WHITESPACE}{}",
                 instruction,
-                label.pc(),
                 label.lexeme()
             ),
             Self::SCI {
@@ -199,11 +456,15 @@ impl<'a> fmt::Display for Statements<'a> {
                 marker,
                 ..
             } => {
+                let sep = match EXPR_DIRECTIVES.contains(&name.lexeme().as_ref()) {
+                    true => ", ",
+                    false => " ",
+                };
                 let params = params
                     .iter()
                     .map(|x| x.to_string())
                     .collect::<Vec<_>>()
-                    .join(" ");
+                    .join(sep);
                 if marker.is_some() && !body.is_empty() {
                     let body = body
                         .iter()
@@ -224,12 +485,24 @@ impl<'a> fmt::Display for Statements<'a> {
                 }
             }
             Self::Substitution { name, values } => {
+                // a named argument is printed as name=value
                 let values = values
+                    .iter()
+                    .map(|x| match x {
+                        Symbol::Input(_) => format!("{x}="),
+                        x => format!("{x} "),
+                    })
+                    .collect::<String>();
+                let values = values.trim_end();
+                write!(f, "\t{:<6}{}{}", name, DEFAULT_WHITESPACE, values)
+            }
+            Self::Expansion { body, .. } => {
+                let body = body
                     .iter()
                     .map(|x| x.to_string())
                     .collect::<Vec<_>>()
-                    .join(" ");
-                write!(f, "\t{:<6}{}{}", name, DEFAULT_WHITESPACE, values)
+                    .join("\n");
+                write!(f, "{}", body)
             }
             Self::Label { name } => write!(f, "\n{}:", name),
             Self::Comment { name } => write!(f, "{}", name),
@@ -0,0 +1,161 @@
+use crate::{error::EsiuxErrorKind, processor::get_all_op};
+
+use super::{labels::numeric_reference, Macros, PreProcessor};
+
+/// # edit_distance
+///
+/// * number of single character insertions, deletions, substitutions and swaps of two
+///   neighbouring characters between a and b
+/// * case sensitive, `Loop` and `loop` are one edit apart
+///
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    // distances of the previous two prefixes of a against every prefix of b
+    let mut before = Vec::new();
+    let mut above = (0..=b.len()).collect::<Vec<_>>();
+
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            row[j] = (above[j - 1] + usize::from(a[i - 1] != b[j - 1]))
+                .min(above[j] + 1)
+                .min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut above, row);
+    }
+
+    above[b.len()]
+}
+
+/// # closest
+///
+/// * the candidate closest to name, if it is close enough to be a typo of it
+/// * a third of the length of name can be wrong, at least one character
+///
+pub fn closest<'s>(name: &str, candidates: impl IntoIterator<Item = &'s str>) -> Option<&'s str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|x| *x != name)
+        .map(|x| (edit_distance(name, x), x))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, x)| x)
+}
+
+impl PreProcessor<'_> {
+    /// # suggest
+    ///
+    /// * adds the closest known name to an error about an unknown one
+    /// * undefined symbols are matched against labels and constants
+    /// * unknown directives against directives, unknown macros against macros and instructions
+    ///
+    pub fn suggest(&self, err: EsiuxErrorKind) -> EsiuxErrorKind {
+        let inner = match &err {
+            EsiuxErrorKind::Spanned(inner, _) => inner.as_ref(),
+            x => x,
+        };
+        // numeric local labels are stored under names nobody can write, name them as written
+        if let Some((reference, label, direction)) = match inner {
+            EsiuxErrorKind::UndefinedSymbol(name) => numeric_reference(name),
+            _ => None,
+        } {
+            let found = EsiuxErrorKind::UndefinedNumeric(reference, label, direction);
+            return match err.span() {
+                Some(span) => found.at(span),
+                None => found,
+            };
+        }
+
+        let hint = match inner {
+            EsiuxErrorKind::UndefinedSymbol(name) | EsiuxErrorKind::UndefinedLabel(name) => {
+                // numeric local labels are stored under names nobody can write
+                let labels = self.labels.keys().filter(|x| !x.contains('$'));
+                closest(
+                    name,
+                    labels.chain(self.variables.keys()).map(|x| x.as_ref()),
+                )
+                .map(str::to_string)
+            }
+            EsiuxErrorKind::UnknownDirective(name) => {
+                closest(name, self.macros.keys().map(|x| x.as_ref())).map(|x| format!(".{x}"))
+            }
+            EsiuxErrorKind::UnknownSubstitution(name) => {
+                let ops = get_all_op();
+                let instructions = ops.split('_').filter(|x| !x.is_empty());
+                let macros = self.macros.iter().filter_map(|(name, mac)| match mac {
+                    Macros::Substitution(_) => Some(name.as_ref()),
+                    Macros::Directive(_) => None,
+                });
+                closest(&name.to_lowercase(), instructions)
+                    .or_else(|| closest(name, macros))
+                    .map(str::to_string)
+            }
+            _ => None,
+        };
+
+        match hint {
+            Some(hint) => err.help(hint),
+            None => err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assembly::Assembler;
+
+    use super::{closest, edit_distance};
+
+    #[test]
+    fn suggest_one() {
+        assert_eq!(edit_distance("loop", "loop"), 0);
+        assert_eq!(edit_distance("lopo", "loop"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("_start", "start"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn suggest_two() {
+        let labels = ["loop", "done", "_start", "buffer_end"];
+
+        assert_eq!(closest("lopp", labels), Some("loop"));
+        assert_eq!(closest("start", labels), Some("_start"));
+        assert_eq!(closest("buffer_ned", labels), Some("buffer_end"));
+        assert_eq!(closest("xyz", labels), None);
+        assert_eq!(closest("loop", labels), None);
+    }
+
+    #[test]
+    fn suggest_three() {
+        let mut asm = Assembler {
+            source: ".global _strat\n.macro PUSH \\reg\n.endm\n_start:\nloop:\n\tb lopo\n\tmovv r1, #1\n\tPUHS r2\n.wrod 1\nloop:\n.data\n.word SIZ\n.equ SIZE, 4\n"
+                .to_string(),
+            ..Default::default()
+        };
+
+        asm.assemble().unwrap_err();
+        let errors = asm
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Undefined label: _strat, did you mean _start? @ 1:9",
+                "Undefined symbol: lopo, did you mean loop? @ 6:4",
+                "Unknown macro called: movv, did you mean mov? @ 7:2",
+                "Unknown macro called: PUHS, did you mean PUSH? @ 8:2",
+                "Unknown directive called: .wrod, did you mean .word? @ 9:2",
+                "Label loop is already defined at 5:1 @ 10:1",
+                "Undefined symbol: SIZ, did you mean SIZE? @ 12:7",
+            ]
+        );
+    }
+}
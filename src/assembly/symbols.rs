@@ -1,5 +1,7 @@
 use std::{borrow::Cow, fmt};
 
+use crate::error::Span;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Symbol<'a> {
     Label(Token<'a>),
@@ -17,6 +19,8 @@ pub enum Symbol<'a> {
     Marker(Token<'a>),
     // For reconstruction purpose
     Comment(Token<'a>),
+    // string literal, quotes included
+    Str(Token<'a>),
     Eof,
 }
 
@@ -36,6 +40,7 @@ impl<'a> Symbol<'a> {
             Self::Whitespace(s) => s.line,
             Self::Marker(s) => s.line,
             Self::Comment(s) => s.line,
+            Self::Str(s) => s.line,
             Self::Eof => 0,
         }
     }
@@ -55,6 +60,7 @@ impl<'a> Symbol<'a> {
             Self::Whitespace(s) => s.lexeme.clone(),
             Self::Marker(s) => s.lexeme.clone(),
             Self::Comment(s) => s.lexeme.clone(),
+            Self::Str(s) => s.lexeme.clone(),
             Self::Eof => Cow::Borrowed("Eof"),
         }
     }
@@ -73,9 +79,56 @@ impl<'a> Symbol<'a> {
             Self::Whitespace(s) => s.pc.unwrap_or(0),
             Self::Marker(s) => s.pc.unwrap_or(0),
             Self::Comment(s) => s.pc.unwrap_or(0),
+            Self::Str(s) => s.pc.unwrap_or(0),
             Self::Eof => 0,
         }
     }
+
+    pub fn token_mut(&mut self) -> Option<&mut Token<'a>> {
+        match self {
+            Self::Label(s) => Some(s),
+            Self::Directive(s) => Some(s),
+            Self::Macros(s) => Some(s),
+            Self::Ident(s) => Some(s),
+            Self::Instruction(s) => Some(s),
+            Self::Literal(s) => Some(s),
+            Self::Register(s) => Some(s),
+            Self::Punct(s) => Some(s),
+            Self::Param(s) => Some(s),
+            Self::Input(s) => Some(s),
+            Self::Whitespace(s) => Some(s),
+            Self::Marker(s) => Some(s),
+            Self::Comment(s) => Some(s),
+            Self::Str(s) => Some(s),
+            Self::Eof => None,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Label(s) => s.span(),
+            Self::Directive(s) => s.span(),
+            Self::Macros(s) => s.span(),
+            Self::Ident(s) => s.span(),
+            Self::Instruction(s) => s.span(),
+            Self::Literal(s) => s.span(),
+            Self::Register(s) => s.span(),
+            Self::Punct(s) => s.span(),
+            Self::Param(s) => s.span(),
+            Self::Input(s) => s.span(),
+            Self::Whitespace(s) => s.span(),
+            Self::Marker(s) => s.span(),
+            Self::Comment(s) => s.span(),
+            Self::Str(s) => s.span(),
+            Self::Eof => Span::default(),
+        }
+    }
+
+    pub fn set_pc(&mut self, pc: u32) {
+        if let Some(token) = self.token_mut() {
+            token.pc = Some(pc);
+        }
+    }
 }
 
 impl fmt::Display for Symbol<'_> {
@@ -127,6 +180,9 @@ pub struct Token<'a> {
     pub offset: usize,
     pub len: usize,
     pub line: usize,
+    pub column: usize,
+    // index into the SourceMap of the assembly
+    pub file: usize,
     pub pc: Option<u32>,
 }
 
@@ -144,6 +200,7 @@ impl<'a> Token<'a> {
             len,
             line,
             pc,
+            ..Default::default()
         }
     }
 
@@ -154,6 +211,16 @@ impl<'a> Token<'a> {
             len: content.len(),
             line,
             pc,
+            ..Default::default()
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            file: self.file,
+            line: self.line,
+            column: self.column,
+            len: self.len,
         }
     }
 }
@@ -0,0 +1,81 @@
+use std::{env, fs, path::Path, process::ExitCode};
+
+use esiux_isa::{
+    error::EsiuxErrorKind,
+    format::{EsiuxArchive, EsiuxObj},
+    parser::{IntoSlice, Sliced},
+    Res,
+};
+
+const USAGE: &str = "Usage:
+\tesiux_ar <archive> <file_.o>...    add objects to <archive>, creating it if needed
+\tesiux_ar -t <archive>              list the members of <archive> and their symbols
+
+Options:
+\t-h                                 print this message";
+
+fn read(path: &str) -> Res<EsiuxArchive> {
+    let in_file = |e| EsiuxErrorKind::InFile(Box::new(e), path.to_string());
+    let bytes = fs::read(path).map_err(|e| in_file(e.into()))?;
+    bytes.as_slice().as_bytes::<EsiuxArchive>().map_err(in_file)
+}
+
+/// members in order, each followed by the symbols the index maps to it
+fn list(path: &str) -> Res<()> {
+    let ar = read(path)?;
+    for (i, member) in ar.members.iter().enumerate() {
+        println!("{}", member.name);
+        for (name, _) in ar.index.iter().filter(|(_, x)| *x as usize == i) {
+            println!("\t{name}");
+        }
+    }
+    Ok(())
+}
+
+/// members are named after the file name of the object, adding it again replaces it
+fn update(path: &str, objects: &[String]) -> Res<()> {
+    let mut ar = match Path::new(path).exists() {
+        true => read(path)?,
+        false => EsiuxArchive::default(),
+    };
+    for object in objects {
+        let in_file = |e| EsiuxErrorKind::InFile(Box::new(e), object.to_string());
+        let bytes = fs::read(object).map_err(|e| in_file(e.into()))?;
+        let obj = bytes.as_slice().as_bytes::<EsiuxObj>().map_err(in_file)?;
+        let name = Path::new(object)
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_else(|| object.to_string());
+        ar.add(&name, obj);
+    }
+    fs::write(path, ar.to_slice()?)?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    if args.iter().any(|x| x == "-h" || x == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let result = match args.as_slice() {
+        [flag, archive] if flag == "-t" => list(archive),
+        [archive, objects @ ..] if !archive.starts_with('-') && !objects.is_empty() => {
+            update(archive, objects)
+        }
+        _ => {
+            eprintln!("error: expected an archive and objects to add\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,17 +1,191 @@
-use std::{env, process};
+use std::{env, fs, io, path::Path, process::ExitCode};
 
-use esiux_isa::{assembly::Assembler, Res};
+use esiux_isa::{
+    assembly::{Assembler, Assembly},
+    error::EsiuxErrorKind,
+    parser::IntoSlice,
+    Res,
+};
 
-fn main() -> Res<()> {
-    let args = env::args().collect::<Vec<_>>();
+const USAGE: &str = "Usage:
+\tesiux_asm [options] <file_.asm>
 
-    if args.len() < 2 {
-        println!("Usage:\n\t{} <file_.asm>", args[0]);
-        process::exit(1);
+Options:
+\t-o <file>          write output to <file>
+\t-E                 preprocess only, write the expanded source
+\t-l <file>          write a listing of addresses and encodings to <file>
+\t-M <file>          write a map of sections and symbols by address to <file>
+\t-f <bin|raw|obj>   output format: EsiuxBin container (default), a raw flat image
+\t                   or a relocatable object to link later
+\t-c                 same as -f obj, the output defaults to <file>.o
+\t-D NAME=VALUE      define a variable before preprocessing
+\t-I <dir>           search <dir> for included files
+\t-h                 print this message";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    #[default]
+    Bin,
+    Raw,
+    Obj,
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    input: String,
+    output: Option<String>,
+    listing: Option<String>,
+    map: Option<String>,
+    preprocess: bool,
+    format: Format,
+    defines: Vec<(String, String)>,
+    include_paths: Vec<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut opts = Self::default();
+        let mut input = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{flag} requires an argument"))
+            };
+
+            match arg.as_str() {
+                "-o" => opts.output = Some(value("-o")?),
+                "-E" => opts.preprocess = true,
+                "-c" => opts.format = Format::Obj,
+                "-l" => opts.listing = Some(value("-l")?),
+                "-M" => opts.map = Some(value("-M")?),
+                "-f" => {
+                    opts.format = match value("-f")?.as_str() {
+                        "bin" => Format::Bin,
+                        "raw" => Format::Raw,
+                        "obj" => Format::Obj,
+                        x => return Err(format!("unknown output format: {x}")),
+                    }
+                }
+                x if x.starts_with("-D") => {
+                    let define = match &x[2..] {
+                        "" => value("-D")?,
+                        d => d.to_string(),
+                    };
+                    let (name, val) = define.split_once('=').unwrap_or((&define, "1"));
+                    opts.defines.push((name.to_string(), val.to_string()));
+                }
+                "-I" => opts.include_paths.push(value("-I")?),
+                x if x.starts_with("-I") => opts.include_paths.push(x[2..].to_string()),
+                x if x.starts_with('-') && x != "-" => return Err(format!("unknown option: {x}")),
+                x => match input {
+                    None => input = Some(x.to_string()),
+                    Some(_) => return Err(format!("unexpected argument: {x}")),
+                },
+            }
+        }
+
+        opts.input = input.ok_or_else(|| "no input file".to_string())?;
+        if opts.format == Format::Obj && opts.listing.is_some() {
+            return Err("-l needs an executable, it cannot be used with -c".to_string());
+        }
+        if opts.format == Format::Obj && opts.map.is_some() {
+            return Err("-M needs an executable, link the object with esiux_ld -M".to_string());
+        }
+        Ok(opts)
     }
+}
 
-    let mut assembler = Assembler::new(&args[1])?;
+fn run(opts: &Options) -> Res<()> {
+    let mut assembler = Assembler::new(&opts.input)?;
+    for (name, value) in &opts.defines {
+        assembler.define(name, value);
+    }
+    for dir in &opts.include_paths {
+        assembler.include_path(dir);
+    }
+
+    if opts.preprocess {
+        let out = assembler.preprocess()?;
+        match &opts.output {
+            Some(path) => fs::write(path, out)?,
+            None => io::Write::write_all(&mut io::stdout(), out.as_bytes())?,
+        }
+        return Ok(());
+    }
+
+    if opts.format == Format::Obj {
+        let object = assembler.object()?;
+        let out = match &opts.output {
+            Some(path) => Path::new(path).to_path_buf(),
+            None => Path::new(&opts.input).with_extension("o"),
+        };
+        fs::write(out, object.to_slice()?)?;
+        return Ok(());
+    }
+
+    let Assembly {
+        program,
+        listing,
+        map,
+    } = assembler.build(opts.listing.is_some(), opts.map.is_some())?;
+    if let (Some(path), Some(listing)) = (&opts.listing, listing) {
+        fs::write(path, listing)?;
+    }
+    if let (Some(path), Some(map)) = (&opts.map, map) {
+        fs::write(path, map.to_string())?;
+    }
+
+    let bytes = match opts.format {
+        Format::Bin => program.to_slice()?,
+        Format::Raw => {
+            if program.header.entry != 0 {
+                eprintln!(
+                    "warning: raw images start executing at 0x00, entry point is 0x{:02x}",
+                    program.header.entry
+                );
+            }
+            program.data
+        }
+        Format::Obj => unreachable!("objects are written above"),
+    };
+
+    let out = match &opts.output {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => Path::new(&opts.input).with_extension("bin"),
+    };
+    fs::write(out, bytes)?;
 
-    let _program = assembler.assemble(true)?;
     Ok(())
 }
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    if args.iter().any(|x| x == "-h" || x == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let opts = match Options::parse(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&opts) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e @ EsiuxErrorKind::Diagnostics(..)) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
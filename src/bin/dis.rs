@@ -0,0 +1,109 @@
+use std::{env, fs, process::ExitCode};
+
+use esiux_isa::{
+    disassembly::Disassembler,
+    error::EsiuxErrorKind,
+    format::{EsiuxBin, EsiuxObj, LinkMap, Section, MAGIC, OBJ_MAGIC},
+    parser::Sliced,
+    Res,
+};
+
+const USAGE: &str = "Usage:
+\tesiux_dis [options] <file_.bin|file_.o>
+
+Prints the address, word and instruction of every word in the text sections of an
+executable or object. Any other file is a raw image loaded at 0.
+
+Options:
+\t-M <file>    name addresses after the symbols of a map written by esiux_asm -M
+\t             or esiux_ld -M, objects always use their own symbols
+\t-h           print this message";
+
+#[derive(Debug, Default)]
+struct Options {
+    input: String,
+    map: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut opts = Self::default();
+        let mut input = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-M" => {
+                    let value = args.next().ok_or("-M requires an argument")?;
+                    opts.map = Some(value.clone());
+                }
+                x if x.starts_with('-') => return Err(format!("unknown option: {x}")),
+                x => match input {
+                    None => input = Some(x.to_string()),
+                    Some(_) => return Err(format!("unexpected argument: {x}")),
+                },
+            }
+        }
+
+        opts.input = input.ok_or_else(|| "no input file".to_string())?;
+        Ok(opts)
+    }
+}
+
+fn run(opts: &Options) -> Res<()> {
+    let in_file = |e| EsiuxErrorKind::InFile(Box::new(e), opts.input.clone());
+    let bytes = fs::read(&opts.input).map_err(|e| in_file(e.into()))?;
+
+    let dis = match &opts.map {
+        Some(path) => {
+            let in_map = |e| EsiuxErrorKind::InFile(Box::new(e), path.clone());
+            let map = fs::read_to_string(path)
+                .map_err(|e| in_map(e.into()))?
+                .parse::<LinkMap>()
+                .map_err(in_map)?;
+            Disassembler::from_map(&map)
+        }
+        None => Disassembler::default(),
+    };
+
+    // an object brings its own symbols, its text starts at 0 until it is linked
+    let text = if bytes.starts_with(&MAGIC.to_le_bytes()) {
+        let bin = bytes.as_slice().as_bytes::<EsiuxBin>().map_err(in_file)?;
+        println!("entry 0x{:08x}", bin.header.entry);
+        dis.bin(&bin)
+    } else if bytes.starts_with(&OBJ_MAGIC.to_le_bytes()) {
+        let obj = bytes.as_slice().as_bytes::<EsiuxObj>().map_err(in_file)?;
+        Disassembler::from_object(&obj)
+            .disassemble(0, obj.section(Section::Text).unwrap_or_default())
+    } else {
+        dis.disassemble(0, &bytes)
+    };
+    print!("{text}");
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    if args.iter().any(|x| x == "-h" || x == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let opts = match Options::parse(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&opts) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
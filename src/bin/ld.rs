@@ -0,0 +1,123 @@
+use std::{env, fs, process::ExitCode};
+
+use esiux_isa::{error::EsiuxErrorKind, format::Section, linker::Linker, parser::IntoSlice, Res};
+
+const USAGE: &str = "Usage:
+\tesiux_ld [options] <file_.o|file_.a>...
+
+Objects are always linked, archive members only when they define a symbol that is
+still undefined.
+
+Options:
+\t-o <file>              write the executable to <file>, a.bin by default
+\t-e, --entry <symbol>   start executing at <symbol> instead of _start
+\t-T<section> <addr>     place <section> (text, rodata, data or bss) at <addr>
+\t-M, --map <file>       write a map of sections and symbols by address to <file>
+\t-h                     print this message";
+
+#[derive(Debug, Default)]
+struct Options {
+    inputs: Vec<String>,
+    output: Option<String>,
+    entry: Option<String>,
+    map: Option<String>,
+    bases: Vec<(Section, u32)>,
+}
+
+/// a decimal or 0x prefixed hex address, sections have to start word aligned
+fn address(value: &str) -> Result<u32, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    match parsed {
+        Ok(x) if x % 4 == 0 => Ok(x),
+        Ok(x) => Err(format!("section address 0x{x:x} is not word aligned")),
+        Err(_) => Err(format!("invalid address: {value}")),
+    }
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut opts = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{flag} requires an argument"))
+            };
+
+            match arg.as_str() {
+                "-o" => opts.output = Some(value("-o")?),
+                "-e" | "--entry" => opts.entry = Some(value(arg)?),
+                "-M" | "--map" => opts.map = Some(value(arg)?),
+                x if x.starts_with("-T") => {
+                    let section = x[2..]
+                        .parse::<Section>()
+                        .map_err(|_| format!("unknown section: {}", &x[2..]))?;
+                    opts.bases.push((section, address(&value(x)?)?));
+                }
+                x if x.starts_with('-') => return Err(format!("unknown option: {x}")),
+                x => opts.inputs.push(x.to_string()),
+            }
+        }
+
+        if opts.inputs.is_empty() {
+            return Err("no input files".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+fn run(opts: &Options) -> Res<()> {
+    let mut linker = Linker {
+        entry: opts.entry.clone(),
+        ..Default::default()
+    };
+    for path in &opts.inputs {
+        linker.add(path)?;
+    }
+    for (section, address) in &opts.bases {
+        linker.base(*section, *address);
+    }
+
+    let program = linker.link()?;
+    let out = opts.output.as_deref().unwrap_or("a.bin");
+    fs::write(out, program.to_slice()?)?;
+    if let Some(path) = &opts.map {
+        fs::write(path, linker.map.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    if args.iter().any(|x| x == "-h" || x == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let opts = match Options::parse(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&opts) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e @ EsiuxErrorKind::Diagnostics(..)) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
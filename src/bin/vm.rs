@@ -5,7 +5,9 @@ use std::{
 };
 
 use esiux_isa::{
+    format::{EsiuxBin, MAGIC},
     machine::{halt, print, Cpu},
+    parser::Sliced,
     Res,
 };
 
@@ -29,7 +31,12 @@ fn main() -> Res<()> {
     let mut program = Vec::<u8>::new();
     readable.read_to_end(&mut program)?;
 
-    vm.load_program(&program, 0)?;
+    if program.starts_with(&MAGIC.to_le_bytes()) {
+        let bin = program.as_slice().as_bytes::<EsiuxBin>()?;
+        vm.load_bin(&bin)?;
+    } else {
+        vm.load_program(&program, 0)?;
+    }
 
     vm.execute()?;
 
@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    assembly::is_literal,
+    format::{Binding, EsiuxBin, EsiuxObj, LinkMap, RelocKind, Section},
+    processor::Instruction,
+};
+
+/// # Disassembler
+///
+/// * decodes words back into instructions, a word that does not decode is shown as `.word`
+/// * a label is printed above the address it names and branch targets are named after
+///   the closest label before them
+/// * relocations name the symbol a field is patched with, an object has not been linked yet
+/// * a word named by a pool entry symbol is data and shown as `.word`, see is_literal
+///
+#[derive(Debug, Default)]
+pub struct Disassembler {
+    // names of the addresses in text, several names for one address are all printed
+    pub symbols: BTreeMap<u32, Vec<String>>,
+    // the symbol a relocation patches the word at an address with
+    pub relocations: HashMap<u32, String>,
+}
+
+impl Disassembler {
+    /// names address, the label is printed above it
+    pub fn symbol(&mut self, address: u32, name: &str) {
+        self.symbols
+            .entry(address)
+            .or_default()
+            .push(name.to_string());
+    }
+
+    /// the symbols of a map written by `esiux_asm -M` or `esiux_ld -M`
+    pub fn from_map(map: &LinkMap) -> Self {
+        let mut dis = Self::default();
+        for sym in map.symbols.iter().filter(|x| x.section == Section::Text) {
+            dis.symbol(sym.address, &sym.name);
+        }
+        dis
+    }
+
+    /// # from_object
+    ///
+    /// * the symbols and relocations of the text section of an object
+    /// * the section symbols are left out, text starts at 0 in an object
+    ///
+    pub fn from_object(obj: &EsiuxObj) -> Self {
+        let mut dis = Self::default();
+        for sym in &obj.symbols {
+            if sym.section == Some(Section::Text) && sym.name != format!(".{}", Section::Text) {
+                dis.symbol(sym.value, &sym.name);
+            }
+        }
+        for reloc in obj.relocations.iter() {
+            let Some(sym) = obj.symbols.get(reloc.symbol as usize) else {
+                continue;
+            };
+            if reloc.section != Section::Text {
+                continue;
+            }
+            let name = match sym.binding {
+                Binding::Extern => sym.name.clone(),
+                _ => format!("{}{:+}", sym.name, reloc.addend),
+            };
+            let name = match reloc.kind {
+                RelocKind::Lo16 => format!("%lo({name})"),
+                RelocKind::Hi16 => format!("%hi({name})"),
+                _ => name,
+            };
+            dis.relocations.insert(reloc.offset, name);
+        }
+        dis
+    }
+
+    /// the closest label at or before address, with the distance to it
+    fn label(&self, address: u32) -> Option<String> {
+        let (at, name) = self
+            .symbols
+            .range(..=address)
+            .rev()
+            .find_map(|(at, names)| Some((at, names.iter().rfind(|x| !is_literal(x))?)))?;
+        match address - at {
+            0 => Some(name.clone()),
+            x => Some(format!("{name}+0x{x:x}")),
+        }
+    }
+
+    /// # disassemble
+    ///
+    /// * one line per word of bytes, bytes are loaded at address
+    /// * every line shows the address, the word as it is read from memory and the
+    ///   instruction, bytes left over after the last word are shown as `.byte`
+    ///
+    pub fn disassemble(&self, address: u32, bytes: &[u8]) -> String {
+        let mut out = String::new();
+        let chunks = bytes.chunks_exact(4);
+        let rest = chunks.remainder();
+
+        for (i, chunk) in chunks.enumerate() {
+            let pc = address + 4 * i as u32;
+            let names = self.symbols.get(&pc).into_iter().flatten();
+            let (pool, names): (Vec<_>, Vec<_>) = names.partition(|x| is_literal(x));
+            for name in names {
+                out.push_str(&format!("\n{pc:08x} <{name}>:\n"));
+            }
+
+            let word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let decoded = match pool.is_empty() {
+                true => Instruction::try_from(word).ok(),
+                false => None,
+            };
+            let text = match decoded {
+                Some(instruction) => instruction.to_string(),
+                None => format!(".word 0x{word:08x}"),
+            };
+            let note = match (self.relocations.get(&pc), decoded) {
+                (Some(name), _) => Some(name.clone()),
+                (None, Some(Instruction::Branch(bri) | Instruction::Bl(bri))) => {
+                    let target = bri.target(pc);
+                    match self.label(target) {
+                        Some(name) => Some(format!("0x{target:x} <{name}>")),
+                        None => Some(format!("0x{target:x}")),
+                    }
+                }
+                _ => None,
+            };
+
+            match note {
+                Some(note) => out.push_str(&format!("{pc:8x}:\t{word:08x}\t{text}\t; {note}\n")),
+                None => out.push_str(&format!("{pc:8x}:\t{word:08x}\t{text}\n")),
+            }
+        }
+
+        if !rest.is_empty() {
+            let pc = address + (bytes.len() - rest.len()) as u32;
+            let values = rest
+                .iter()
+                .map(|x| format!("0x{x:02x}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            out.push_str(&format!("{pc:8x}:\t.byte {values}\n"));
+        }
+
+        out
+    }
+
+    /// # bin
+    ///
+    /// * disassembles the text segments of an executable at the address they are loaded at
+    /// * the other segments hold data and are skipped
+    ///
+    pub fn bin(&self, bin: &EsiuxBin) -> String {
+        let head = bin.get_head_offset() as u32;
+        let mut out = String::new();
+        for seg in bin
+            .section_headers
+            .iter()
+            .filter(|x| x.kind == Section::Text)
+        {
+            let address = seg.offset.saturating_sub(head);
+            let start = (address as usize).min(bin.data.len());
+            let end = (start + seg.size as usize).min(bin.data.len());
+            out.push_str(&self.disassemble(address, &bin.data[start..end]));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembly::Assembler,
+        format::{LinkMap, MapSymbol, Section},
+        parser::ToNum,
+        processor::Instruction,
+    };
+
+    use super::Disassembler;
+
+    fn word(ins: &str) -> u32 {
+        ins.parse::<Instruction>().unwrap().mask()
+    }
+
+    #[test]
+    fn dis_one() {
+        let mut dis = Disassembler::default();
+        dis.symbol(0, "_start");
+        dis.symbol(8, "done");
+        let bytes = [word("b #2"), 0xffff_ffff, word("svc #0xf0")]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .chain([1, 2])
+            .collect::<Vec<_>>();
+
+        let text = dis.disassemble(0, &bytes);
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[1], "00000000 <_start>:");
+        assert_eq!(
+            lines[2],
+            format!("       0:\t{:08x}\tb  #2\t; 0x8 <done>", word("b #2"))
+        );
+        assert_eq!(lines[3], "       4:\tffffffff\t.word 0xffffffff");
+        assert_eq!(lines[5], "00000008 <done>:");
+        assert_eq!(lines[7], "       c:\t.byte 0x01, 0x02");
+    }
+
+    #[test]
+    fn dis_two() {
+        let mut asm = Assembler {
+            source: ".global _start\n.extern print\n_start:\n\tb print\nloop:\n\tb loop\n"
+                .to_string(),
+            ..Default::default()
+        };
+        let obj = asm.object().unwrap();
+
+        let dis = Disassembler::from_object(&obj);
+        let text = dis.disassemble(0, obj.section(Section::Text).unwrap());
+
+        assert!(text.contains("b  #0\t; print\n"));
+        assert!(text.contains("00000004 <loop>:\n"));
+        assert!(text.contains("b  #0\t; 0x4 <loop>\n"));
+    }
+
+    #[test]
+    fn dis_three() {
+        let mut asm = Assembler {
+            source: "_start:\n\tmov r1, #1\nnext:\n\tb next\n.data\n\t.word 0xffffffff\n"
+                .to_string(),
+            ..Default::default()
+        };
+        let bin = asm.assemble().unwrap();
+        let map = LinkMap::new(
+            Vec::new(),
+            vec![MapSymbol::new(
+                "next",
+                4,
+                Section::Text,
+                Default::default(),
+                "",
+            )],
+        );
+
+        let text = Disassembler::from_map(&map).bin(&bin);
+
+        assert!(text.contains("00000004 <next>:\n"));
+        assert!(text.contains("; 0x4 <next>\n"));
+        assert!(!text.contains(".word"));
+    }
+
+    #[test]
+    fn dis_four() {
+        let mov = word("mov r1, #1");
+        let mut asm = Assembler {
+            source: format!("_start:\n\tldr r1, =0x{mov:x}\n\tldr r2, =msg\n\tb done\ndone:\n\tsvc #0xf0\n.data\nmsg:\n\t.word 1\n"),
+            ..Default::default()
+        };
+
+        let obj = asm.object().unwrap();
+        let text =
+            Disassembler::from_object(&obj).disassemble(0, obj.section(Section::Text).unwrap());
+
+        assert!(text.contains(&format!("       c:\t{mov:08x}\t.word 0x{mov:08x}\n")));
+        assert!(text.contains("      10:\t00000000\t.word 0x00000000\t; .data+0\n"));
+        assert!(!text.contains("Lpool"));
+
+        let built = asm.build(false, true).unwrap();
+        let text = Disassembler::from_map(&built.map.unwrap()).bin(&built.program);
+
+        assert!(text.contains(&format!("       c:\t{mov:08x}\t.word 0x{mov:08x}\n")));
+        assert!(text.contains("      10:\t00000018\t.word 0x00000018\n"));
+        assert!(text.contains("00000014 <done>:\n"));
+    }
+}
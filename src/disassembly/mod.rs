@@ -0,0 +1,3 @@
+mod disassemble;
+
+pub use self::disassemble::*;
@@ -3,6 +3,26 @@ use std::{fmt::Display, io, num::ParseIntError};
 use emacro::Error;
 use eparser::error::ParserErrorKind;
 
+/// # Span
+///
+/// * location of a token inside a source file
+/// * file is an index into the assemblers file table
+/// * line and column are 1 based, a line of 0 means the location is unknown
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub file: usize,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 /// # EsiuxErrorKind
 ///
 /// * Contains all the error types
@@ -28,6 +48,8 @@ pub enum EsiuxErrorKind {
     NotEnoughParts(Box<dyn Display + 'static>, u8),
     /// Tried to access mem: {:02x} which is out of range: {:032b}
     MemOutOfBounds(u32),
+    /// Program of {} bytes at 0x{:x} does not fit in {} bytes of memory
+    ProgramSize(usize, u32, usize),
     /// Failed to access i/o: {}
     Io(io::Error),
     /// Binary file does not contain any raw data
@@ -46,16 +68,117 @@ pub enum EsiuxErrorKind {
     UnknownSymbol(char, usize, usize),
     /// Error empty symbol stream returned
     EmptySymbolStream,
-    /// Unknown directive called: .{} @ line: {}
-    UnknownDirective(String, usize),
-    /// Unknown macro called: {} @ line: {}
-    UnknownSubstitution(String, usize),
+    /// Unknown directive called: .{}
+    UnknownDirective(String),
+    /// Unknown macro called: {}
+    UnknownSubstitution(String),
     /// Macro mismatch error:  {}
     InvalidMacroMatch(String),
     /// {} @ {}
     DirectiveResolve(String, usize),
-    /// Expected a label: {} @ {}
-    ExpectedLabel(String, usize),
+    /// Expected a label: {}
+    ExpectedLabel(String),
+    /// Undefined label: {}
+    UndefinedLabel(String),
+    /// Section {} is {} bytes which exceeds the segment size limit of 65535
+    SegmentSize(Box<dyn Display + 'static>, usize),
+    /// Expected an instruction statement, found: {}
+    NotAnInstruction(Box<dyn Display + 'static>),
+    /// Expected a directive statement, found: {}
+    NotADirective(Box<dyn Display + 'static>),
+    /// {} @ {}
+    Spanned(Box<EsiuxErrorKind>, Span),
+    /// {}error: aborting due to {} previous error(s)
+    Diagnostics(Box<dyn Display + 'static>, usize),
+    /// Unrecognized instruction: {}
+    UnknownInstruction(String),
+    /// Expected {}, found '{}'
+    Expected(Box<dyn Display + 'static>, String),
+    /// Unterminated .{} block, missing .{}
+    Unterminated(String, String),
+    /// Value {} does not fit in .{}
+    DataRange(i64, String),
+    /// .align expects a power of two, found {}
+    Alignment(i64),
+    /// Invalid escape sequence in string: {}
+    InvalidEscape(String),
+    /// Section .bss can only hold zero initialised data
+    BssData,
+    /// Undefined symbol: {}
+    UndefinedSymbol(String),
+    /// Undefined label {}, there is no numeric label {}: {} it
+    UndefinedNumeric(String, String, &'static str),
+    /// Division by zero in expression
+    DivisionByZero,
+    /// Value {} does not fit in the {} field, expected {}..={}
+    ImmRange(i64, String, i64, i64),
+    /// Cannot find file: {}
+    FileNotFound(String),
+    /// Include cycle: {}
+    IncludeCycle(String),
+    /// Range {}..{} is outside of {} which is {} bytes
+    IncbinRange(i64, i64, String, usize),
+    /// .{} without a matching .if
+    UnmatchedConditional(String),
+    /// .{} after .else
+    AfterElse(String),
+    /// Macro {} expects {} arguments, found {}
+    MacroArity(String, String, usize),
+    /// Macro {} has no parameter named {}
+    UnknownMacroParam(String, String),
+    /// Macro {} is nested more than {} levels deep, does it call itself?
+    MacroDepth(String, usize),
+    /// Repeat count {} is out of range, expected 0..={}
+    RepeatCount(i64, i64),
+    /// Branch target {} is {} words away, a branch reaches {}..={} words
+    BranchRange(i64, i64, i64, i64),
+    /// Branch target {} is not word aligned
+    BranchAlignment(i64),
+    /// Literal pool is {} bytes past the ldr of {}, an ldr reaches {} bytes, add a .ltorg closer to it
+    PoolRange(i64, String, i64),
+    /// {}, did you mean {}?
+    Help(Box<EsiuxErrorKind>, String),
+    /// Label {} is already defined at {}
+    DuplicateLabel(String, Span),
+    /// Cannot relocate {}, expected a symbol plus or minus a constant
+    NotRelocatable(String),
+    /// Section .{} at 0x{:x} overlaps the sections before it, which end at 0x{:x}
+    SectionOverlap(String, u32, u32),
+    /// Symbol {} is defined in both {} and {}
+    DuplicateSymbol(String, String, String),
+    /// Undefined symbol {} referenced in {}
+    UnresolvedSymbol(String, String),
+    /// Entry point {} is not defined by any object
+    UndefinedEntry(String),
+    /// No entry point, {} and other symbols are exported but _start is not, name the entry with .entry
+    NoEntry(String),
+    /// {} in {}
+    InFile(Box<EsiuxErrorKind>, String),
+}
+
+impl EsiuxErrorKind {
+    /// attaches a source location to the error, keeps an existing one
+    pub fn at(self, span: Span) -> Self {
+        match self {
+            Self::Spanned(..) => self,
+            x => Self::Spanned(Box::new(x), span),
+        }
+    }
+
+    /// attaches the name the user probably meant, inside the source location if there is one
+    pub fn help(self, hint: String) -> Self {
+        match self {
+            Self::Spanned(inner, span) => Self::Spanned(Box::new(inner.help(hint)), span),
+            x => Self::Help(Box::new(x), hint),
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Spanned(_, span) => Some(*span),
+            _ => None,
+        }
+    }
 }
 
 impl From<ParseIntError> for EsiuxErrorKind {
@@ -0,0 +1,201 @@
+use crate::{
+    error::EsiuxErrorKind,
+    parser::{FromSlice, IntoSlice, Sliced},
+    Res,
+};
+
+use super::{
+    object::{put_string, Reader},
+    Binding, EsiuxObj, Version,
+};
+
+pub const ARCHIVE_MAGIC: u32 = 0x63724145; // "EArc"
+
+/// # Member
+///
+/// * an object inside an archive, name is the file it was added from
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub object: EsiuxObj,
+}
+
+/// # EsiuxArchive
+///
+/// * a library of objects, the linker only pulls in the members it needs
+/// * index maps every global symbol to the member defining it, the first member wins
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EsiuxArchive {
+    pub version: Version,
+    pub members: Vec<Member>,
+    pub index: Vec<(String, u32)>,
+}
+
+impl EsiuxArchive {
+    /// # add
+    ///
+    /// * adds an object, replacing the member of the same name
+    /// * the index is rebuilt
+    ///
+    pub fn add(&mut self, name: &str, object: EsiuxObj) {
+        let member = Member {
+            name: name.to_string(),
+            object,
+        };
+        match self.members.iter_mut().find(|x| x.name == name) {
+            Some(x) => *x = member,
+            None => self.members.push(member),
+        }
+
+        self.index.clear();
+        for (i, member) in self.members.iter().enumerate() {
+            for sym in &member.object.symbols {
+                if sym.binding == Binding::Global && self.member(&sym.name).is_none() {
+                    self.index.push((sym.name.clone(), i as u32));
+                }
+            }
+        }
+    }
+
+    /// index of the member defining symbol
+    pub fn member(&self, symbol: &str) -> Option<usize> {
+        self.index
+            .iter()
+            .find(|(name, _)| name == symbol)
+            .map(|(_, member)| *member as usize)
+    }
+}
+
+impl FromSlice<EsiuxArchive> for EsiuxArchive {
+    fn from_slice(slice: &[u8]) -> Res<EsiuxArchive> {
+        let mut rd = Reader { slice, pos: 0 };
+
+        let magic = rd.u32()?;
+        if magic != ARCHIVE_MAGIC {
+            return Err(EsiuxErrorKind::Invalid(
+                "archive magic".to_string(),
+                ARCHIVE_MAGIC as usize,
+                magic as usize,
+            ));
+        }
+        let version = rd.take(3)?.as_bytes::<Version>()?;
+        rd.take(1)?;
+        let member_count = rd.u32()?;
+        let index_count = rd.u32()?;
+
+        let mut index = Vec::new();
+        for _ in 0..index_count {
+            let member = rd.u32()?;
+            if member >= member_count {
+                return Err(EsiuxErrorKind::Invalid(
+                    "archive member".to_string(),
+                    member_count as usize,
+                    member as usize,
+                ));
+            }
+            index.push((rd.string()?, member));
+        }
+
+        let mut members = Vec::new();
+        for _ in 0..member_count {
+            let name = rd.string()?;
+            let len = rd.u32()? as usize;
+            let object = rd.take(len)?.as_bytes::<EsiuxObj>()?;
+            members.push(Member { name, object });
+        }
+
+        Ok(Self {
+            version,
+            members,
+            index,
+        })
+    }
+}
+
+impl IntoSlice for EsiuxArchive {
+    fn to_slice(&self) -> Res<Vec<u8>> {
+        let count =
+            |len: usize| u32::try_from(len).map_err(|_| EsiuxErrorKind::TryFrom(Box::new(len)));
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&ARCHIVE_MAGIC.to_le_bytes());
+        out.extend_from_slice(&self.version.to_slice()?);
+        out.push(0);
+        out.extend_from_slice(&count(self.members.len())?.to_le_bytes());
+        out.extend_from_slice(&count(self.index.len())?.to_le_bytes());
+
+        for (name, member) in &self.index {
+            out.extend_from_slice(&member.to_le_bytes());
+            put_string(&mut out, name)?;
+        }
+        for member in &self.members {
+            let object = member.object.to_slice()?;
+            put_string(&mut out, &member.name)?;
+            out.extend_from_slice(&count(object.len())?.to_le_bytes());
+            out.extend_from_slice(&object);
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        format::{Binding, EsiuxObj, Section, SymbolEntry},
+        parser::{IntoSlice, Sliced},
+    };
+
+    use super::EsiuxArchive;
+
+    fn object(symbols: &[(&str, Binding)]) -> EsiuxObj {
+        EsiuxObj {
+            sections: vec![(Section::Text, vec![0; 4])],
+            symbols: symbols
+                .iter()
+                .map(|(name, binding)| SymbolEntry::new(name, *binding, Some(Section::Text), 0))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn archive_one() {
+        let mut ar = EsiuxArchive::default();
+        ar.add(
+            "string.o",
+            object(&[("strlen", Binding::Global), ("loop", Binding::Local)]),
+        );
+        ar.add(
+            "math.o",
+            object(&[("mul", Binding::Global), ("strlen", Binding::Global)]),
+        );
+        ar.add("string.o", object(&[("strcpy", Binding::Global)]));
+
+        assert_eq!(ar.members.len(), 2);
+        assert_eq!(
+            ar.index,
+            vec![
+                ("strcpy".to_string(), 0),
+                ("mul".to_string(), 1),
+                ("strlen".to_string(), 1),
+            ]
+        );
+        assert_eq!(ar.member("loop"), None);
+    }
+
+    #[test]
+    fn archive_two() {
+        let mut ar = EsiuxArchive::default();
+        ar.add("string.o", object(&[("strlen", Binding::Global)]));
+        ar.add("math.o", object(&[("mul", Binding::Global)]));
+
+        let bytes = ar.to_slice().unwrap();
+
+        assert_eq!(bytes[..4], [0x45, 0x41, 0x72, 0x63]);
+        assert_eq!(bytes.as_slice().as_bytes::<EsiuxArchive>().unwrap(), ar);
+        assert!(bytes[..bytes.len() - 1].as_bytes::<EsiuxArchive>().is_err());
+    }
+}
@@ -3,7 +3,7 @@ use crate::{
     parser::{FromSlice, IntoSlice, Sliced},
 };
 
-use super::{Header, SegmentHeader};
+use super::{Header, Section, SegmentHeader};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EsiuxBin {
@@ -13,8 +13,73 @@ pub struct EsiuxBin {
 }
 
 impl EsiuxBin {
+    /// # from_segments
+    ///
+    /// * packs the raw bytes of each section back to back after the headers
+    /// * segment offsets are file offsets into the packed binary
+    /// * data is loaded at address 0 so a segment lives at `offset - get_head_offset()`
+    /// * bss only records its size, it has to be the last segment
+    ///
+    pub fn from_segments(entry: u32, segments: Vec<(Section, Vec<u8>)>) -> crate::Res<EsiuxBin> {
+        let mut address = 0;
+        let placed = segments
+            .into_iter()
+            .map(|(kind, bytes)| {
+                let at = address;
+                address += bytes.len() as u32;
+                (kind, at, bytes)
+            })
+            .collect();
+
+        Self::from_placed(entry, placed)
+    }
+
+    /// # from_placed
+    ///
+    /// * like from_segments, but every segment is loaded at the address it comes with
+    /// * the gaps before and between segments are filled with zeros
+    /// * addresses have to increase and segments must not overlap
+    ///
+    pub fn from_placed(entry: u32, segments: Vec<(Section, u32, Vec<u8>)>) -> crate::Res<EsiuxBin> {
+        let section_count = u8::try_from(segments.len())
+            .map_err(|_| EsiuxErrorKind::TryFrom(Box::new(segments.len())))?;
+        let header = Header::new(entry, section_count);
+
+        let head = header.size() + SegmentHeader::size() * segments.len();
+        let mut section_headers = Vec::new();
+        let mut data = Vec::new();
+
+        for (kind, address, bytes) in segments {
+            let size = u16::try_from(bytes.len()).map_err(|_| {
+                EsiuxErrorKind::SegmentSize(Box::new(format!("{kind:?}")), bytes.len())
+            })?;
+            if (address as usize) < data.len() {
+                return Err(EsiuxErrorKind::SectionOverlap(
+                    kind.to_string(),
+                    address,
+                    data.len() as u32,
+                ));
+            }
+            section_headers.push(SegmentHeader::new(
+                size,
+                kind,
+                (head + address as usize) as u32,
+            ));
+            if kind != Section::Bss {
+                data.resize(address as usize, 0);
+                data.extend_from_slice(&bytes);
+            }
+        }
+
+        Ok(Self {
+            header,
+            section_headers,
+            data,
+        })
+    }
+
     pub fn get_head_offset(&self) -> usize {
-        self.header.size() + (SegmentHeader::size() + self.header.section_count as usize)
+        self.header.size() + (SegmentHeader::size() * self.header.section_count as usize)
     }
 }
 
@@ -117,4 +182,57 @@ mod test {
 
         assert_eq!(decoded, bin);
     }
+
+    #[test]
+    fn bin_three() {
+        let data = "mov r1, #69"
+            .parse::<Instruction>()
+            .unwrap()
+            .mask()
+            .to_le_bytes()
+            .to_vec();
+        let bin = EsiuxBin::from_segments(0xdeadbeef, vec![(Section::Text, data.clone())]).unwrap();
+
+        let ctrl = EsiuxBin {
+            header: Header::new(0xdeadbeef, 1),
+            section_headers: vec![SegmentHeader::new(4, Section::Text, 24)],
+            data,
+        };
+
+        assert_eq!(bin, ctrl);
+        assert_eq!(bin.get_head_offset(), 24);
+    }
+
+    #[test]
+    fn bin_four() {
+        let bin = EsiuxBin::from_placed(
+            0x10,
+            vec![
+                (Section::Text, 0x10, vec![1, 2, 3, 4]),
+                (Section::Data, 0x20, vec![5, 6]),
+                (Section::Bss, 0x40, vec![0; 8]),
+            ],
+        )
+        .unwrap();
+
+        let mut data = vec![0; 0x10];
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        data.resize(0x20, 0);
+        data.extend_from_slice(&[5, 6]);
+
+        assert_eq!(bin.data, data);
+        assert_eq!(
+            bin.section_headers,
+            vec![
+                SegmentHeader::new(4, Section::Text, 40 + 0x10),
+                SegmentHeader::new(2, Section::Data, 40 + 0x20),
+                SegmentHeader::new(8, Section::Bss, 40 + 0x40),
+            ]
+        );
+        assert!(EsiuxBin::from_placed(
+            0,
+            vec![(Section::Text, 0, vec![0; 8]), (Section::Data, 4, vec![1])]
+        )
+        .is_err());
+    }
 }
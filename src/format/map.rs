@@ -0,0 +1,263 @@
+use std::{fmt, str::FromStr};
+
+use crate::error::EsiuxErrorKind;
+
+use super::{Binding, Section};
+
+/// # MapSection
+///
+/// * where a section of an executable is loaded and how many bytes it takes
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapSection {
+    pub kind: Section,
+    pub address: u32,
+    pub size: u32,
+}
+
+/// # MapSymbol
+///
+/// * a label of an executable, file is the source or object that defines it
+/// * size runs up to the next symbol of its section or to the end of the section
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapSymbol {
+    pub name: String,
+    pub address: u32,
+    pub size: u32,
+    pub section: Section,
+    pub binding: Binding,
+    pub file: String,
+}
+
+impl MapSymbol {
+    pub fn new(name: &str, address: u32, section: Section, binding: Binding, file: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            address,
+            size: 0,
+            section,
+            binding,
+            file: file.to_string(),
+        }
+    }
+}
+
+/// # LinkMap
+///
+/// * the sections and symbols of an executable, sorted by address
+/// * written next to the executable by `esiux_asm -M` and `esiux_ld -M`
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LinkMap {
+    pub sections: Vec<MapSection>,
+    pub symbols: Vec<MapSymbol>,
+}
+
+impl LinkMap {
+    /// sorts sections and symbols by address and sizes the symbols
+    pub fn new(mut sections: Vec<MapSection>, mut symbols: Vec<MapSymbol>) -> Self {
+        sections.sort_by_key(|x| (x.address, x.kind.order()));
+        symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+
+        for i in 0..symbols.len() {
+            let (address, section) = (symbols[i].address, symbols[i].section);
+            let end = sections
+                .iter()
+                .find(|x| x.kind == section)
+                .map(|x| x.address + x.size)
+                .unwrap_or(address);
+            // symbols at the same address share the bytes up to the next one
+            let next = symbols[i..]
+                .iter()
+                .find(|x| x.section == section && x.address > address)
+                .map_or(end, |x| x.address);
+            symbols[i].size = next.max(address) - address;
+        }
+
+        Self { sections, symbols }
+    }
+
+    /// the symbol whose bytes hold address, the last one of several at the same place
+    pub fn lookup(&self, address: u32) -> Option<&MapSymbol> {
+        self.symbols
+            .iter()
+            .rev()
+            .find(|x| x.address <= address && address < x.address + x.size)
+    }
+}
+
+fn binding(binding: Binding) -> &'static str {
+    match binding {
+        Binding::Local => "local",
+        Binding::Global => "global",
+        Binding::Extern => "extern",
+    }
+}
+
+/// the first column of line and the rest of it
+fn column(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.split_once(char::is_whitespace) {
+        Some((first, rest)) => (first, rest.trim_start()),
+        None => (line, ""),
+    }
+}
+
+fn hex(value: &str) -> Result<u32, EsiuxErrorKind> {
+    u32::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| EsiuxErrorKind::FromStr(Box::new(format!("invalid map address: {value}"))))
+}
+
+impl fmt::Display for LinkMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Sections:")?;
+        writeln!(f, "  {:<8}  {:<10}  size", "section", "address")?;
+        for x in &self.sections {
+            let name = format!(".{}", x.kind);
+            writeln!(f, "  {name:<8}  0x{:08x}  0x{:08x}", x.address, x.size)?;
+        }
+
+        let width = self
+            .symbols
+            .iter()
+            .map(|x| x.name.len())
+            .max()
+            .unwrap_or_default()
+            .max("symbol".len());
+        writeln!(f)?;
+        writeln!(f, "Symbols:")?;
+        writeln!(
+            f,
+            "  {:<10}  {:<10}  {:<8}  {:<7}  {:<width$}  file",
+            "address", "size", "section", "binding", "symbol"
+        )?;
+        for x in &self.symbols {
+            let section = format!(".{}", x.section);
+            writeln!(
+                f,
+                "  0x{:08x}  0x{:08x}  {section:<8}  {:<7}  {:<width$}  {}",
+                x.address,
+                x.size,
+                binding(x.binding),
+                x.name,
+                x.file
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// reads a map back from the text Display writes, sizes are taken as written
+impl FromStr for LinkMap {
+    type Err = EsiuxErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::default();
+        let mut symbols = false;
+
+        for line in s.lines() {
+            let (first, rest) = column(line);
+            match first {
+                "" | "section" | "address" => continue,
+                "Sections:" => symbols = false,
+                "Symbols:" => symbols = true,
+                _ if !symbols => {
+                    let (address, rest) = column(rest);
+                    map.sections.push(MapSection {
+                        kind: first.parse()?,
+                        address: hex(address)?,
+                        size: hex(column(rest).0)?,
+                    });
+                }
+                _ => {
+                    let (size, rest) = column(rest);
+                    let (section, rest) = column(rest);
+                    let (bind, rest) = column(rest);
+                    let (name, file) = column(rest);
+                    let bind = [Binding::Local, Binding::Global, Binding::Extern]
+                        .into_iter()
+                        .find(|x| binding(*x) == bind)
+                        .ok_or_else(|| {
+                            EsiuxErrorKind::FromStr(Box::new(format!("invalid binding: {bind}")))
+                        })?;
+                    let mut symbol =
+                        MapSymbol::new(name, hex(first)?, section.parse()?, bind, file);
+                    symbol.size = hex(size)?;
+                    map.symbols.push(symbol);
+                }
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::format::{Binding, Section};
+
+    use super::{LinkMap, MapSection, MapSymbol};
+
+    fn map() -> LinkMap {
+        let section = |kind, address, size| MapSection {
+            kind,
+            address,
+            size,
+        };
+        LinkMap::new(
+            vec![
+                section(Section::Data, 0x20, 8),
+                section(Section::Text, 0, 0x1c),
+            ],
+            vec![
+                MapSymbol::new("value", 0x20, Section::Data, Binding::Global, "main.o"),
+                MapSymbol::new("loop", 0x8, Section::Text, Binding::Local, "main.o"),
+                MapSymbol::new("_start", 0, Section::Text, Binding::Global, "main.o"),
+                MapSymbol::new("print", 0x10, Section::Text, Binding::Global, "lib.a(io.o)"),
+                MapSymbol::new("write", 0x10, Section::Text, Binding::Local, "lib.a(io.o)"),
+            ],
+        )
+    }
+
+    #[test]
+    fn map_one() {
+        let map = map();
+        let symbols = map
+            .symbols
+            .iter()
+            .map(|x| (x.name.as_str(), x.address, x.size))
+            .collect::<Vec<_>>();
+
+        assert_eq!(map.sections[0].kind, Section::Text);
+        assert_eq!(
+            symbols,
+            vec![
+                ("_start", 0, 8),
+                ("loop", 8, 8),
+                ("print", 0x10, 0xc),
+                ("write", 0x10, 0xc),
+                ("value", 0x20, 8),
+            ]
+        );
+        assert_eq!(map.lookup(0xc).map(|x| x.name.as_str()), Some("loop"));
+        assert_eq!(map.lookup(0x1a).map(|x| x.name.as_str()), Some("write"));
+        assert_eq!(map.lookup(0x1c), None);
+    }
+
+    #[test]
+    fn map_two() {
+        let text = map().to_string();
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "Sections:");
+        assert_eq!(lines[2], "  .text     0x00000000  0x0000001c");
+        assert_eq!(lines[5], "Symbols:");
+        assert_eq!(
+            lines[9],
+            "  0x00000010  0x0000000c  .text     global   print   lib.a(io.o)"
+        );
+        assert_eq!(text.parse::<LinkMap>().unwrap(), map());
+    }
+}
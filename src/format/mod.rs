@@ -1,6 +1,9 @@
+mod archive;
 mod binary;
 mod header;
+mod map;
+mod object;
 mod section;
 mod segment;
 
-pub use self::{binary::*, header::*, section::*, segment::*};
+pub use self::{archive::*, binary::*, header::*, map::*, object::*, section::*, segment::*};
@@ -0,0 +1,382 @@
+use crate::{
+    error::EsiuxErrorKind,
+    parser::{FromSlice, IntoSlice, Sliced},
+    Res,
+};
+
+use super::{Section, SegmentHeader, Version};
+
+pub const OBJ_MAGIC: u32 = 0x6a624f45; // "EObj"
+
+/// # Binding
+///
+/// * local symbols are only visible inside their object
+/// * global symbols are defined here and can be referenced by other objects
+/// * extern symbols are referenced here and defined by another object
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Binding {
+    #[default]
+    Local,
+    Global,
+    Extern,
+}
+
+impl TryFrom<u8> for Binding {
+    type Error = EsiuxErrorKind;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Local),
+            1 => Ok(Self::Global),
+            2 => Ok(Self::Extern),
+            _ => Err(EsiuxErrorKind::TryFrom(Box::new(format!(
+                "failed to match symbol binding: {value}"
+            )))),
+        }
+    }
+}
+
+/// # SymbolEntry
+///
+/// * a named address, value is the offset from the start of section
+/// * extern symbols have no section, their value is 0
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SymbolEntry {
+    pub name: String,
+    pub binding: Binding,
+    pub section: Option<Section>,
+    pub value: u32,
+}
+
+impl SymbolEntry {
+    pub fn new(name: &str, binding: Binding, section: Option<Section>, value: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            binding,
+            section,
+            value,
+        }
+    }
+}
+
+/// # RelocKind
+///
+/// * the field a relocation patches once the address of its symbol is known
+/// * Branch is the l20 word offset of a branch, counted from the branch itself
+/// * Imm12 is the l12 immediate of a data processing or load/store instruction
+/// * Word is a 32 bit little endian `.word`
+/// * Lo16 and Hi16 are the l16 immediate of a movw or movt, loaded with one half of the address
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RelocKind {
+    Branch,
+    Imm12,
+    Word,
+    Lo16,
+    Hi16,
+}
+
+impl TryFrom<u8> for RelocKind {
+    type Error = EsiuxErrorKind;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Branch),
+            1 => Ok(Self::Imm12),
+            2 => Ok(Self::Word),
+            3 => Ok(Self::Lo16),
+            4 => Ok(Self::Hi16),
+            _ => Err(EsiuxErrorKind::TryFrom(Box::new(format!(
+                "failed to match relocation kind: {value}"
+            )))),
+        }
+    }
+}
+
+/// # Relocation
+///
+/// * the field at offset in section is patched with the address of symbol plus addend
+/// * symbol is an index into the symbol table of the object
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    pub section: Section,
+    pub offset: u32,
+    pub kind: RelocKind,
+    pub symbol: u32,
+    pub addend: i32,
+}
+
+impl Relocation {
+    /// 16 bytes
+    pub fn size() -> usize {
+        1 + 1 + 2 + 4 + 4 + 4
+    }
+}
+
+/// # EsiuxObj
+///
+/// * a relocatable object, one per assembled file
+/// * sections are placed by the linker, every address in here is relative to its section
+/// * bss only records its size, its bytes are never written
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EsiuxObj {
+    pub version: Version,
+    pub sections: Vec<(Section, Vec<u8>)>,
+    pub symbols: Vec<SymbolEntry>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl EsiuxObj {
+    /// magic, version, section count, symbol count and relocation count
+    pub fn header_size() -> usize {
+        4 + 3 + 1 + 4 + 4
+    }
+
+    pub fn section(&self, kind: Section) -> Option<&[u8]> {
+        self.sections
+            .iter()
+            .find(|(x, _)| *x == kind)
+            .map(|(_, bytes)| bytes.as_slice())
+    }
+
+    /// index of the symbol called name
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        self.symbols.iter().position(|x| x.name == name)
+    }
+}
+
+/// reads an object front to back, running out of bytes is an error
+pub(super) struct Reader<'s> {
+    pub slice: &'s [u8],
+    pub pos: usize,
+}
+
+impl<'s> Reader<'s> {
+    pub fn take(&mut self, len: usize) -> Res<&'s [u8]> {
+        let bytes = self.slice.get(self.pos..self.pos + len).ok_or_else(|| {
+            EsiuxErrorKind::Invalid(
+                "object file length".to_string(),
+                self.pos + len,
+                self.slice.len(),
+            )
+        })?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Res<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Res<u16> {
+        let x = self.take(2)?;
+        Ok(u16::from_le_bytes([x[0], x[1]]))
+    }
+
+    pub fn u32(&mut self) -> Res<u32> {
+        let x = self.take(4)?;
+        Ok(u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+    }
+
+    /// a string prefixed with its u16 length
+    pub fn string(&mut self) -> Res<String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+}
+
+/// writes a string prefixed with its u16 length, see Reader::string
+pub(super) fn put_string(out: &mut Vec<u8>, value: &str) -> Res<()> {
+    let len = u16::try_from(value.len())
+        .map_err(|_| EsiuxErrorKind::TryFrom(Box::new(value.to_string())))?;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn section_from(value: u8) -> Res<Option<Section>> {
+    match value {
+        u8::MAX => Ok(None),
+        x => Ok(Some(Section::try_from(u16::from(x))?)),
+    }
+}
+
+impl FromSlice<EsiuxObj> for EsiuxObj {
+    fn from_slice(slice: &[u8]) -> Res<EsiuxObj> {
+        let mut rd = Reader { slice, pos: 0 };
+
+        let magic = rd.u32()?;
+        if magic != OBJ_MAGIC {
+            return Err(EsiuxErrorKind::Invalid(
+                "object file magic".to_string(),
+                OBJ_MAGIC as usize,
+                magic as usize,
+            ));
+        }
+        let version = rd.take(3)?.as_bytes::<Version>()?;
+        let section_count = rd.u8()?;
+        let symbol_count = rd.u32()?;
+        let relocation_count = rd.u32()?;
+
+        let mut headers = Vec::new();
+        for _ in 0..section_count {
+            headers.push(
+                rd.take(SegmentHeader::size())?
+                    .as_bytes::<SegmentHeader>()?,
+            );
+        }
+        let mut sections = Vec::new();
+        for header in headers {
+            let bytes = match header.kind {
+                Section::Bss => vec![0; header.size as usize],
+                _ => rd.take(header.size as usize)?.to_vec(),
+            };
+            sections.push((header.kind, bytes));
+        }
+
+        let mut symbols = Vec::new();
+        for _ in 0..symbol_count {
+            let binding = Binding::try_from(rd.u8()?)?;
+            let section = section_from(rd.u8()?)?;
+            let len = rd.u16()? as usize;
+            let value = rd.u32()?;
+            let name = String::from_utf8_lossy(rd.take(len)?).to_string();
+            symbols.push(SymbolEntry {
+                name,
+                binding,
+                section,
+                value,
+            });
+        }
+
+        let mut relocations = Vec::new();
+        for _ in 0..relocation_count {
+            let section = Section::try_from(u16::from(rd.u8()?))?;
+            let kind = RelocKind::try_from(rd.u8()?)?;
+            rd.take(2)?;
+            let offset = rd.u32()?;
+            let symbol = rd.u32()?;
+            let addend = rd.u32()? as i32;
+            relocations.push(Relocation {
+                section,
+                offset,
+                kind,
+                symbol,
+                addend,
+            });
+        }
+
+        Ok(Self {
+            version,
+            sections,
+            symbols,
+            relocations,
+        })
+    }
+}
+
+impl IntoSlice for EsiuxObj {
+    fn to_slice(&self) -> Res<Vec<u8>> {
+        let count =
+            |len: usize| u32::try_from(len).map_err(|_| EsiuxErrorKind::TryFrom(Box::new(len)));
+        let section_count = u8::try_from(self.sections.len())
+            .map_err(|_| EsiuxErrorKind::TryFrom(Box::new(self.sections.len())))?;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&OBJ_MAGIC.to_le_bytes());
+        out.extend_from_slice(&self.version.to_slice()?);
+        out.push(section_count);
+        out.extend_from_slice(&count(self.symbols.len())?.to_le_bytes());
+        out.extend_from_slice(&count(self.relocations.len())?.to_le_bytes());
+
+        let mut offset = Self::header_size() + SegmentHeader::size() * self.sections.len();
+        let mut data = Vec::new();
+        for (kind, bytes) in &self.sections {
+            let size = u16::try_from(bytes.len()).map_err(|_| {
+                EsiuxErrorKind::SegmentSize(Box::new(format!("{kind:?}")), bytes.len())
+            })?;
+            out.extend_from_slice(&SegmentHeader::new(size, *kind, offset as u32).to_slice()?);
+            if *kind != Section::Bss {
+                offset += bytes.len();
+                data.extend_from_slice(bytes);
+            }
+        }
+        out.extend_from_slice(&data);
+
+        for sym in &self.symbols {
+            let len = u16::try_from(sym.name.len())
+                .map_err(|_| EsiuxErrorKind::TryFrom(Box::new(sym.name.clone())))?;
+            out.push(sym.binding as u8);
+            out.push(sym.section.map(|x| x as u8).unwrap_or(u8::MAX));
+            out.extend_from_slice(&len.to_le_bytes());
+            out.extend_from_slice(&sym.value.to_le_bytes());
+            out.extend_from_slice(sym.name.as_bytes());
+        }
+
+        for reloc in &self.relocations {
+            out.push(reloc.section as u8);
+            out.push(reloc.kind as u8);
+            out.extend_from_slice(&[0; 2]);
+            out.extend_from_slice(&reloc.offset.to_le_bytes());
+            out.extend_from_slice(&reloc.symbol.to_le_bytes());
+            out.extend_from_slice(&reloc.addend.to_le_bytes());
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        format::Section,
+        parser::{IntoSlice, Sliced},
+    };
+
+    use super::{Binding, EsiuxObj, RelocKind, Relocation, SymbolEntry};
+
+    fn object() -> EsiuxObj {
+        EsiuxObj {
+            sections: vec![
+                (Section::Text, vec![1, 2, 3, 4]),
+                (Section::Data, vec![5, 6, 7, 8]),
+                (Section::Bss, vec![0; 16]),
+            ],
+            symbols: vec![
+                SymbolEntry::new("_start", Binding::Global, Some(Section::Text), 0),
+                SymbolEntry::new("buffer", Binding::Local, Some(Section::Bss), 8),
+                SymbolEntry::new("print", Binding::Extern, None, 0),
+            ],
+            relocations: vec![Relocation {
+                section: Section::Text,
+                offset: 0,
+                kind: RelocKind::Branch,
+                symbol: 2,
+                addend: -4,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn obj_one() {
+        let bytes = object().to_slice().unwrap();
+
+        // header, 3 section headers, 8 bytes of data, 3 symbols and a relocation
+        assert_eq!(bytes.len(), 16 + 3 * 8 + 8 + (8 * 3 + 6 + 6 + 5) + 16);
+        assert_eq!(bytes[..4], [0x45, 0x4f, 0x62, 0x6a]);
+        assert_eq!(bytes.as_slice().as_bytes::<EsiuxObj>().unwrap(), object());
+    }
+
+    #[test]
+    fn obj_two() {
+        let bytes = object().to_slice().unwrap();
+
+        assert!(bytes[..bytes.len() - 1].as_bytes::<EsiuxObj>().is_err());
+        assert!(bytes[4..].as_bytes::<EsiuxObj>().is_err());
+    }
+}
@@ -1,6 +1,6 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Section {
     Data,
     #[default]
@@ -10,6 +10,34 @@ pub enum Section {
     // Comment,
 }
 
+impl Section {
+    /// # LAYOUT
+    ///
+    /// * order sections are placed in memory, text starts at address 0
+    /// * bss comes last so it needs no bytes in the file
+    ///
+    pub const LAYOUT: [Section; 4] = [Self::Text, Self::Rodata, Self::Data, Self::Bss];
+
+    /// position of the section in LAYOUT
+    pub fn order(self) -> usize {
+        Self::LAYOUT
+            .iter()
+            .position(|x| *x == self)
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Data => write!(f, "data"),
+            Self::Text => write!(f, "text"),
+            Self::Rodata => write!(f, "rodata"),
+            Self::Bss => write!(f, "bss"),
+        }
+    }
+}
+
 impl FromStr for Section {
     type Err = crate::error::EsiuxErrorKind;
 
@@ -20,9 +48,9 @@ impl FromStr for Section {
             "rodata" => Ok(Self::Rodata),
             "bss" => Ok(Self::Bss),
             // "comment" => Ok(Self::Comment),
-            _ => Err(crate::error::EsiuxErrorKind::FromStr(Box::new(
-                "failed to parse segment: {s}",
-            ))),
+            _ => Err(crate::error::EsiuxErrorKind::FromStr(Box::new(format!(
+                "failed to parse segment: {s}"
+            )))),
         }
     }
 }
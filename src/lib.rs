@@ -1,8 +1,10 @@
 #[macro_use]
 mod macros;
 pub mod assembly;
+pub mod disassembly;
 pub mod error;
 pub mod format;
+pub mod linker;
 pub mod machine;
 pub mod memory;
 pub mod parser;
@@ -0,0 +1,518 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+use crate::{
+    error::EsiuxErrorKind,
+    format::{
+        Binding, EsiuxArchive, EsiuxBin, EsiuxObj, LinkMap, MapSection, MapSymbol, Section,
+        SymbolEntry, ARCHIVE_MAGIC,
+    },
+    parser::Sliced,
+    Res,
+};
+
+use super::patch;
+
+/// symbol execution starts at unless told otherwise
+pub const DEFAULT_ENTRY: &str = "_start";
+
+/// # Linker
+///
+/// * combines objects into an executable EsiuxBin
+/// * sections of the same kind are merged in the order the objects were added, each
+///   part word aligned, and the kinds are placed in Section::LAYOUT order
+/// * a section without a base follows the one before it
+/// * members of archives are only linked if they define a symbol that is needed
+///
+#[derive(Debug, Default)]
+pub struct Linker {
+    // objects to link and the files they came from
+    pub objects: Vec<(String, EsiuxObj)>,
+    // archives to pull members from, searched in order
+    pub archives: Vec<(String, EsiuxArchive)>,
+    pub bases: HashMap<Section, u32>,
+    // DEFAULT_ENTRY if not given
+    pub entry: Option<String>,
+    // addresses of the global symbols, filled in by link
+    pub symbols: HashMap<String, u32>,
+    // sections and defined symbols of the executable, filled in by link
+    pub map: LinkMap,
+    pub diagnostics: Vec<EsiuxErrorKind>,
+}
+
+/// where the part of every section an object brings starts
+type Places = HashMap<Section, u32>;
+
+/// the bytes of one kind of section in the executable
+struct Merged {
+    kind: Section,
+    address: u32,
+    bytes: Vec<u8>,
+}
+
+impl Linker {
+    /// reads an object or archive file and adds it to the link
+    pub fn add(&mut self, path: &str) -> Res<()> {
+        let in_file = |e| EsiuxErrorKind::InFile(Box::new(e), path.to_string());
+        let bytes = fs::read(path).map_err(|e| in_file(e.into()))?;
+        if bytes.starts_with(&ARCHIVE_MAGIC.to_le_bytes()) {
+            let ar = bytes
+                .as_slice()
+                .as_bytes::<EsiuxArchive>()
+                .map_err(in_file)?;
+            self.add_archive(path, ar);
+        } else {
+            let obj = bytes.as_slice().as_bytes::<EsiuxObj>().map_err(in_file)?;
+            self.add_object(path, obj);
+        }
+        Ok(())
+    }
+
+    /// adds an archive that is already in memory, name is used to report errors
+    pub fn add_archive(&mut self, name: &str, ar: EsiuxArchive) {
+        self.archives.push((name.to_string(), ar));
+    }
+
+    /// adds an object that is already in memory, name is used to report errors
+    pub fn add_object(&mut self, name: &str, obj: EsiuxObj) {
+        self.objects.push((name.to_string(), obj));
+    }
+
+    /// places section at address instead of after the section before it
+    pub fn base(&mut self, section: Section, address: u32) {
+        self.bases.insert(section, address);
+    }
+
+    /// # select
+    ///
+    /// * adds the archive members that define a symbol the objects use but do not define
+    /// * a member can need symbols of its own, members are added until none is missing
+    ///   that an archive defines
+    /// * a member is named `archive(member)` in errors
+    ///
+    fn select(&mut self) {
+        loop {
+            let defined = self
+                .objects
+                .iter()
+                .flat_map(|(_, obj)| &obj.symbols)
+                .filter(|x| x.binding == Binding::Global)
+                .map(|x| x.name.as_str())
+                .collect::<HashSet<_>>();
+            let needed = self
+                .objects
+                .iter()
+                .flat_map(|(_, obj)| &obj.symbols)
+                .filter(|x| x.binding == Binding::Extern && !defined.contains(x.name.as_str()));
+
+            let mut found = None;
+            for sym in needed {
+                found = self.archives.iter().find_map(|(path, ar)| {
+                    let member = &ar.members[ar.member(&sym.name)?];
+                    Some((format!("{path}({})", member.name), member.object.clone()))
+                });
+                if found.is_some() {
+                    break;
+                }
+            }
+
+            match found {
+                Some((name, obj)) => self.add_object(&name, obj),
+                None => break,
+            }
+        }
+    }
+
+    /// # layout
+    ///
+    /// * merges the sections of every object and picks their addresses
+    /// * returns the merged sections in Section::LAYOUT order and, for each object,
+    ///   where its part of every section starts
+    ///
+    fn layout(&self) -> Res<(Vec<Merged>, Vec<Places>)> {
+        let mut places = vec![HashMap::new(); self.objects.len()];
+        let mut merged = Vec::new();
+        let mut end = 0u32;
+
+        for kind in Section::LAYOUT {
+            let address = match self.bases.get(&kind) {
+                Some(&base) if base < end => {
+                    return Err(EsiuxErrorKind::SectionOverlap(kind.to_string(), base, end));
+                }
+                Some(&base) => base,
+                None => end.next_multiple_of(4),
+            };
+
+            let mut bytes = Vec::new();
+            for (i, (_, obj)) in self.objects.iter().enumerate() {
+                bytes.resize(bytes.len().next_multiple_of(4), 0);
+                places[i].insert(kind, address + bytes.len() as u32);
+                bytes.extend_from_slice(obj.section(kind).unwrap_or_default());
+            }
+
+            end = address + bytes.len() as u32;
+            merged.push(Merged {
+                kind,
+                address,
+                bytes,
+            });
+        }
+
+        Ok((merged, places))
+    }
+
+    /// # map
+    ///
+    /// * the sections that make it into the executable and every symbol an object defines
+    /// * the symbols naming a section are left out, the section is listed instead
+    ///
+    fn map(&self, merged: &[Merged], places: &[Places]) -> LinkMap {
+        let sections = merged
+            .iter()
+            .filter(|x| !x.bytes.is_empty() || x.kind == Section::Text)
+            .map(|x| MapSection {
+                kind: x.kind,
+                address: x.address,
+                size: x.bytes.len() as u32,
+            })
+            .collect();
+
+        let mut symbols = Vec::new();
+        for (i, (path, obj)) in self.objects.iter().enumerate() {
+            for sym in &obj.symbols {
+                let Some(section) = sym.section else {
+                    continue;
+                };
+                if sym.binding == Binding::Extern || sym.name == format!(".{section}") {
+                    continue;
+                }
+                let address = places[i][&section] + sym.value;
+                symbols.push(MapSymbol::new(
+                    &sym.name,
+                    address,
+                    section,
+                    sym.binding,
+                    path,
+                ));
+            }
+        }
+
+        LinkMap::new(sections, symbols)
+    }
+
+    /// renders collected errors into a single Diagnostics error
+    fn check(&mut self, errors: Vec<EsiuxErrorKind>) -> Res<()> {
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let rendered = errors
+            .iter()
+            .map(|x| format!("error: {x}\n"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let count = errors.len();
+        self.diagnostics = errors;
+        Err(EsiuxErrorKind::Diagnostics(
+            Box::new(format!("{rendered}\n")),
+            count,
+        ))
+    }
+
+    /// # link
+    ///
+    /// * pulls in the archive members that are needed, see select
+    /// * lays out the sections, resolves extern symbols against the globals of every
+    ///   object and applies the relocations
+    /// * a global defined twice and an extern nobody defines are reported with the files
+    ///   involved, all errors are collected into diagnostics
+    /// * the entry point of the executable is the global symbol entry
+    /// * map lists where every section and symbol ended up
+    ///
+    pub fn link(&mut self) -> Res<EsiuxBin> {
+        self.select();
+        let (mut merged, places) = self.layout()?;
+        let mut errors = Vec::new();
+
+        let mut globals = HashMap::<&str, (usize, u32)>::new();
+        for (i, (path, obj)) in self.objects.iter().enumerate() {
+            for sym in obj.symbols.iter().filter(|x| x.binding == Binding::Global) {
+                let address = sym.section.map(|x| places[i][&x]).unwrap_or_default() + sym.value;
+                match globals.get(sym.name.as_str()) {
+                    Some((first, _)) => errors.push(EsiuxErrorKind::DuplicateSymbol(
+                        sym.name.clone(),
+                        self.objects[*first].0.clone(),
+                        path.clone(),
+                    )),
+                    None => {
+                        globals.insert(&sym.name, (i, address));
+                    }
+                }
+            }
+        }
+
+        let address = |i: usize, sym: &SymbolEntry| match (sym.binding, sym.section) {
+            (Binding::Extern, _) | (_, None) => globals.get(sym.name.as_str()).map(|x| x.1),
+            (_, Some(section)) => Some(places[i][&section] + sym.value),
+        };
+
+        let mut unresolved = HashSet::new();
+        for (i, (path, obj)) in self.objects.iter().enumerate() {
+            let in_file = |e: EsiuxErrorKind| EsiuxErrorKind::InFile(Box::new(e), path.clone());
+
+            for reloc in &obj.relocations {
+                let Some(sym) = obj.symbols.get(reloc.symbol as usize) else {
+                    errors.push(in_file(EsiuxErrorKind::Invalid(
+                        "relocation symbol".to_string(),
+                        obj.symbols.len(),
+                        reloc.symbol as usize,
+                    )));
+                    continue;
+                };
+                let Some(value) = address(i, sym) else {
+                    if unresolved.insert((sym.name.as_str(), i)) {
+                        errors.push(EsiuxErrorKind::UnresolvedSymbol(
+                            sym.name.clone(),
+                            path.clone(),
+                        ));
+                    }
+                    continue;
+                };
+
+                let place = places[i][&reloc.section] + reloc.offset;
+                let section = &mut merged[reloc.section.order()];
+                let value = i64::from(value) + i64::from(reloc.addend);
+                if let Err(e) = patch(
+                    &mut section.bytes,
+                    reloc.kind,
+                    place - section.address,
+                    value,
+                    place,
+                ) {
+                    errors.push(in_file(e));
+                }
+            }
+        }
+
+        let entry = self.entry.as_deref().unwrap_or(DEFAULT_ENTRY);
+        let start = match globals.get(entry) {
+            Some((_, address)) => *address,
+            None => {
+                errors.push(EsiuxErrorKind::UndefinedEntry(entry.to_string()));
+                0
+            }
+        };
+
+        let symbols = globals
+            .iter()
+            .map(|(name, (_, address))| (name.to_string(), *address))
+            .collect();
+        self.check(errors)?;
+        self.symbols = symbols;
+        self.map = self.map(&merged, &places);
+
+        let segments = merged
+            .into_iter()
+            .filter(|x| !x.bytes.is_empty() || x.kind == Section::Text)
+            .map(|x| (x.kind, x.address, x.bytes))
+            .collect();
+        EsiuxBin::from_placed(start, segments)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembly::Assembler,
+        format::{Binding, EsiuxArchive, EsiuxObj, Section},
+        parser::ToNum,
+        processor::Instruction,
+    };
+
+    use super::Linker;
+
+    fn object(source: &str) -> EsiuxObj {
+        let mut asm = Assembler {
+            source: source.to_string(),
+            ..Default::default()
+        };
+        asm.object().unwrap()
+    }
+
+    fn word(ins: &str) -> [u8; 4] {
+        ins.parse::<Instruction>().unwrap().mask().to_le_bytes()
+    }
+
+    #[test]
+    fn link_one() {
+        let mut ld = Linker::default();
+        ld.add_object(
+            "main.o",
+            object(".global _start, done, value\n.extern helper\n_start:\n\tb helper\ndone:\n\tsvc #0xf0\n.data\nvalue:\n\t.word 7\n"),
+        );
+        ld.add_object(
+            "helper.o",
+            object(".global helper\n.extern done, value\nhelper:\n\tmov r1, #value\n\tb done\n.data\n\t.word helper\n"),
+        );
+        ld.base(Section::Data, 0x40);
+
+        let bin = ld.link().unwrap();
+
+        let mut data = [
+            word("b #2"),
+            word("svc #0xf0"),
+            word("mov r1, #0x40"),
+            word("b #-2"),
+        ]
+        .concat();
+        data.resize(0x40, 0);
+        data.extend_from_slice(&[7, 0, 0, 0, 8, 0, 0, 0]);
+
+        assert_eq!(bin.header.entry, 0);
+        assert_eq!(bin.data, data);
+        assert_eq!(ld.symbols["helper"], 8);
+        assert_eq!(ld.symbols["done"], 4);
+    }
+
+    #[test]
+    fn link_two() {
+        let mut ld = Linker::default();
+        ld.add_object(
+            "a.o",
+            object(".global main\n.extern missing\nmain:\n\tb missing\n\tb missing\n"),
+        );
+        ld.add_object("b.o", object(".global main\nmain:\n\tmov r1, #1\n"));
+        ld.entry = Some("begin".to_string());
+
+        ld.link().unwrap_err();
+        let errors = ld
+            .diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Symbol main is defined in both a.o and b.o",
+                "Undefined symbol missing referenced in a.o",
+                "Entry point begin is not defined by any object",
+            ]
+        );
+    }
+
+    #[test]
+    fn link_three() {
+        let mut ld = Linker::default();
+        ld.add_object(
+            "a.o",
+            object(".global _start\n_start:\n\tmov r1, #1\n\tmov r1, #1\n"),
+        );
+        ld.base(Section::Text, 0x10);
+        ld.base(Section::Data, 0x14);
+
+        assert_eq!(
+            ld.link().unwrap_err().to_string(),
+            "Section .data at 0x14 overlaps the sections before it, which end at 0x18"
+        );
+    }
+
+    #[test]
+    fn link_four() {
+        let mut ar = EsiuxArchive::default();
+        ar.add(
+            "string.o",
+            object(".global strlen\n.extern square\nstrlen:\n\tb square\n"),
+        );
+        ar.add("math.o", object(".global square\nsquare:\n\tmov r1, #2\n"));
+        ar.add("io.o", object(".global print\nprint:\n\tsvc #0xe0\n"));
+
+        let mut ld = Linker::default();
+        ld.add_archive("lib.a", ar);
+        ld.add_object(
+            "main.o",
+            object(".global _start\n.extern strlen\n_start:\n\tb strlen\n"),
+        );
+
+        let bin = ld.link().unwrap();
+        let names = ld
+            .objects
+            .iter()
+            .map(|(x, _)| x.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["main.o", "lib.a(string.o)", "lib.a(math.o)"]);
+        assert_eq!(
+            bin.data,
+            [word("b #1"), word("b #1"), word("mov r1, #2")].concat()
+        );
+        assert!(!ld.symbols.contains_key("print"));
+    }
+
+    #[test]
+    fn link_five() {
+        let mut ar = EsiuxArchive::default();
+        ar.add(
+            "io.o",
+            object(
+                ".global print
+print:
+	mov r8, #0xe0
+write:
+	svc #0xe0
+",
+            ),
+        );
+
+        let mut ld = Linker::default();
+        ld.add_archive("lib.a", ar);
+        ld.add_object(
+            "main.o",
+            object(
+                ".global _start
+.extern print
+_start:
+	b print
+.data
+value:
+	.word 7
+",
+            ),
+        );
+        ld.link().unwrap();
+
+        let sections = ld
+            .map
+            .sections
+            .iter()
+            .map(|x| (x.kind, x.address, x.size))
+            .collect::<Vec<_>>();
+        let symbols = ld
+            .map
+            .symbols
+            .iter()
+            .map(|x| {
+                (
+                    x.name.as_str(),
+                    x.address,
+                    x.size,
+                    x.binding,
+                    x.file.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(sections, [(Section::Text, 0, 0xc), (Section::Data, 0xc, 4)]);
+        assert_eq!(
+            symbols,
+            [
+                ("_start", 0, 4, Binding::Global, "main.o"),
+                ("print", 4, 4, Binding::Global, "lib.a(io.o)"),
+                ("write", 8, 4, Binding::Local, "lib.a(io.o)"),
+                ("value", 0xc, 4, Binding::Local, "main.o"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,4 @@
+mod link;
+mod relocate;
+
+pub use self::{link::*, relocate::*};
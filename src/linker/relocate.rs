@@ -0,0 +1,152 @@
+use crate::{
+    error::EsiuxErrorKind,
+    format::RelocKind,
+    types::{l12, l20},
+    Res,
+};
+
+/// instruction types of the loads and stores
+const LSI_TYPES: [u32; 2] = [0x3, 0x6];
+
+/// # patch
+///
+/// * applies a relocation to the word at offset in bytes
+/// * value is the address of the symbol plus the addend, place is the address of the word
+/// * a branch gets the signed word offset from place to value, see BRI::target
+///
+pub fn patch(bytes: &mut [u8], kind: RelocKind, offset: u32, value: i64, place: u32) -> Res<()> {
+    let len = bytes.len();
+    let at = offset as usize;
+    let field = bytes
+        .get_mut(at..at + 4)
+        .ok_or_else(|| EsiuxErrorKind::Invalid("relocation offset".to_string(), at + 4, len))?;
+    let word = u32::from_le_bytes([field[0], field[1], field[2], field[3]]);
+
+    let word = match kind {
+        RelocKind::Word => value as u32,
+        // a load or store encodes the magnitude of its offset and the sign in the negative bit
+        RelocKind::Imm12 if LSI_TYPES.contains(&((word >> 4) & 0b111)) => {
+            if !(-l12::UMAX..=l12::UMAX).contains(&value) {
+                return Err(EsiuxErrorKind::ImmRange(
+                    value,
+                    "offset".to_string(),
+                    -l12::UMAX,
+                    l12::UMAX,
+                ));
+            }
+            let negative = u32::from(value < 0) << 9;
+            (word & 0x000f_fdff) | negative | ((value.unsigned_abs() as u32) << 20)
+        }
+        // the bounds the assembler checks, the immediate of a data processing op is sign extended
+        RelocKind::Imm12 => {
+            if !(l12::MIN..=l12::MAX).contains(&value) {
+                return Err(EsiuxErrorKind::ImmRange(
+                    value,
+                    "l12".to_string(),
+                    l12::MIN,
+                    l12::MAX,
+                ));
+            }
+            (word & 0x000f_ffff) | ((value as u32 & 0xfff) << 20)
+        }
+        RelocKind::Lo16 => (word & 0xffff) | ((value as u32) << 16),
+        RelocKind::Hi16 => (word & 0xffff) | ((value as u32) & 0xffff_0000),
+        RelocKind::Branch => {
+            let distance = value - i64::from(place);
+            if distance % 4 != 0 {
+                return Err(EsiuxErrorKind::BranchAlignment(value));
+            }
+            let (min, max) = (l20::MIN, l20::MAX >> 1);
+            if !(min..=max).contains(&(distance / 4)) {
+                return Err(EsiuxErrorKind::BranchRange(value, distance / 4, min, max));
+            }
+            (word & 0xfff) | (((distance / 4) as u32 & 0xfffff) << 12)
+        }
+    };
+
+    field.copy_from_slice(&word.to_le_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{format::RelocKind, parser::ToNum, processor::Instruction};
+
+    use super::patch;
+
+    fn word(ins: &str) -> [u8; 4] {
+        ins.parse::<Instruction>().unwrap().mask().to_le_bytes()
+    }
+
+    #[test]
+    fn patch_one() {
+        let mut bytes = [word("b #0"), word("mov r1, #0"), [0; 4]].concat();
+
+        patch(&mut bytes, RelocKind::Branch, 0, 0x100, 0x10c).unwrap();
+        patch(&mut bytes, RelocKind::Imm12, 4, 0x120, 0x110).unwrap();
+        patch(&mut bytes, RelocKind::Word, 8, 0xdead_beef, 0x114).unwrap();
+
+        assert_eq!(
+            bytes,
+            [
+                word("b #-3"),
+                word("mov r1, #0x120"),
+                0xdead_beefu32.to_le_bytes()
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn patch_two() {
+        let mut bytes = [word("b #0"), word("mov r1, #0")].concat();
+
+        assert!(patch(&mut bytes, RelocKind::Branch, 0, 0x102, 0).is_err());
+        assert!(patch(&mut bytes, RelocKind::Branch, 0, 0x400000, 0).is_err());
+        assert!(patch(&mut bytes, RelocKind::Imm12, 4, 0x1000, 0).is_err());
+        assert!(patch(&mut bytes, RelocKind::Word, 8, 0, 0).is_err());
+    }
+
+    #[test]
+    fn patch_three() {
+        let mut bytes = [word("movw r1, #0"), word("movt r1, #0")].concat();
+
+        patch(&mut bytes, RelocKind::Lo16, 0, 0x1234_5678, 0).unwrap();
+        patch(&mut bytes, RelocKind::Hi16, 4, 0x1234_5678, 4).unwrap();
+
+        assert_eq!(
+            bytes,
+            [word("movw r1, #0x5678"), word("movt r1, #0x1234")].concat()
+        );
+    }
+
+    #[test]
+    fn patch_four() {
+        let mut bytes = [word("ldr r1, [r2, #4]"), word("str r1, [r2], #0")].concat();
+
+        patch(&mut bytes, RelocKind::Imm12, 0, -8, 0).unwrap();
+        patch(&mut bytes, RelocKind::Imm12, 4, 0xfff, 4).unwrap();
+
+        assert_eq!(
+            bytes,
+            [word("ldr r1, [r2, #-8]"), word("str r1, [r2], #0xfff")].concat()
+        );
+        assert!(patch(&mut bytes, RelocKind::Imm12, 0, -0x1000, 0).is_err());
+    }
+
+    #[test]
+    fn patch_five() {
+        let mut bytes = [word("mov r1, #0"), word("add r1, r1, #0")].concat();
+
+        // the immediate is sign extended, 0x800 would run as -0x800
+        assert!(patch(&mut bytes, RelocKind::Imm12, 0, 0x800, 0).is_err());
+        assert!(patch(&mut bytes, RelocKind::Imm12, 0, -0x801, 0).is_err());
+        patch(&mut bytes, RelocKind::Imm12, 0, 0x7ff, 0).unwrap();
+        patch(&mut bytes, RelocKind::Imm12, 4, -0x800, 4).unwrap();
+
+        assert_eq!(
+            bytes,
+            [word("mov r1, #0x7ff"), word("add r1, r1, #-0x800")].concat()
+        );
+    }
+}
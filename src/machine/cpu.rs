@@ -1,8 +1,10 @@
 use std::collections::HashMap;
 
 use crate::{
+    error::EsiuxErrorKind,
+    format::EsiuxBin,
     memory::{Addressable, LineMem},
-    processor::{CPSRflags, Instruction, Register, BRI, DPI, SCI},
+    processor::{CPSRflags, Instruction, Op, Register, SCI},
     types::Operand,
     Res,
 };
@@ -27,7 +29,7 @@ impl Default for Cpu {
             core: CpuCore {
                 registers: [0u32; 16],
                 flags: CPSRflags::default(),
-                memory: Box::new(LineMem::new(0x1000)),
+                memory: Box::new(LineMem::new(Self::MEMORY_SIZE)),
                 state: false,
             },
             interrupt_table: HashMap::new(),
@@ -36,6 +38,9 @@ impl Default for Cpu {
 }
 
 impl Cpu {
+    /// bytes of memory, programs and every segment of them have to fit in it
+    pub const MEMORY_SIZE: usize = 0x1000;
+
     pub(crate) fn is_halted(&self) -> bool {
         self.core.state
     }
@@ -54,7 +59,7 @@ impl Cpu {
 
     pub fn reset(&mut self) {
         self.core.registers = Default::default();
-        self.core.memory = Box::new(LineMem::new(0x1000));
+        self.core.memory = Box::new(LineMem::new(Self::MEMORY_SIZE));
         self.core.state = false;
     }
 
@@ -95,86 +100,242 @@ impl Cpu {
     }
 
     pub fn load_program(&mut self, program: &[u8], addr: u32) -> Res<()> {
+        if addr as usize + program.len() > Self::MEMORY_SIZE {
+            return Err(EsiuxErrorKind::ProgramSize(
+                program.len(),
+                addr,
+                Self::MEMORY_SIZE,
+            ));
+        }
         for (idx, &byte) in program.iter().enumerate() {
             self.core.memory.write_u8(addr + idx as u32, byte)?;
         }
         Ok(())
     }
 
+    /// # load_bin
+    ///
+    /// * loads the packed segment data of an EsiuxBin at address 0
+    /// * sets the program counter to the header entry point
+    ///
+    pub fn load_bin(&mut self, bin: &EsiuxBin) -> Res<()> {
+        self.load_program(&bin.data, 0)?;
+        let entry = bin.header.entry;
+        self.register(Register::PC, |_| entry);
+        Ok(())
+    }
+
+    /// # operand
+    ///
+    /// * value of the last operand of a data processing instruction
+    /// * immediates are sign extended, the assembler and linker only accept -0x800..=0x7ff
+    ///   so every immediate runs as the value it was written as
+    ///
+    fn operand(&mut self, operand: Operand) -> u32 {
+        match operand {
+            Operand::Reg(r) => self.register(r, |x| x),
+            Operand::Imm(imm) => imm.as_signed() as u32,
+        }
+    }
+
+    /// # alu
+    ///
+    /// * result of a data processing operation on the value of rn and the operand
+    /// * add, sub and mul wrap around on overflow
+    /// * div is signed and rounds towards zero, dividing by zero gives 0 and
+    ///   i32::MIN / -1 wraps to i32::MIN
+    /// * lsl and lsr shift by the low byte of the operand, 32 and more clears every bit
+    ///
+    fn alu(op: Op, value: u32, rm: u32) -> u32 {
+        let shift = rm & 0xff;
+        match op {
+            Op::Add => value.wrapping_add(rm),
+            Op::Sub => value.wrapping_sub(rm),
+            Op::Mul => value.wrapping_mul(rm),
+            Op::Div => match rm {
+                0 => 0,
+                _ => (value as i32).wrapping_div(rm as i32) as u32,
+            },
+            Op::And => value & rm,
+            Op::Or => value | rm,
+            Op::Lsl => value.checked_shl(shift).unwrap_or(0),
+            Op::Lsr => value.checked_shr(shift).unwrap_or(0),
+            _ => unreachable!("{op} is not a data processing operation"),
+        }
+    }
+
+    /// # set_flags
+    ///
+    /// * updates the flags after res = value op rm, for cmp and the `s` forms
+    /// * N and Z follow the result
+    /// * add sets C on an unsigned carry out and sub on no borrow, value >= rm, both set V
+    ///   when the signed result does not fit in 32 bits
+    /// * lsl and lsr set C to the last bit shifted out, shifting by 0 keeps C and by more
+    ///   than 32 clears it
+    /// * the other operations keep C and V
+    ///
+    fn set_flags(&mut self, op: Op, value: u32, rm: u32, res: u32) {
+        let flags = &mut self.core.flags;
+        flags.set_negative(res >> 31 == 1);
+        flags.set_zero(res == 0);
+
+        let shift = rm & 0xff;
+        match op {
+            Op::Add => {
+                flags.set_carry(res < value);
+                flags.set_overflow(((value ^ res) & (rm ^ res)) >> 31 == 1);
+            }
+            Op::Sub | Op::Cmp => {
+                flags.set_carry(value >= rm);
+                flags.set_overflow(((value ^ rm) & (value ^ res)) >> 31 == 1);
+            }
+            Op::Lsl if shift > 0 => {
+                flags.set_carry(shift <= 32 && (value >> (32 - shift)) & 1 == 1);
+            }
+            Op::Lsr if shift > 0 => {
+                flags.set_carry(shift <= 32 && (value >> (shift - 1)) & 1 == 1);
+            }
+            _ => {}
+        }
+    }
+
     pub fn step(&mut self) -> Res<()> {
         let pc = self.register(Register::PC, |x| x + 4) - 4;
         let byte_code = self.core.memory.read_u32(pc)?;
 
         let instruction = Instruction::try_from(byte_code)?;
         match instruction {
-            Instruction::Add(DPI {
-                cond,
-                rn,
-                rd,
-                operand,
-                ..
-            }) => {
-                if !self.core.flags.validate(cond) {
+            Instruction::Add(dpi)
+            | Instruction::Sub(dpi)
+            | Instruction::Mul(dpi)
+            | Instruction::Div(dpi)
+            | Instruction::And(dpi)
+            | Instruction::Or(dpi)
+            | Instruction::Lsl(dpi)
+            | Instruction::Lsr(dpi) => {
+                if !self.core.flags.validate(dpi.cond) {
                     return Ok(());
                 }
 
-                let value = self.register(rn, |x| x);
-                let rm = match operand {
-                    Operand::Reg(r) => self.register(r, |x| x),
-                    Operand::Imm(imm) => imm.as_signed() as u32,
-                };
-                let res = value.wrapping_add(rm);
-                self.register(rd, |_| res);
+                let value = self.register(dpi.rn, |x| x);
+                let rm = self.operand(dpi.operand);
+                let res = Self::alu(dpi.opcode, value, rm);
+                self.register(dpi.rd, |_| res);
+                if dpi.set_flags {
+                    self.set_flags(dpi.opcode, value, rm, res);
+                }
 
                 Ok(())
             }
-            Instruction::Mov(DPI {
-                cond, rd, operand, ..
-            }) => {
-                if !self.core.flags.validate(cond) {
+            Instruction::Mov(dpi) => {
+                if !self.core.flags.validate(dpi.cond) {
                     return Ok(());
                 }
 
-                let imm = match operand {
-                    Operand::Imm(imm) => imm.as_signed() as u32,
-                    _ => unreachable!("shudnt be here coz mov doesnt accept register as rm"),
-                };
+                let value = self.operand(dpi.operand);
+                self.register(dpi.rd, |_| value);
+                if dpi.set_flags {
+                    self.set_flags(Op::Mov, value, value, value);
+                }
+
+                Ok(())
+            }
+            // cmp has no rn, it compares rd with the sign extended operand like sub does
+            Instruction::Cmp(dpi) => {
+                if !self.core.flags.validate(dpi.cond) {
+                    return Ok(());
+                }
 
-                self.register(rd, |_| imm);
+                let value = self.register(dpi.rd, |x| x);
+                let rm = self.operand(dpi.operand);
+                self.set_flags(Op::Cmp, value, rm, value.wrapping_sub(rm));
 
                 Ok(())
             }
-            Instruction::Cmp(DPI {
-                cond, rd, operand, ..
-            }) => {
-                if !self.core.flags.validate(cond) {
+            Instruction::Branch(bri)
+            | Instruction::Bl(bri)
+            | Instruction::Br(bri)
+            | Instruction::Blr(bri) => {
+                if !self.core.flags.validate(bri.cond) {
                     return Ok(());
                 }
 
-                let rd_value = self.register(rd, |x| x);
-                let rn_value = match operand {
-                    Operand::Reg(r) => self.register(r, |x| x),
-                    Operand::Imm(i) => i.value as u32,
+                // the target is read before lr is written, `blr lr` jumps to the old lr
+                let target = match bri.register() {
+                    Some(rn) => self.register(rn, |x| x),
+                    None => bri.target(pc),
                 };
+                // a call returns to the instruction after it
+                if matches!(bri.opcode, Op::Bl | Op::Blr) {
+                    self.register(Register::LR, |_| pc.wrapping_add(4));
+                }
+                self.register(Register::PC, |_| target);
 
-                let res = rd_value.wrapping_sub(rn_value);
+                Ok(())
+            }
+            Instruction::Movw(wdi) | Instruction::Movt(wdi) => {
+                if !self.core.flags.validate(wdi.cond) {
+                    return Ok(());
+                }
 
-                self.core.flags.set_negative(res >> 31 == 1);
-                self.core.flags.set_zero(res == 0);
-                self.core.flags.set_carry(rd_value >= rn_value);
-                self.core.flags.set_overflow(
-                    (rn_value >> 31 == rd_value >> 31) && (res >> 31 != rd_value >> 31),
-                );
+                let imm = u32::from(wdi.imm.value);
+                match wdi.opcode {
+                    Op::Movw => self.register(wdi.rd, |_| imm),
+                    _ => self.register(wdi.rd, |x| (x & 0xffff) | (imm << 16)),
+                };
 
                 Ok(())
             }
-            Instruction::Branch(BRI { cond, offset, .. }) => {
-                if !self.core.flags.validate(cond) {
+            Instruction::Ldr(lsi)
+            | Instruction::Str(lsi)
+            | Instruction::Ldrh(lsi)
+            | Instruction::Strh(lsi)
+            | Instruction::Ldrb(lsi)
+            | Instruction::Strb(lsi)
+            | Instruction::Ldrsb(lsi)
+            | Instruction::Ldrsh(lsi) => {
+                if !self.core.flags.validate(lsi.cond) {
                     return Ok(());
                 }
 
-                let offset = offset.value;
-                self.register(Register::PC, |_| offset);
+                // the pc reads as the next instruction, a pc relative load counts from there
+                let base = self.register(lsi.rn, |x| x);
+                let offset = u32::from(lsi.offset.value);
+                let moved = match lsi.negative {
+                    true => base.wrapping_sub(offset),
+                    false => base.wrapping_add(offset),
+                };
+                let address = if lsi.index { moved } else { base };
+                // a store writes rd as it was before rn is written back
+                let value = self.register(lsi.rd, |x| x);
+                if lsi.write_back {
+                    self.register(lsi.rn, |_| moved);
+                }
+
+                // bytes and halves are zero extended, ldrsb and ldrsh extend their sign
+                let memory = &mut self.core.memory;
+                let loaded = match lsi.load_store {
+                    Op::Str => {
+                        memory.write_u32(address, value)?;
+                        None
+                    }
+                    Op::Strh => {
+                        memory.write_u16(address, value as u16)?;
+                        None
+                    }
+                    Op::Strb => {
+                        memory.write_u8(address, value as u8)?;
+                        None
+                    }
+                    Op::Ldrh => Some(u32::from(memory.read_u16(address)?)),
+                    Op::Ldrb => Some(u32::from(memory.read_u8(address)?)),
+                    Op::Ldrsh => Some(memory.read_u16(address)? as i16 as u32),
+                    Op::Ldrsb => Some(memory.read_u8(address)? as i8 as u32),
+                    _ => Some(memory.read_u32(address)?),
+                };
+                if let Some(loaded) = loaded {
+                    self.register(lsi.rd, |_| loaded);
+                }
 
                 Ok(())
             }
@@ -194,7 +355,292 @@ impl Cpu {
 
                 Ok(())
             }
-            _ => todo!(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembly::Assembler,
+        error::EsiuxErrorKind,
+        machine::halt,
+        processor::{Instruction, Op, Register},
+    };
+
+    use super::Cpu;
+
+    /// assembles source and runs it until it halts
+    fn run(source: &str) -> Cpu {
+        let mut asm = Assembler {
+            source: format!("{source}\n\tsvc #0xf0\n"),
+            ..Default::default()
+        };
+        let bin = asm.assemble().unwrap();
+        let mut vm = Cpu::default();
+        vm.define_interrupt(0xf0, halt);
+        vm.load_bin(&bin).unwrap();
+        vm.execute().unwrap();
+        vm
+    }
+
+    fn registers(vm: &Cpu, registers: &[Register]) -> Vec<u32> {
+        registers
+            .iter()
+            .map(|x| vm.core.registers[*x as usize])
+            .collect()
+    }
+
+    #[test]
+    fn cpu_one() {
+        use Register::*;
+        let vm = run("\tmov r1, #7\n\tmov r2, #3\n\tadd r3, r1, r2\n\tsub r4, r2, r1\n\tsub r5, r1, #-1\n\tmul r6, r1, r2\n\tmul r7, r1, #-2\n\tmov r9, r6");
+
+        assert_eq!(
+            registers(&vm, &[R3, R4, R5, R6, R7, R9]),
+            [10, -4i32 as u32, 8, 21, -14i32 as u32, 21]
+        );
+    }
+
+    #[test]
+    fn cpu_two() {
+        use Register::*;
+        let vm = run("\tmov r1, #-7\n\tmov r2, #2\n\tdiv r3, r1, r2\n\tdiv r4, r1, #-2\n\tdiv r5, r2, r1\n\tdiv r6, r1, #0");
+
+        assert_eq!(registers(&vm, &[R3, R4, R5, R6]), [-3i32 as u32, 3, 0, 0]);
+        assert_eq!(
+            Cpu::alu(Op::Div, i32::MIN as u32, u32::MAX),
+            i32::MIN as u32
+        );
+    }
+
+    #[test]
+    fn cpu_three() {
+        use Register::*;
+        let vm = run("\tmov r1, #0x5c\n\tmov r2, #0x36\n\tand r3, r1, r2\n\torr r4, r1, r2\n\tand r5, r1, #-4\n\torr r6, r1, #0x3");
+
+        assert_eq!(registers(&vm, &[R3, R4, R5, R6]), [0x14, 0x7e, 0x5c, 0x5f]);
+    }
+
+    #[test]
+    fn cpu_four() {
+        use Register::*;
+        let vm = run("\tmov r1, #-1\n\tmov r2, #28\n\tlsl r3, r1, r2\n\tlsr r4, r1, #28\n\tlsl r5, r1, #32\n\tlsr r6, r1, #0x101\n\tlsl r7, r1, #0");
+
+        assert_eq!(
+            registers(&vm, &[R3, R4, R5, R6, R7]),
+            [0xf000_0000, 0xf, 0, 0x7fff_ffff, u32::MAX]
+        );
+    }
+
+    /// flags after value op rm worked out in 64 bits, carry in c stands for the flags before
+    fn reference(op: Op, value: u32, rm: u32, c: bool) -> (bool, bool, bool, bool) {
+        let (wide, signed) = (u64::from(value), i64::from(value as i32));
+        let (rm_wide, rm_signed) = (u64::from(rm), i64::from(rm as i32));
+        let shift = u64::from(rm & 0xff);
+        let (res, c, v) = match op {
+            Op::Add => {
+                let sum = signed + rm_signed;
+                (
+                    wide + rm_wide,
+                    (wide + rm_wide) >> 32 == 1,
+                    sum != i64::from(sum as i32),
+                )
+            }
+            Op::Sub => {
+                let diff = signed - rm_signed;
+                (
+                    wide.wrapping_sub(rm_wide),
+                    wide >= rm_wide,
+                    diff != i64::from(diff as i32),
+                )
+            }
+            Op::Lsl if shift == 0 => (wide, c, false),
+            Op::Lsl => {
+                let res = wide.checked_shl(shift as u32).unwrap_or(0);
+                (res, (res >> 32) & 1 == 1, false)
+            }
+            Op::Lsr if shift == 0 => (wide, c, false),
+            Op::Lsr => {
+                let res = (wide << 1).checked_shr(shift as u32).unwrap_or(0);
+                (res >> 1, res & 1 == 1, false)
+            }
+            Op::And => (wide & rm_wide, c, false),
+            _ => unreachable!(),
+        };
+        let res = res as u32;
+        (res >> 31 == 1, res == 0, c, v)
+    }
+
+    #[test]
+    fn cpu_five() {
+        let values = [
+            0,
+            1,
+            2,
+            0x1f,
+            0x20,
+            0x21,
+            0xff,
+            0x100,
+            0x7fff_ffff,
+            0x8000_0000,
+            0x8000_0001,
+            0xffff_fffe,
+            0xffff_ffff,
+            0x1234_5678,
+        ];
+
+        for op in [Op::Add, Op::Sub, Op::Lsl, Op::Lsr, Op::And] {
+            for value in values {
+                for rm in values {
+                    for carry in [false, true] {
+                        let mut vm = Cpu::default();
+                        vm.core.flags.set_carry(carry);
+                        // the overflow flag is only kept by operations that do not set it
+                        vm.core.flags.set_overflow(false);
+                        let res = Cpu::alu(op, value, rm);
+                        vm.set_flags(op, value, rm, res);
+
+                        let flags = vm.core.flags;
+                        assert_eq!(
+                            (flags.n, flags.z, flags.c, flags.v),
+                            reference(op, value, rm, carry),
+                            "{op} 0x{value:x}, 0x{rm:x} with C {carry}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cpu_six() {
+        use Register::*;
+        // subs ends the loop without a cmp, adds carries out of r3 into r4
+        let vm = run("\tmov r1, #5\n1:\n\tadd r2, r2, #3\n\tsubs r1, r1, #1\n\tb.ne 1b\n\tmov r3, #-1\n\tadds r3, r3, #2\n\tadd.cs r4, r4, #1\n\tlsls r5, r3, #31\n\tmov.mi r6, #1\n\tcmp r1, #-1\n\tmov.gt r7, #1");
+
+        assert_eq!(
+            registers(&vm, &[R1, R2, R3, R4, R6, R7]),
+            [0, 15, 1, 1, 1, 1]
+        );
+        assert_eq!(
+            (vm.core.flags.n, vm.core.flags.z, vm.core.flags.c),
+            (false, false, false)
+        );
+    }
+
+    #[test]
+    fn cpu_seven() {
+        use Register::*;
+        // r1 walks the table with each addressing mode, r6 stores into it from the end
+        let vm = run(".data\ntable:\n\t.word 1, 2, 3\n.text\n\tldr r1, =table\n\tldr r2, [r1, #4]\n\tldr r3, [r1, #8]!\n\tldr r4, [r1], #-4\n\tldr r5, [r1]\n\tadd r6, r1, #8\n\tstr r2, [r6, #-4]!\n\tldr r7, [r1, #4]");
+
+        assert_eq!(registers(&vm, &[R2, R3, R4, R5, R7]), [2, 3, 3, 2, 2]);
+        assert_eq!(
+            vm.core.registers[R6 as usize],
+            vm.core.registers[R1 as usize] + 4
+        );
+    }
+
+    #[test]
+    fn cpu_eight() {
+        use Register::*;
+        let vm = run(".data\nbytes:\n\t.byte 0x80, 0x7f\n\t.half 0x8001, 0x1234\nbuf:\n\t.word 0\n.text\n\tldr r1, =bytes\n\tldrb r2, [r1]\n\tldrsb r3, [r1]\n\tldrsb r4, [r1, #1]\n\tldrh r5, [r1, #2]\n\tldrsh r6, [r1, #2]!\n\tldrsh r7, [r1, #2]\n\tmov r9, #-1\n\tldr r10, =buf\n\tstrb r9, [r10, #2]\n\tstrh r9, [r10], #2\n\tldr r11, [r10, #-2]");
+
+        assert_eq!(
+            registers(&vm, &[R2, R3, R4, R5, R6, R7, R11]),
+            [
+                0x80,
+                0xffff_ff80,
+                0x7f,
+                0x8001,
+                0xffff_8001,
+                0x1234,
+                0x00ff_ffff
+            ]
+        );
+    }
+
+    #[test]
+    fn cpu_nine() {
+        use Register::*;
+        // the pool of the ldr goes after `b 1f`, not after a call that returns
+        let vm = run(".data\ntable:\n\t.word double, inc\n.text\n\tmov r1, #3\n\tbl double\n\tmov r2, r1\n\tldr r4, =table\n\tldr r5, [r4, #4]\n\tblr r5\n\tmov r7, r14\n\tb 1f\ndouble:\n\tadd r1, r1, r1\n\tret\ninc:\n\tadd r2, r2, #1\n\tcmp r2, #7\n\tret.eq\n\tmov r6, #1\n\tret\n1:");
+
+        assert_eq!(registers(&vm, &[R1, R2, R6]), [6, 7, 0]);
+        assert_eq!(
+            vm.core.registers[R7 as usize],
+            vm.core.registers[LR as usize]
+        );
+        assert_eq!(vm.core.registers[LR as usize], 6 * 4);
+    }
+
+    #[test]
+    fn cpu_ten() {
+        use Register::*;
+        let vm = run("\tmov r1, #0x7ff\n\tmov r2, #-0x800\n\tcmp r1, #0x7ff\n\tmov.eq r3, #1\n\tcmp r2, #-0x800\n\tmov.eq r4, #1\n\tcmp r1, #-1\n\tmov.gt r5, #1");
+
+        assert_eq!(
+            registers(&vm, &[R1, R2, R3, R4, R5]),
+            [0x7ff, 0xffff_f800, 1, 1, 1]
+        );
+
+        // 0x800 and up would run as a negative number, they do not assemble
+        for source in [
+            "\tmov r1, #3000",
+            "\tcmp r1, #0x800",
+            "\tadd r1, r1, #0xfff",
+        ] {
+            let mut asm = Assembler {
+                source: source.to_string(),
+                ..Default::default()
+            };
+            assert!(asm.assemble().is_err());
+            assert!(source.trim().parse::<Instruction>().is_err());
+        }
+    }
+
+    #[test]
+    fn cpu_eleven() {
+        use Register::*;
+        let vm = run("\tmovw r2, #4095\n\tmov r1, #0x5a\n\tstrb r1, [r2]\n\tldrb r3, [r2]");
+
+        assert_eq!(registers(&vm, &[R3]), [0x5a]);
+
+        // an access at or running past the end of memory is an error, not a panic
+        for source in [
+            "\tmovw r2, #4096\n\tstr r1, [r2]",
+            "\tmovw r2, #4096\n\tldrb r1, [r2]",
+            "\tmovw r2, #4094\n\tstr r1, [r2]",
+            "\tmov r2, #-1\n\tldrh r1, [r2]",
+        ] {
+            let mut asm = Assembler {
+                source: source.to_string(),
+                ..Default::default()
+            };
+            let mut vm = Cpu::default();
+            vm.load_bin(&asm.assemble().unwrap()).unwrap();
+            assert!(matches!(
+                vm.execute(),
+                Err(EsiuxErrorKind::MemOutOfBounds(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn cpu_twelve() {
+        let mut vm = Cpu::default();
+
+        assert!(vm.load_program(&[0; Cpu::MEMORY_SIZE], 0).is_ok());
+        assert!(vm.load_program(&[0; 4], 0xffc).is_ok());
+        assert_eq!(
+            vm.load_program(&[0; 4], 0xffe).unwrap_err().to_string(),
+            "Program of 4 bytes at 0xffe does not fit in 4096 bytes of memory"
+        );
+        assert!(matches!(
+            vm.load_program(&[0; Cpu::MEMORY_SIZE + 1], 0),
+            Err(EsiuxErrorKind::ProgramSize(..))
+        ));
+    }
+}
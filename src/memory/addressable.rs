@@ -1,4 +1,11 @@
-use crate::Res;
+use crate::{error::EsiuxErrorKind, Res};
+
+/// the address n bytes after addr, an access running past the end of the address space is
+/// out of bounds
+fn next(addr: u32, n: u32) -> Res<u32> {
+    addr.checked_add(n)
+        .ok_or(EsiuxErrorKind::MemOutOfBounds(addr))
+}
 
 pub trait Addressable {
     fn read_u8(&self, addr: u32) -> Res<u8>;
@@ -6,7 +13,7 @@ pub trait Addressable {
 
     fn read_u16(&self, addr: u32) -> Res<u16> {
         let lower = self.read_u8(addr)?;
-        let upper = self.read_u8(addr + 1)?;
+        let upper = self.read_u8(next(addr, 1)?)?;
 
         Ok((lower as u16) & 0xff | (upper as u16) << 8)
     }
@@ -16,14 +23,14 @@ pub trait Addressable {
         let higher = (half_word >> 8) as u8;
 
         self.write_u8(addr, lower)?;
-        self.write_u8(addr + 1, higher)?;
+        self.write_u8(next(addr, 1)?, higher)?;
         Ok(())
     }
 
     fn read_u32(&self, addr: u32) -> Res<u32> {
         let mut byte_buffer = [0u8; 4];
         for (idx, byte) in byte_buffer.iter_mut().enumerate() {
-            *byte = self.read_u8(addr + idx as u32)?;
+            *byte = self.read_u8(next(addr, idx as u32)?)?;
         }
 
         Ok(u32::from_le_bytes(byte_buffer))
@@ -32,15 +39,15 @@ pub trait Addressable {
     fn write_u32(&mut self, addr: u32, word: u32) -> Res<()> {
         let bytes = word.to_le_bytes();
         for (idx, &byte) in bytes.iter().enumerate() {
-            self.write_u8(addr + idx as u32, byte)?;
+            self.write_u8(next(addr, idx as u32)?, byte)?;
         }
         Ok(())
     }
 
     fn copy(&mut self, from: u32, to: u32, n: usize) -> Res<()> {
         for index in 0..n as u32 {
-            let x = self.read_u8(from + index)?;
-            self.write_u8(to + index, x)?;
+            let x = self.read_u8(next(from, index)?)?;
+            self.write_u8(next(to, index)?, x)?;
         }
         Ok(())
     }
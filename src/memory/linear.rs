@@ -16,7 +16,7 @@ impl LineMem {
 
 impl Addressable for LineMem {
     fn read_u8(&self, addr: u32) -> crate::Res<u8> {
-        if self.mem.len() < addr as usize {
+        if self.mem.len() <= addr as usize {
             Err(EsiuxErrorKind::MemOutOfBounds(addr))
         } else {
             Ok(self.mem[addr as usize])
@@ -24,7 +24,7 @@ impl Addressable for LineMem {
     }
 
     fn write_u8(&mut self, addr: u32, byte: u8) -> crate::Res<()> {
-        if self.mem.len() < addr as usize {
+        if self.mem.len() <= addr as usize {
             Err(EsiuxErrorKind::MemOutOfBounds(addr))
         } else {
             self.mem[addr as usize] = byte;
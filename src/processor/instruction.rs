@@ -1,8 +1,9 @@
 use std::fmt;
 
 use crate::{
-    parser::{Negative, Parser, ToNum},
-    types::{l12, l20, Operand},
+    error::EsiuxErrorKind,
+    parser::{Parser, ToNum},
+    types::{l12, l16, l20, Operand},
 };
 
 use super::{Condition, Op, Register};
@@ -13,21 +14,33 @@ pub struct DPI {
     pub cond: Condition,
     pub instruction_type: u8,
     pub imm: bool,
+    // the `s` suffix, encoded as instruction type SET_FLAGS
+    pub set_flags: bool,
     pub opcode: Op,
     pub rn: Register,
     pub rd: Register,
     pub operand: Operand,
 }
 
+impl DPI {
+    /// instruction type of a data processing instruction that updates the flags
+    pub const SET_FLAGS: u8 = 0b100;
+}
+
 impl fmt::Display for DPI {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = if self.set_flags { "s" } else { "" };
+        let mnemonic = match self.cond {
+            Condition::Al => format!("{}{s}", self.opcode),
+            cond => format!("{}{s}.{cond}", self.opcode),
+        };
         match self.operand {
             Operand::Imm(imm) => write!(
                 f,
-                "{}  {}, {}, {}\t; {:02x}",
-                self.opcode, self.rd, self.rn, imm, imm
+                "{mnemonic}  {}, {}, {}\t; {:02x}",
+                self.rd, self.rn, imm, imm
             ),
-            Operand::Reg(r) => write!(f, "{}  {}, {}, {}", self.opcode, self.rd, self.rn, r,),
+            Operand::Reg(r) => write!(f, "{mnemonic}  {}, {}, {}", self.rd, self.rn, r,),
         }
     }
 }
@@ -35,7 +48,10 @@ impl fmt::Display for DPI {
 impl ToNum for DPI {
     fn mask(&self) -> u32 {
         let mut mask = self.cond as u32;
-        mask |= (((self.opcode as u32) >> 4) & 0b111) << 4;
+        match self.set_flags {
+            true => mask |= (Self::SET_FLAGS as u32) << 4,
+            false => mask |= (((self.opcode as u32) >> 4) & 0b111) << 4,
+        }
         // mask |= ((self.instruction_type as u32) & 0b11) << 4;
         if self.imm {
             mask |= 1 << 7;
@@ -60,6 +76,8 @@ impl TryFrom<u32> for DPI {
     fn try_from(value: u32) -> Result<Self, Self::Error> {
         let cond = Condition::try_from((value as u8) & 0xf)?;
         let ins = ((value >> 4) & 0b111) as u8;
+        let set_flags = ins == Self::SET_FLAGS;
+        let ins = if set_flags { 0b001 } else { ins };
         let imm = ((value >> 7) & 0b1) == 1;
         let opcode = Op::try_from(((value >> 8) & 0xf) as u8 | ins << 4)?;
         let rd = Register::try_from(((value >> 12) & 0xf) as u8)?;
@@ -75,6 +93,7 @@ impl TryFrom<u32> for DPI {
             cond,
             instruction_type: ins,
             imm,
+            set_flags,
             opcode,
             rn,
             rd,
@@ -93,7 +112,15 @@ impl Parser<DPI> for DPI {
         rn: Register,
         op1: Self::Op1,
     ) -> crate::Res<DPI> {
-        let cond = value.parse::<Condition>()?;
+        // `adds.eq` is add with the s suffix and condition eq, the condition comes last
+        let mnemonic = value.to_lowercase();
+        let suffix = mnemonic.get(opcode.to_string().len()..).unwrap_or_default();
+        let (set_flags, suffix) = match suffix.strip_prefix('s') {
+            Some(rest) if opcode.can_set_flags() => (true, rest),
+            Some(_) => return Err(EsiuxErrorKind::UnknownInstruction(value.to_string())),
+            None => (false, suffix),
+        };
+        let cond = suffix.parse::<Condition>()?;
         let imm = match op1 {
             Operand::Reg(_) => false,
             Operand::Imm(_) => true,
@@ -103,6 +130,7 @@ impl Parser<DPI> for DPI {
             cond,
             instruction_type: ((opcode as u8) >> 4) & 0b111,
             imm,
+            set_flags,
             opcode,
             rn,
             rd,
@@ -111,6 +139,93 @@ impl Parser<DPI> for DPI {
     }
 }
 
+/// # WDI
+///
+/// * wide immediate, moves a 16 bit immediate into one half of rd
+/// * movw clears the high half, movt keeps the low half
+///
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct WDI {
+    pub cond: Condition,
+    pub instruction_type: u8,
+    pub opcode: Op,
+    pub rd: Register,
+    pub imm: l16,
+}
+
+impl fmt::Display for WDI {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}  {}, {}\t; {:04x}",
+            self.opcode, self.rd, self.imm, self.imm
+        )
+    }
+}
+
+impl ToNum for WDI {
+    fn mask(&self) -> u32 {
+        let mut mask = self.cond as u32;
+        mask |= ((self.instruction_type as u32) & 0b111) << 4;
+        mask |= ((self.opcode as u32) & 0xf) << 8;
+        mask |= ((self.rd as u32) & 0xf) << 12;
+        mask |= (self.imm.value as u32) << 16;
+
+        mask
+    }
+}
+
+impl TryFrom<u32> for WDI {
+    type Error = crate::error::EsiuxErrorKind;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let cond = Condition::try_from((value & 0xf) as u8)?;
+        let instruction_type = ((value >> 4) & 0b111) as u8;
+        let opcode = Op::try_from(((value >> 8) & 0xf) as u8 | instruction_type << 4)?;
+        let rd = Register::try_from(((value >> 12) & 0xf) as u8)?;
+        let imm = l16 {
+            value: (value >> 16) as u16,
+        };
+
+        Ok(Self {
+            cond,
+            instruction_type,
+            opcode,
+            rd,
+            imm,
+        })
+    }
+}
+
+impl Parser<WDI> for WDI {
+    type Op1 = l16;
+
+    fn parse_instruction(
+        value: &str,
+        opcode: Op,
+        rd: Register,
+        _: Register,
+        op1: Self::Op1,
+    ) -> crate::Res<WDI> {
+        let cond = value.parse::<Condition>()?;
+
+        Ok(WDI {
+            cond,
+            instruction_type: ((opcode as u8) >> 4) & 0b111,
+            opcode,
+            rd,
+            imm: op1,
+        })
+    }
+}
+
+/// # LSI
+///
+/// * offset is the magnitude of the offset, negative subtracts it from rn
+/// * index adds the offset before the access, `[rn, #off]`, otherwise after it, `[rn], #off`
+/// * write_back stores the address with the offset added back in rn, `[rn, #off]!`
+/// * bit 11 and bit 7 hold the low two bits of the op, ldrb to ldrsh are of type 0x6
+///
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct LSI {
     pub cond: Condition,
@@ -126,17 +241,16 @@ pub struct LSI {
 
 impl fmt::Display for LSI {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.index {
-            true => write!(
-                f,
-                "{}  {}, [{}, {}]",
-                self.load_store, self.rd, self.rn, self.offset
-            ),
-            false => write!(
-                f,
-                "{} {}, [{}], {}",
-                self.load_store, self.rd, self.rn, self.offset
-            ),
+        let mnemonic = match self.cond {
+            Condition::Al => self.load_store.to_string(),
+            cond => format!("{}.{cond}", self.load_store),
+        };
+        let sign = if self.negative { "-" } else { "" };
+        let offset = format!("#{sign}{}", self.offset.value);
+        match (self.index, self.write_back) {
+            (true, false) => write!(f, "{mnemonic}  {}, [{}, {offset}]", self.rd, self.rn),
+            (true, true) => write!(f, "{mnemonic}  {}, [{}, {offset}]!", self.rd, self.rn),
+            (false, _) => write!(f, "{mnemonic}  {}, [{}], {offset}", self.rd, self.rn),
         }
     }
 }
@@ -145,6 +259,7 @@ impl ToNum for LSI {
     fn mask(&self) -> u32 {
         let mut mask = self.cond as u32;
         mask |= (((self.load_store as u32) >> 4) & 0b111) << 4;
+        mask |= (((self.load_store as u32) >> 1) & 0b1) << 7;
         mask |= (self.index.mask() & 0b1) << 8;
         mask |= (self.negative.mask() & 0b1) << 9;
         mask |= (self.write_back.mask() & 0b1) << 10;
@@ -166,7 +281,8 @@ impl TryFrom<u32> for LSI {
         let index = ((value >> 8) & 0b1) == 1;
         let negative = ((value >> 9) & 0b1) == 1;
         let write_back = ((value >> 10) & 0b1) == 1;
-        let load_store = Op::try_from(((value >> 11) & 0b1) as u8 | instruction_type << 4)?;
+        let low = (value >> 11) & 0b1 | (value >> 6) & 0b10;
+        let load_store = Op::try_from(low as u8 | instruction_type << 4)?;
         let rd = Register::try_from(((value >> 12) & 0xf) as u8)?;
         let rn = Register::try_from(((value >> 16) & 0xf) as u8)?;
         let offset = l12 {
@@ -197,18 +313,18 @@ impl Parser<LSI> for LSI {
         rn: Register,
         op1: Self::Op1,
     ) -> crate::Res<LSI> {
-        let cond = value.parse::<Condition>()?;
-        // TODO: Fix this
-        let index = false;
-        let negative = op1.is_negative();
-        let write_back = value.ends_with("w");
+        // `ldrb.eq` is ldrb with condition eq, a condition is never read from the op itself
+        let mnemonic = value.to_lowercase();
+        let suffix = mnemonic.get(opcode.to_string().len()..).unwrap_or_default();
+        let cond = suffix.parse::<Condition>()?;
 
+        // the addressing mode is set by the caller, the default is `[rn, #off]`
         Ok(Self {
             cond,
             instruction_type: ((opcode as u8) >> 4) & 0b111,
-            index,
-            negative,
-            write_back,
+            index: true,
+            negative: false,
+            write_back: false,
             load_store: opcode,
             rd,
             rn,
@@ -222,17 +338,36 @@ pub struct BRI {
     pub cond: Condition,
     pub instruction_type: u8,
     pub opcode: Op,
-    // TODO: support labels
+    // signed number of words from the branch to its target
     pub offset: l20,
 }
 
+impl BRI {
+    /// address a branch at pc jumps to, the offset counts words from the branch itself
+    pub fn target(&self, pc: u32) -> u32 {
+        pc.wrapping_add_signed(self.offset.as_signed().wrapping_mul(4))
+    }
+
+    /// register a `br` or `blr` jumps to, it is kept in the low bits of the offset
+    pub fn register(&self) -> Option<Register> {
+        match self.opcode.is_indirect() {
+            true => Register::try_from((self.offset.value & 0xf) as u8).ok(),
+            false => None,
+        }
+    }
+}
+
 impl fmt::Display for BRI {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let cond = self.cond;
+        let offset = match self.register() {
+            Some(rn) => rn.to_string(),
+            None => format!("#{}", self.offset.as_signed()),
+        };
         if cond == Condition::Al {
-            write!(f, "{}  {}", self.opcode, self.offset)
+            write!(f, "{}  {offset}", self.opcode)
         } else {
-            write!(f, "{}.{cond}  {}", self.opcode, self.offset)
+            write!(f, "{}.{cond}  {offset}", self.opcode)
         }
     }
 }
@@ -278,7 +413,10 @@ impl Parser<BRI> for BRI {
         _: Register,
         op1: Self::Op1,
     ) -> crate::Res<BRI> {
-        let cond = value.parse::<Condition>()?;
+        // `bl.eq` is bl with condition eq, a condition is never read from the op itself
+        let mnemonic = value.to_lowercase();
+        let suffix = mnemonic.get(opcode.to_string().len()..).unwrap_or_default();
+        let cond = suffix.parse::<Condition>()?;
 
         Ok(BRI {
             cond,
@@ -364,7 +502,7 @@ mod test {
         processor::{Instruction, SCI},
     };
 
-    use super::{BRI, DPI, LSI};
+    use super::{BRI, DPI, LSI, WDI};
 
     #[test]
     fn dpi_one() {
@@ -372,6 +510,7 @@ mod test {
             cond: crate::processor::Condition::Al,
             instruction_type: (crate::processor::Op::Add as u8) >> 4,
             imm: true,
+            set_flags: false,
             opcode: crate::processor::Op::Add,
             rn: crate::processor::Register::R2,
             rd: crate::processor::Register::R1,
@@ -390,6 +529,7 @@ mod test {
             cond: crate::processor::Condition::Al,
             instruction_type: (crate::processor::Op::Add as u8) >> 4,
             imm: false,
+            set_flags: false,
             opcode: crate::processor::Op::Add,
             rn: crate::processor::Register::R2,
             rd: crate::processor::Register::R1,
@@ -410,6 +550,7 @@ mod test {
             cond: crate::processor::Condition::Al,
             instruction_type: (crate::processor::Op::Add as u8) >> 4,
             imm: false,
+            set_flags: false,
             opcode: crate::processor::Op::Add,
             rn: crate::processor::Register::R0,
             rd: crate::processor::Register::R2,
@@ -433,6 +574,7 @@ mod test {
             cond: crate::processor::Condition::Al,
             instruction_type: (crate::processor::Op::Add as u8) >> 4,
             imm: true,
+            set_flags: false,
             opcode: crate::processor::Op::Add,
             rn: crate::processor::Register::R0,
             rd: crate::processor::Register::R2,
@@ -442,6 +584,101 @@ mod test {
         assert_eq!(repr, decoded)
     }
 
+    #[test]
+    fn dpi_five() {
+        let Instruction::Add(adds) = "adds.eq r1, r2, #1".parse::<Instruction>().unwrap() else {
+            unreachable!()
+        };
+        let Instruction::Lsl(lsls) = "lsls r1, r1, r3".parse::<Instruction>().unwrap() else {
+            unreachable!()
+        };
+
+        assert!(adds.set_flags);
+        assert_eq!(adds.cond, crate::processor::Condition::Eq);
+        assert_eq!((adds.mask() >> 4) & 0b111, DPI::SET_FLAGS as u32);
+        assert_eq!(
+            Instruction::try_from(adds.mask()).unwrap(),
+            Instruction::Add(adds)
+        );
+        assert_eq!(adds.to_string(), "adds.eq  r1, r2, 1\t; 01");
+        assert!(lsls.set_flags);
+        assert_eq!(lsls.cond, crate::processor::Condition::Al);
+        assert_eq!(
+            "cmps r1, #1"
+                .parse::<Instruction>()
+                .unwrap_err()
+                .to_string(),
+            "Unrecognized instruction: cmps"
+        );
+    }
+
+    #[test]
+    fn wdi_one() {
+        let ins = WDI {
+            cond: crate::processor::Condition::Al,
+            instruction_type: (crate::processor::Op::Movw as u8) >> 4,
+            opcode: crate::processor::Op::Movw,
+            rd: crate::processor::Register::R1,
+            imm: crate::types::l16 { value: 0xbeef },
+        };
+
+        let encoded = ins.mask();
+        let decoded = WDI::try_from(encoded).unwrap();
+
+        assert_eq!(encoded, 0xbeef_112e);
+        assert_eq!(ins, decoded);
+    }
+
+    #[test]
+    fn wdi_two() {
+        let ins = WDI {
+            cond: crate::processor::Condition::Eq,
+            instruction_type: (crate::processor::Op::Movt as u8) >> 4,
+            opcode: crate::processor::Op::Movt,
+            rd: crate::processor::Register::LR,
+            imm: crate::types::l16 { value: 0xffff },
+        };
+
+        let encoded = ins.mask();
+        let decoded = WDI::try_from(encoded).unwrap();
+
+        assert_eq!(ins, decoded);
+    }
+
+    #[test]
+    fn wdi_three() {
+        let ins = 0b1101_1110_1010_1101_0011_0010_0010_1110;
+        let decoded = WDI::try_from(ins).unwrap();
+
+        let repr = WDI {
+            cond: crate::processor::Condition::Al,
+            instruction_type: (crate::processor::Op::Movt as u8) >> 4,
+            opcode: crate::processor::Op::Movt,
+            rd: crate::processor::Register::R3,
+            imm: crate::types::l16 { value: 0xdead },
+        };
+
+        let int = Instruction::try_from(ins).unwrap();
+        match int {
+            Instruction::Movt(w) => assert_eq!(decoded, w),
+            _ => unreachable!(),
+        }
+
+        assert_eq!(repr, decoded);
+    }
+
+    #[test]
+    fn wdi_four() {
+        let movw = "movw r2, #-1".parse::<Instruction>().unwrap();
+        let movt = "movt r2, #0x1234".parse::<Instruction>().unwrap();
+        let mov = "mov r2, #1".parse::<Instruction>().unwrap();
+
+        assert!(matches!(movw, Instruction::Movw(WDI { imm, .. }) if imm.value == 0xffff));
+        assert!(matches!(movt, Instruction::Movt(WDI { imm, .. }) if imm.value == 0x1234));
+        assert!(matches!(mov, Instruction::Mov(_)));
+        assert_eq!(Instruction::try_from(movt.mask()).unwrap(), movt);
+    }
+
     #[test]
     fn lsi_one() {
         let ins = LSI {
@@ -526,6 +763,51 @@ mod test {
         assert_eq!(repr, decoded)
     }
 
+    #[test]
+    fn lsi_five() {
+        let modes =
+            ["[r2, #4]", "[r2, #-4]!", "[r2], #0x10", "[r2], #-8", "[r2]"].map(|x| match format!(
+                "ldr r1, {x}"
+            )
+            .parse::<Instruction>()
+            .unwrap()
+            {
+                Instruction::Ldr(lsi) => {
+                    (lsi.index, lsi.negative, lsi.write_back, lsi.offset.value)
+                }
+                _ => unreachable!(),
+            });
+
+        assert_eq!(
+            modes,
+            [
+                (true, false, false, 4),
+                (true, true, true, 4),
+                (false, false, true, 0x10),
+                (false, true, true, 8),
+                (true, false, false, 0),
+            ]
+        );
+
+        let ins = "str.ne r3, [r4, #-12]!".parse::<Instruction>().unwrap();
+        assert_eq!(ins.to_string(), "str.ne  r3, [r4, #-12]!");
+        assert_eq!(Instruction::try_from(ins.mask()).unwrap(), ins);
+    }
+
+    #[test]
+    fn lsi_six() {
+        let ops = ["ldrh", "strh", "ldrb", "strb", "ldrsb", "ldrsh"].map(|x| {
+            let ins = format!("{x}.eq r1, [r2], #-3")
+                .parse::<Instruction>()
+                .unwrap();
+            assert_eq!(Instruction::try_from(ins.mask()).unwrap(), ins);
+            assert_eq!(ins.to_string(), format!("{x}.eq  r1, [r2], #-3"));
+            ins.mask() & 0xff
+        });
+
+        assert_eq!(ops, [0xb0, 0xb0, 0x60, 0x60, 0xe0, 0xe0]);
+    }
+
     #[test]
     fn bri_one() {
         let ins = BRI {
@@ -592,6 +874,22 @@ mod test {
         assert_eq!(ins, decoded);
     }
 
+    #[test]
+    fn bri_five() {
+        for ins in ["bl.eq  #-2", "br  r14", "blr.ne  r3"] {
+            let parsed = ins.replace("  ", " ").parse::<Instruction>().unwrap();
+            assert_eq!(parsed.to_string(), ins);
+            assert_eq!(Instruction::try_from(parsed.mask()).unwrap(), parsed);
+        }
+
+        let blr = "blr r3".parse::<Instruction>().unwrap();
+        assert!(
+            matches!(blr, Instruction::Blr(bri) if bri.register() == Some(crate::processor::Register::R3))
+        );
+        assert!("b r1".parse::<Instruction>().is_err());
+        assert!("br #4".parse::<Instruction>().is_err());
+    }
+
     #[test]
     fn sci_one() {
         let ins = SCI {
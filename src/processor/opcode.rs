@@ -1,11 +1,14 @@
 use emacro::Codable;
 
-use super::{BRI, DPI, LSI, SCI};
+use super::{BRI, DPI, LSI, SCI, WDI};
 
 /// # Instruction
 ///
 /// DPI = 0b001 = 0x1
+/// DPI setting the flags = 0b100 = 0x4
+/// WDI = 0b010 = 0x2
 /// LsI = 0b011 = 0x3
+/// LSI of bytes and sign extended halves = 0b110 = 0x6
 /// BRI = 0b101 = 0x5
 /// SCI = 0b111 = 0x7
 ///
@@ -33,18 +36,56 @@ pub enum Instruction {
     #[alias("cmp", 0x1a)]
     Cmp(DPI),
 
+    #[alias("movw", 0x21)]
+    Movw(WDI),
+    #[alias("movt", 0x22)]
+    Movt(WDI),
+
     #[alias("ldr", 0x30)]
     Ldr(LSI),
     #[alias("str", 0x31)]
     Str(LSI),
+    #[alias("ldrh", 0x32)]
+    Ldrh(LSI),
+    #[alias("strh", 0x33)]
+    Strh(LSI),
+    #[alias("ldrb", 0x60)]
+    Ldrb(LSI),
+    #[alias("strb", 0x61)]
+    Strb(LSI),
+    #[alias("ldrsb", 0x62)]
+    Ldrsb(LSI),
+    #[alias("ldrsh", 0x63)]
+    Ldrsh(LSI),
 
     #[alias("b", 0x51)]
     Branch(BRI),
+    #[alias("bl", 0x52)]
+    Bl(BRI),
+    #[alias("br", 0x53)]
+    Br(BRI),
+    #[alias("blr", 0x54)]
+    Blr(BRI),
 
     #[alias("svc", 0x71)]
     Svc(SCI),
 }
 
+impl Op {
+    /// data processing ops that take the `s` suffix, cmp always sets the flags
+    pub fn can_set_flags(self) -> bool {
+        matches!(
+            self,
+            Op::Add | Op::Sub | Op::Mul | Op::Mov | Op::And | Op::Or | Op::Lsl | Op::Lsr
+        )
+    }
+
+    /// branches to the address in a register instead of an offset from the pc
+    pub fn is_indirect(self) -> bool {
+        matches!(self, Op::Br | Op::Blr)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
@@ -66,6 +107,7 @@ mod test {
                 cond: Condition::Al,
                 instruction_type: 0b001,
                 imm: true,
+                set_flags: false,
                 opcode: Op::Add,
                 rn: Register::R0,
                 rd: Register::R1,
@@ -86,6 +128,7 @@ mod test {
                 cond: Condition::Al,
                 instruction_type: 0b001,
                 imm: true,
+                set_flags: false,
                 opcode: Op::Add,
                 rn: Register::R0,
                 rd: Register::R1,
@@ -106,6 +149,7 @@ mod test {
                 cond: Condition::Al,
                 instruction_type: 0b001,
                 imm: false,
+                set_flags: false,
                 opcode: Op::Add,
                 rn: Register::R0,
                 rd: Register::R1,
@@ -126,6 +170,7 @@ mod test {
                 cond: Condition::Al,
                 instruction_type: 0b001,
                 imm: false,
+                set_flags: false,
                 opcode: Op::Add,
                 rn: Register::R0,
                 rd: Register::R1,
@@ -146,6 +191,7 @@ mod test {
                 cond: Condition::Eq,
                 instruction_type: 0b001,
                 imm: false,
+                set_flags: false,
                 opcode: Op::Add,
                 rn: Register::R0,
                 rd: Register::R1,
@@ -166,6 +212,7 @@ mod test {
                 cond: Condition::Eq,
                 instruction_type: 0b001,
                 imm: false,
+                set_flags: false,
                 opcode: Op::Add,
                 rn: Register::R0,
                 rd: Register::R1,
@@ -186,6 +233,7 @@ mod test {
                 cond: Condition::Al,
                 instruction_type: 0b001,
                 imm: true,
+                set_flags: false,
                 opcode: Op::Mov,
                 rn: Register::R0,
                 rd: Register::R1,
@@ -240,6 +288,7 @@ mod test {
                 cond: Condition::Al,
                 instruction_type: 0b001,
                 imm: true,
+                set_flags: false,
                 opcode: Op::Cmp,
                 rn: Register::R0,
                 rd: Register::R1,
@@ -247,4 +296,33 @@ mod test {
             })
         );
     }
+
+    #[test]
+    fn op_eleven() {
+        let ins = "b.ne #-3";
+
+        let instruction = ins.parse::<Instruction>().unwrap();
+        let Instruction::Branch(bri) = instruction else {
+            panic!("expected a branch, found {instruction:?}");
+        };
+
+        assert_eq!(bri.cond, Condition::Ne);
+        assert_eq!(bri.target(0x100), 0xf4);
+        assert_eq!(bri.to_string(), "b.ne  #-3");
+        assert_eq!(
+            "b #524287".parse::<Instruction>().unwrap().to_string(),
+            "b  #524287"
+        );
+    }
+
+    #[test]
+    fn op_twelve() {
+        // instruction type 0x0 is not assigned
+        for word in [0x0000_0000, 0x0000_000e, 0xdead_be0f] {
+            assert_eq!(
+                Instruction::try_from(word).unwrap_err().to_string(),
+                crate::error::EsiuxErrorKind::Decode(word).to_string()
+            );
+        }
+    }
 }
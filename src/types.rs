@@ -18,6 +18,13 @@ pub struct l12 {
 impl_pretty_print!(l12);
 
 impl l12 {
+    /// smallest value an operand can hold, operands are read back as signed
+    pub const MIN: i64 = -0x800;
+    /// largest value an operand can hold, operands are read back as signed
+    pub const MAX: i64 = 0x7ff;
+    /// largest value of the 12 bits read as unsigned, the magnitude of a load or store offset
+    pub const UMAX: i64 = 0xfff;
+
     pub fn new_u(value: u16) -> Res<l12> {
         if value > 0xfff {
             Err(EsiuxErrorKind::Overflow12(value))
@@ -60,6 +67,11 @@ impl FromStr for l12 {
     type Err = EsiuxErrorKind;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // `-0xfff` is the negated magnitude, a decimal is parsed as signed below
+        if let Some(magnitude) = s.strip_prefix('-').filter(|x| x.starts_with('0')) {
+            let value = magnitude.parse::<l12>()?.value;
+            return Self::new_i(-(value as i16));
+        }
         let (base, offset) = if s.len() >= 3 {
             match s {
                 x if &x[..2] == "0x" => (16, 2usize),
@@ -80,6 +92,55 @@ impl Negative for l12 {
     }
 }
 
+/// # Literal 16 bit
+///
+/// * Representation of the half word immediates of movw and movt
+/// * We can represent both signed and unsigned values
+/// * negative values are kept as their 16 bit two's complement
+///
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct l16 {
+    pub value: u16,
+}
+
+impl_pretty_print!(l16);
+
+impl l16 {
+    /// smallest value an operand can hold, read back as signed
+    pub const MIN: i64 = -0x8000;
+    /// largest value an operand can hold, read back as unsigned
+    pub const MAX: i64 = 0xffff;
+
+    pub fn from_str_radix(s: &str, base: u32) -> Res<l16> {
+        match u16::from_str_radix(s, base) {
+            Ok(value) => Ok(l16 { value }),
+            Err(_) => match i16::from_str_radix(s, base) {
+                Ok(i) => Ok(l16 { value: i as u16 }),
+                Err(err) => Err(err.into()),
+            },
+        }
+    }
+}
+
+impl FromStr for l16 {
+    type Err = EsiuxErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, offset) = if s.len() >= 3 {
+            match s {
+                x if &x[..2] == "0x" => (16, 2usize),
+                x if &x[..2] == "0b" => (2, 2usize),
+                _ => (10, 0usize),
+            }
+        } else {
+            (10, 0usize)
+        };
+
+        Self::from_str_radix(&s[offset..], base)
+    }
+}
+
 /// # Literal 24 bit
 ///
 /// * Representation of imm values as 24 bits
@@ -96,6 +157,11 @@ pub struct l20 {
 impl_pretty_print!(l20);
 
 impl l20 {
+    /// smallest value an operand can hold, read back as signed
+    pub const MIN: i64 = -0x80000;
+    /// largest value an operand can hold, read back as unsigned
+    pub const MAX: i64 = 0xfffff;
+
     pub fn new_u(value: u32) -> Res<l20> {
         if value > 0xfffff {
             Err(EsiuxErrorKind::Overflow20(value))
@@ -190,8 +256,22 @@ impl FromStr for Operand {
         match &s[..1] {
             "#" => {
                 let imm = &s[1..];
-                let imm = imm.parse::<l12>()?;
-                Ok(Self::Imm(imm))
+                let value = imm.parse::<l12>()?;
+                // the immediate is sign extended, 0x800 and up would read back negative
+                // and a magnitude above 0x800 would read back positive
+                let signed = match imm.strip_prefix('-') {
+                    Some(magnitude) => -i64::from(magnitude.parse::<l12>()?.value),
+                    None => i64::from(value.value),
+                };
+                if !(l12::MIN..=l12::MAX).contains(&signed) {
+                    return Err(EsiuxErrorKind::ImmRange(
+                        signed,
+                        "l12".to_string(),
+                        l12::MIN,
+                        l12::MAX,
+                    ));
+                }
+                Ok(Self::Imm(value))
             }
             "r" => {
                 let reg = s.parse::<Register>()?;
@@ -204,7 +284,7 @@ impl FromStr for Operand {
 
 #[cfg(test)]
 mod test {
-    use crate::types::l20;
+    use crate::types::{l16, l20};
 
     use super::l12;
 
@@ -246,6 +326,14 @@ mod test {
         assert_eq!(l12.value, 0x0010);
     }
 
+    #[test]
+    fn l16_one() {
+        assert_eq!("0xbeef".parse::<l16>().unwrap().value, 0xbeef);
+        assert_eq!("-1".parse::<l16>().unwrap().value, 0xffff);
+        assert_eq!("-32768".parse::<l16>().unwrap().value, 0x8000);
+        assert!("0x10000".parse::<l16>().is_err());
+    }
+
     #[test]
     fn l20_one() {
         let val = -12i32;